@@ -0,0 +1,44 @@
+use std::{env, fs, path::PathBuf};
+
+// Built-in shaders, compiled to SPIR-V and embedded by assets::BUILTIN_ASSETS
+const SHADERS: [(&str, shaderc::ShaderKind); 4] = [
+    ("simple_shader.vert", shaderc::ShaderKind::Vertex),
+    ("simple_shader.frag", shaderc::ShaderKind::Fragment),
+    ("point_light.vert", shaderc::ShaderKind::Vertex),
+    ("point_light.frag", shaderc::ShaderKind::Fragment),
+];
+
+fn main() {
+    let shader_dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap()).join("shaders");
+    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap()).join("shaders");
+
+    fs::create_dir_all(&out_dir).unwrap();
+
+    let mut compiler = shaderc::Compiler::new().expect("Failed to initialize shaderc compiler");
+    let mut options =
+        shaderc::CompileOptions::new().expect("Failed to create shader compile options");
+
+    options.set_target_env(
+        shaderc::TargetEnv::Vulkan,
+        shaderc::EnvVersion::Vulkan1_2 as u32,
+    );
+    options.set_optimization_level(shaderc::OptimizationLevel::Performance);
+
+    for (name, kind) in SHADERS {
+        let path = shader_dir.join(name);
+
+        println!("cargo:rerun-if-changed={}", path.display());
+
+        let source = fs::read_to_string(&path)
+            .unwrap_or_else(|err| panic!("Failed to read shader {:?}: {}", path, err));
+        let artifact = compiler
+            .compile_into_spirv(&source, kind, name, "main", Some(&options))
+            .unwrap_or_else(|err| panic!("Failed to compile shader {}: {}", name, err));
+
+        fs::write(
+            out_dir.join(format!("{}.spv", name)),
+            artifact.as_binary_u8(),
+        )
+        .unwrap();
+    }
+}
@@ -6,6 +6,9 @@ layout(location = 2) in vec3 fragNormalWorld;
 
 layout(location = 0) out vec4 outColor;
 
+layout(constant_id = 0) const int MAX_LIGHTS = 10;
+layout(constant_id = 1) const float SPECULAR_EXPONENT = 32.0;
+
 struct PointLight {
     vec4 position;
     vec4 color;
@@ -16,8 +19,9 @@ layout(set = 0, binding = 0) uniform GlobalUbo {
     mat4 view;
     mat4 inverse_view;
     vec4 ambient_light_color;
-    PointLight point_lights[10];
     int num_lights;
+    // Last, so specializing MAX_LIGHTS doesn't move the other members
+    PointLight point_lights[MAX_LIGHTS];
 } ubo;
 
 layout(push_constant) uniform Push {
@@ -46,7 +50,7 @@ void main() {
         float blinnTerm = dot(surface_normal, halfAngle);
 
         blinnTerm = clamp(blinnTerm, 0, 1);
-        blinnTerm = pow(blinnTerm, 32.0);
+        blinnTerm = pow(blinnTerm, SPECULAR_EXPONENT);
         specularLight += intensity * blinnTerm;
     }
 
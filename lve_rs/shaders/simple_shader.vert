@@ -9,6 +9,8 @@ layout(location = 0) out vec3 fragColor;
 layout(location = 1) out vec3 fragPosWorld;
 layout(location = 2) out vec3 fragNormalWorld;
 
+layout(constant_id = 0) const int MAX_LIGHTS = 10;
+
 struct PointLight {
     vec4 position;
     vec4 color;
@@ -19,8 +21,9 @@ layout(set = 0, binding = 0) uniform GlobalUbo {
     mat4 view;
     mat4 inverse_view;
     vec4 ambient_light_color;
-    PointLight point_lights[10];
     int num_lights;
+    // Last, so specializing MAX_LIGHTS doesn't move the other members
+    PointLight point_lights[MAX_LIGHTS];
 } ubo;
 
 layout(push_constant) uniform Push {
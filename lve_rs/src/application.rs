@@ -0,0 +1,168 @@
+use anyhow::{bail, Result};
+use winit::{
+    event::{DeviceEvent, ElementState, Event, KeyboardInput, VirtualKeyCode, WindowEvent},
+    event_loop::{ControlFlow, EventLoop},
+    platform::run_return::EventLoopExtRunReturn,
+};
+
+/* MEMO
+ *  Hooks driven by `run`, in this order each frame:
+ *   - on_event for every winit event, after run has handled close and resize
+ *   - update with the keys held and the frame's delta time (see FramePacer)
+ *   - render
+ *  shutdown is called once the loop is about to exit, with the frame timings
+ *  collected by run; it is the place to wait for the device to go idle. No
+ *  update or render follows it.
+ *
+ *  Errors returned by any hook are printed and end the loop.
+ */
+pub trait Application: Sized {
+    fn init(event_loop: &EventLoop<()>) -> Result<Self>;
+
+    fn window(&self) -> &crate::Window;
+
+    fn window_mut(&mut self) -> &mut crate::Window;
+
+    fn update(&mut self, _keys: &[Option<VirtualKeyCode>], _delta_time: f32) -> Result<()> {
+        Ok(())
+    }
+
+    // Skipped while the window is minimized
+    fn render(&mut self, control_flow: &mut ControlFlow) -> Result<()>;
+
+    fn on_event(&mut self, _event: &Event<()>, _control_flow: &mut ControlFlow) -> Result<()> {
+        Ok(())
+    }
+
+    fn shutdown(&mut self, _frame_timing: &crate::FrameTimingStats) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct RunConfig {
+    target_fps: Option<f64>,
+}
+
+impl RunConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // See FramePacer::set_target_fps
+    pub fn target_fps(&self, target_fps: Option<f64>) -> Self {
+        Self { target_fps }
+    }
+}
+
+/* MEMO
+ *  Owns the event loop: creates the application, routes window events to it,
+ *  tracks the keys held, paces frames and turns hook errors into exit codes.
+ *
+ *  lve_rs::run::<App>(&lve_rs::RunConfig::new().target_fps(Some(60.0)))?;
+ */
+pub fn run<A: Application>(config: &RunConfig) -> Result<()> {
+    const FRAME_ERROR: i32 = 0x10;
+    const SHUTDOWN_ERROR: i32 = 0x20;
+
+    let mut event_loop = EventLoop::new();
+    let mut app = A::init(&event_loop)?;
+    let mut frame_pacer = crate::FramePacer::new();
+    let mut keys_pressed: Vec<Option<VirtualKeyCode>> = vec![];
+    let mut shut_down = false;
+
+    frame_pacer.set_target_fps(config.target_fps);
+
+    let result = event_loop.run_return(|event, _, control_flow| {
+        // Nothing is drawn while minimized, so sleep until the next event
+        if app.window().is_minimized() {
+            control_flow.set_wait();
+        } else {
+            match frame_pacer.next_deadline() {
+                Some(deadline) => control_flow.set_wait_until(deadline),
+                None => control_flow.set_poll(),
+            }
+        }
+
+        match &event {
+            Event::WindowEvent { event, window_id }
+                if Some(*window_id) == app.window().window().map(|window| window.id()) =>
+            {
+                match event {
+                    WindowEvent::CloseRequested => control_flow.set_exit(),
+                    WindowEvent::Resized(physical_size) => app.window_mut().framebuffer_resized(
+                        physical_size.width as i32,
+                        physical_size.height as i32,
+                    ),
+                    WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
+                        app.window_mut().framebuffer_resized(
+                            new_inner_size.width as i32,
+                            new_inner_size.height as i32,
+                        )
+                    }
+                    _ => (),
+                }
+            }
+            Event::DeviceEvent {
+                event:
+                    DeviceEvent::Key(KeyboardInput {
+                        state,
+                        virtual_keycode: Some(key),
+                        ..
+                    }),
+                ..
+            } => match state {
+                ElementState::Pressed => {
+                    if !keys_pressed.contains(&Some(*key)) {
+                        keys_pressed.push(Some(*key));
+                    }
+                }
+                ElementState::Released => keys_pressed.retain(|pressed| *pressed != Some(*key)),
+            },
+            _ => (),
+        }
+
+        let result = app.on_event(&event, control_flow);
+
+        report(control_flow, result, FRAME_ERROR);
+
+        // Once exiting, shutdown has waited for the device, so nothing may be submitted anymore
+        let exiting = matches!(*control_flow, ControlFlow::ExitWithCode(_));
+
+        if !exiting && matches!(event, Event::MainEventsCleared) {
+            if app.window().is_minimized() {
+                frame_pacer.reset();
+            } else if frame_pacer.is_frame_due() {
+                let delta_time = frame_pacer.begin_frame();
+                let result = app
+                    .update(&keys_pressed, delta_time)
+                    .and_then(|_| app.render(control_flow));
+
+                report(control_flow, result, FRAME_ERROR);
+            }
+        }
+
+        if let ControlFlow::ExitWithCode(_) = *control_flow {
+            if !shut_down {
+                shut_down = true;
+
+                let result = app.shutdown(&frame_pacer.stats());
+
+                report(control_flow, result, SHUTDOWN_ERROR);
+            }
+        }
+    });
+
+    match result {
+        0 => Ok(()),
+        _ => bail!("Exit with status: {:0x}", result),
+    }
+}
+
+/* --- Helper functions --- */
+fn report(control_flow: &mut ControlFlow, result: Result<()>, exit_code: i32) {
+    if let Err(e) = result {
+        eprintln!("{:?}", e);
+        control_flow.set_exit_with_code(exit_code);
+    }
+}
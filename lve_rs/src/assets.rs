@@ -0,0 +1,200 @@
+use anyhow::{bail, Context, Result};
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+pub type EmbeddedArchive = &'static [(&'static str, &'static [u8])];
+
+/* MEMO
+ *  Built-in shaders are compiled to SPIR-V by build.rs and embedded, so the
+ *  binary depends neither on being started from the repository root nor on
+ *  compiling GLSL at startup.
+ */
+pub const BUILTIN_ASSETS: EmbeddedArchive = &[
+    (
+        "shaders/simple_shader.vert.spv",
+        include_bytes!(concat!(env!("OUT_DIR"), "/shaders/simple_shader.vert.spv")),
+    ),
+    (
+        "shaders/simple_shader.frag.spv",
+        include_bytes!(concat!(env!("OUT_DIR"), "/shaders/simple_shader.frag.spv")),
+    ),
+    (
+        "shaders/point_light.vert.spv",
+        include_bytes!(concat!(env!("OUT_DIR"), "/shaders/point_light.vert.spv")),
+    ),
+    (
+        "shaders/point_light.frag.spv",
+        include_bytes!(concat!(env!("OUT_DIR"), "/shaders/point_light.frag.spv")),
+    ),
+];
+
+#[derive(Clone)]
+pub enum AssetRoot {
+    Directory(PathBuf),
+    Embedded(EmbeddedArchive),
+}
+
+#[derive(Clone)]
+pub struct AssetLoader {
+    roots: Vec<AssetRoot>,
+}
+
+impl AssetLoader {
+    pub const ASSET_ROOT_ENV: &'static str = "LVE_ASSET_ROOT";
+
+    pub fn new() -> Self {
+        Self { roots: vec![] }
+    }
+
+    // Search order: $LVE_ASSET_ROOT, the assets built into lve_rs, the executable's
+    //  directory and the working directory; only $LVE_ASSET_ROOT overrides the
+    //  built-in assets, so stray files next to the binary can't
+    pub fn with_default_roots() -> Self {
+        let mut loader = Self::new();
+
+        if let Some(paths) = env::var_os(Self::ASSET_ROOT_ENV) {
+            for path in env::split_paths(&paths) {
+                loader = loader.add_directory(path);
+            }
+        }
+        loader = loader.add_embedded(BUILTIN_ASSETS);
+        if let Some(exe_dir) = env::current_exe()
+            .ok()
+            .and_then(|exe| exe.parent().map(Path::to_path_buf))
+        {
+            loader = loader.add_directory(exe_dir);
+        }
+        if let Ok(current_dir) = env::current_dir() {
+            loader = loader.add_directory(current_dir);
+        }
+
+        loader
+    }
+
+    pub fn embedded() -> Self {
+        Self::new().add_embedded(BUILTIN_ASSETS)
+    }
+
+    pub fn add_directory<P: AsRef<Path>>(&self, directory: P) -> Self {
+        let mut roots = self.roots.clone();
+
+        roots.push(AssetRoot::Directory(directory.as_ref().to_path_buf()));
+
+        Self { roots }
+    }
+
+    pub fn add_embedded(&self, archive: EmbeddedArchive) -> Self {
+        let mut roots = self.roots.clone();
+
+        roots.push(AssetRoot::Embedded(archive));
+
+        Self { roots }
+    }
+
+    #[inline]
+    pub fn roots(&self) -> &[AssetRoot] {
+        &self.roots
+    }
+
+    // Path on disk for `path`, if any directory root contains it
+    pub fn resolve_path<P: AsRef<Path>>(&self, path: P) -> Option<PathBuf> {
+        let path = path.as_ref();
+
+        if path.is_absolute() {
+            return path.is_file().then(|| path.to_path_buf());
+        }
+
+        self.roots.iter().find_map(|root| match root {
+            AssetRoot::Directory(directory) => {
+                let candidate = directory.join(path);
+
+                candidate.is_file().then_some(candidate)
+            }
+            AssetRoot::Embedded(_) => None,
+        })
+    }
+
+    pub fn exists<P: AsRef<Path>>(&self, path: P) -> bool {
+        self.read(path).is_ok()
+    }
+
+    pub fn read<P: AsRef<Path>>(&self, path: P) -> Result<Vec<u8>> {
+        let path = path.as_ref();
+
+        if path.is_absolute() {
+            return fs::read(path).with_context(|| format!("Failed to read asset {:?}", path));
+        }
+
+        for root in self.roots.iter() {
+            match root {
+                AssetRoot::Directory(directory) => {
+                    let candidate = directory.join(path);
+
+                    if candidate.is_file() {
+                        return fs::read(&candidate)
+                            .with_context(|| format!("Failed to read asset {:?}", candidate));
+                    }
+                }
+                AssetRoot::Embedded(archive) => {
+                    if let Some((_, bytes)) = archive
+                        .iter()
+                        .find(|(name, _)| Self::normalize(path) == *name)
+                    {
+                        return Ok(bytes.to_vec());
+                    }
+                }
+            }
+        }
+
+        bail!(
+            "Asset {:?} was not found in any asset root (set {} to add one)",
+            path,
+            Self::ASSET_ROOT_ENV
+        )
+    }
+
+    pub fn read_to_string<P: AsRef<Path>>(&self, path: P) -> Result<String> {
+        let path = path.as_ref();
+
+        String::from_utf8(self.read(path)?)
+            .with_context(|| format!("Asset {:?} is not valid UTF-8", path))
+    }
+
+    // Shader language is picked from the extension: .spv, .hlsl or GLSL otherwise
+    pub fn shader_source<P: AsRef<Path>>(&self, path: P) -> Result<crate::ShaderSource> {
+        let path = path.as_ref();
+        let name = path.display().to_string();
+
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("spv") => Ok(crate::ShaderSource::Spirv(ash::util::read_spv(
+                &mut std::io::Cursor::new(self.read(path)?),
+            )?)),
+            Some("hlsl") => Ok(crate::ShaderSource::hlsl(
+                &name,
+                &self.read_to_string(path)?,
+            )),
+            _ => Ok(crate::ShaderSource::glsl(
+                &name,
+                &self.read_to_string(path)?,
+            )),
+        }
+    }
+
+    fn normalize(path: &Path) -> String {
+        path.components()
+            .filter_map(|component| match component {
+                std::path::Component::Normal(part) => part.to_str(),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+}
+
+impl Default for AssetLoader {
+    fn default() -> Self {
+        Self::with_default_roots()
+    }
+}
@@ -0,0 +1,313 @@
+use anyhow::{bail, Context, Result};
+use ash::vk;
+use std::{
+    fs,
+    io::BufWriter,
+    path::{Path, PathBuf},
+    thread::{self, JoinHandle},
+};
+
+// Pixels read back from the GPU, still in the source image's format
+pub struct CapturedFrame {
+    pub width: u32,
+    pub height: u32,
+    pub format: vk::Format,
+    pub pixels: Vec<u8>,
+}
+
+// Copy recorded into a frame's command buffer, read back once that frame has executed
+pub(crate) struct PendingCapture {
+    buffer: crate::Buffer,
+    extent: vk::Extent2D,
+    format: vk::Format,
+    path: PathBuf,
+}
+
+impl CapturedFrame {
+    pub fn bytes_per_pixel(format: vk::Format) -> Result<usize> {
+        Ok(match format {
+            vk::Format::B8G8R8A8_SRGB
+            | vk::Format::B8G8R8A8_UNORM
+            | vk::Format::R8G8B8A8_SRGB
+            | vk::Format::R8G8B8A8_UNORM
+            | vk::Format::A2B10G10R10_UNORM_PACK32
+            | vk::Format::A2R10G10B10_UNORM_PACK32 => 4,
+            vk::Format::R16G16B16A16_SFLOAT => 8,
+            _ => bail!("Capturing {:?} images is not supported", format),
+        })
+    }
+
+    // 8-bit RGBA with sRGB encoding, as stored in the PNG
+    pub fn to_rgba8(&self) -> Result<Vec<u8>> {
+        let bytes_per_pixel = Self::bytes_per_pixel(self.format)?;
+        let mut rgba = Vec::with_capacity((self.width * self.height * 4) as usize);
+
+        for pixel in self.pixels.chunks_exact(bytes_per_pixel) {
+            match self.format {
+                // *_SRGB formats are stored already encoded, *_UNORM ones are written
+                //  by shaders that encode themselves; either way the bytes are display-ready
+                vk::Format::B8G8R8A8_SRGB | vk::Format::B8G8R8A8_UNORM => {
+                    rgba.extend_from_slice(&[pixel[2], pixel[1], pixel[0], pixel[3]])
+                }
+                vk::Format::R8G8B8A8_SRGB | vk::Format::R8G8B8A8_UNORM => {
+                    rgba.extend_from_slice(pixel)
+                }
+                vk::Format::A2B10G10R10_UNORM_PACK32 | vk::Format::A2R10G10B10_UNORM_PACK32 => {
+                    let packed = u32::from_le_bytes([pixel[0], pixel[1], pixel[2], pixel[3]]);
+                    let channel = |shift: u32| ((packed >> shift) & 0x3ff) as f32 / 1023.0;
+                    let (r, b) = if self.format == vk::Format::A2B10G10R10_UNORM_PACK32 {
+                        (channel(0), channel(20))
+                    } else {
+                        (channel(20), channel(0))
+                    };
+
+                    rgba.extend_from_slice(&[
+                        Self::unorm8(r),
+                        Self::unorm8(channel(10)),
+                        Self::unorm8(b),
+                        ((packed >> 30) * 85) as u8,
+                    ]);
+                }
+                // Linear (scRGB), so encode and clip to the SDR range
+                vk::Format::R16G16B16A16_SFLOAT => {
+                    let channel = |index: usize| {
+                        Self::f16_to_f32(u16::from_le_bytes([
+                            pixel[index * 2],
+                            pixel[index * 2 + 1],
+                        ]))
+                    };
+
+                    rgba.extend_from_slice(&[
+                        Self::unorm8(Self::linear_to_srgb(channel(0))),
+                        Self::unorm8(Self::linear_to_srgb(channel(1))),
+                        Self::unorm8(Self::linear_to_srgb(channel(2))),
+                        Self::unorm8(channel(3)),
+                    ]);
+                }
+                _ => unreachable!(),
+            }
+        }
+
+        Ok(rgba)
+    }
+
+    pub fn save_png<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let rgba = self.to_rgba8()?;
+        let file = fs::File::create(path)
+            .with_context(|| format!("Failed to create screenshot {:?}", path))?;
+        let mut encoder = png::Encoder::new(BufWriter::new(file), self.width, self.height);
+
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.set_source_srgb(png::SrgbRenderingIntent::Perceptual);
+        encoder.write_header()?.write_image_data(&rgba)?;
+
+        Ok(())
+    }
+
+    // Tightly packed RGBA8 rows with no header, same pixels as save_png
+    pub fn save_raw<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+
+        fs::write(path, self.to_rgba8()?)
+            .with_context(|| format!("Failed to write capture {:?}", path))
+    }
+
+    // Format is picked from the extension: .raw, or PNG otherwise
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("raw") => self.save_raw(path),
+            _ => self.save_png(path),
+        }
+    }
+
+    // Conversion and encoding happen on a worker thread
+    pub fn save_async<P: AsRef<Path>>(self, path: P) -> JoinHandle<Result<PathBuf>> {
+        let path = path.as_ref().to_path_buf();
+
+        thread::spawn(move || {
+            self.save(&path)?;
+
+            Ok(path)
+        })
+    }
+
+    /* --- Helper functions --- */
+    #[inline]
+    fn unorm8(value: f32) -> u8 {
+        (value.clamp(0.0, 1.0) * 255.0).round() as u8
+    }
+
+    fn linear_to_srgb(value: f32) -> f32 {
+        if value <= 0.003_130_8 {
+            value * 12.92
+        } else {
+            1.055 * value.powf(1.0 / 2.4) - 0.055
+        }
+    }
+
+    fn f16_to_f32(half: u16) -> f32 {
+        let sign = if half & 0x8000 != 0 { -1.0 } else { 1.0 };
+        let exponent = ((half >> 10) & 0x1f) as i32;
+        let mantissa = (half & 0x3ff) as f32;
+
+        match exponent {
+            0 => sign * mantissa * 2f32.powi(-24),
+            0x1f if mantissa == 0.0 => sign * f32::INFINITY,
+            0x1f => f32::NAN,
+            _ => sign * (1.0 + mantissa / 1024.0) * 2f32.powi(exponent - 15),
+        }
+    }
+}
+
+impl PendingCapture {
+    /* MEMO
+     *  `image` must be in `layout` when the recorded commands execute and is
+     *  returned to it afterwards, so this can sit between the end of the last
+     *  render pass and vkEndCommandBuffer without disturbing presentation.
+     */
+    pub(crate) unsafe fn record(
+        device: &crate::Device,
+        command_buffer: &vk::CommandBuffer,
+        image: vk::Image,
+        layout: vk::ImageLayout,
+        extent: vk::Extent2D,
+        format: vk::Format,
+        path: PathBuf,
+    ) -> Result<Self> {
+        let bytes_per_pixel = CapturedFrame::bytes_per_pixel(format)?;
+        let buffer = crate::Buffer::new(
+            device,
+            bytes_per_pixel as vk::DeviceSize,
+            (extent.width * extent.height) as usize,
+            vk::BufferUsageFlags::TRANSFER_DST,
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+            None,
+        )?;
+        let subresource_range = vk::ImageSubresourceRange {
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            base_mip_level: 0,
+            level_count: 1,
+            base_array_layer: 0,
+            layer_count: 1,
+        };
+        let to_transfer = vk::ImageMemoryBarrier::builder()
+            .src_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE)
+            .dst_access_mask(vk::AccessFlags::TRANSFER_READ)
+            .old_layout(layout)
+            .new_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+            .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .image(image)
+            .subresource_range(subresource_range)
+            .build();
+        let from_transfer = vk::ImageMemoryBarrier::builder()
+            .src_access_mask(vk::AccessFlags::TRANSFER_READ)
+            .dst_access_mask(vk::AccessFlags::empty())
+            .old_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+            .new_layout(layout)
+            .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .image(image)
+            .subresource_range(subresource_range)
+            .build();
+        let to_host = vk::BufferMemoryBarrier::builder()
+            .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+            .dst_access_mask(vk::AccessFlags::HOST_READ)
+            .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .buffer(*buffer.buffer())
+            .offset(0)
+            .size(vk::WHOLE_SIZE)
+            .build();
+        let region = vk::BufferImageCopy::builder()
+            .buffer_offset(0)
+            .buffer_row_length(0)
+            .buffer_image_height(0)
+            .image_subresource(vk::ImageSubresourceLayers {
+                aspect_mask: vk::ImageAspectFlags::COLOR,
+                mip_level: 0,
+                base_array_layer: 0,
+                layer_count: 1,
+            })
+            .image_offset(vk::Offset3D { x: 0, y: 0, z: 0 })
+            .image_extent(vk::Extent3D {
+                width: extent.width,
+                height: extent.height,
+                depth: 1,
+            })
+            .build();
+        let device_ref = device.device();
+
+        device_ref.cmd_pipeline_barrier(
+            *command_buffer,
+            vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+                | vk::PipelineStageFlags::FRAGMENT_SHADER,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::DependencyFlags::empty(),
+            &[],
+            &[],
+            std::slice::from_ref(&to_transfer),
+        );
+        device_ref.cmd_copy_image_to_buffer(
+            *command_buffer,
+            image,
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            *buffer.buffer(),
+            std::slice::from_ref(&region),
+        );
+        device_ref.cmd_pipeline_barrier(
+            *command_buffer,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::PipelineStageFlags::HOST | vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+            vk::DependencyFlags::empty(),
+            &[],
+            std::slice::from_ref(&to_host),
+            std::slice::from_ref(&from_transfer),
+        );
+
+        Ok(Self {
+            buffer,
+            extent,
+            format,
+            path,
+        })
+    }
+
+    pub(crate) unsafe fn discard(mut self, device: &crate::Device) {
+        self.buffer.destroy(device);
+    }
+
+    // The commands recorded by `record` must have finished executing
+    pub(crate) unsafe fn read_back(
+        mut self,
+        device: &crate::Device,
+    ) -> Result<(CapturedFrame, PathBuf)> {
+        let size = self.buffer.buffer_size() as usize;
+
+        let pixels = self.buffer.map(device, None, None).and_then(|_| {
+            let mapped = self
+                .buffer
+                .mapped_memory()
+                .context("Capture buffer is not mapped")?;
+
+            Ok(std::slice::from_raw_parts(mapped as *const u8, size).to_vec())
+        });
+        // Destroyed whether or not reading it back worked
+        self.buffer.destroy(device);
+        let pixels = pixels?;
+
+        Ok((
+            CapturedFrame {
+                width: self.extent.width,
+                height: self.extent.height,
+                format: self.format,
+                pixels,
+            },
+            self.path,
+        ))
+    }
+}
@@ -0,0 +1,462 @@
+use anyhow::{bail, Context, Result};
+use ash::{prelude::VkResult, vk};
+use std::{
+    panic::{self, AssertUnwindSafe},
+    sync::mpsc,
+    thread::JoinHandle,
+};
+
+// What secondary command buffers recorded for a render pass inherit from it
+#[derive(Debug, Clone, Copy)]
+pub struct SecondaryInheritance {
+    // Null with dynamic rendering; the formats below are inherited instead
+    pub render_pass: vk::RenderPass,
+    pub framebuffer: vk::Framebuffer,
+    pub color_format: vk::Format,
+    pub depth_format: vk::Format,
+    pub samples: vk::SampleCountFlags,
+    // Dynamic state isn't inherited, so every secondary command buffer sets these first
+    pub viewport: vk::Viewport,
+    pub scissor: vk::Rect2D,
+    // Statistics of a query left active in the primary command buffer
+    pub pipeline_statistics: vk::QueryPipelineStatisticFlags,
+}
+
+/* MEMO
+ *  Records secondary command buffers on a fixed set of worker threads, started
+ *  once in new and joined in destroy. A command pool may only be used by one
+ *  thread at a time, so every worker owns a pool per frame in flight, and the
+ *  thread driving SecondaryRecorder has pools of its own. Pools are reset once
+ *  per begin_frame of their frame index (the workers' lazily, on their first job
+ *  of the frame), which keeps the command buffers allocated from them for reuse.
+ *
+ *  let mut recorder = command_recorder.begin_frame(&device, frame_index, inheritance)?;
+ *  recorder.record_parallel(&device, &draws, |device, command_buffer, draws| { ... })?;
+ *  recorder.execute(&device, &primary_command_buffer);
+ */
+pub struct CommandRecorder {
+    // One per frame in flight, for the thread driving SecondaryRecorder
+    pools: Vec<ThreadCommandPool>,
+    workers: Vec<Worker>,
+    // Counts begin_frame calls; a pool last reset in an older one is reset before use
+    generation: u64,
+}
+
+/* MEMO
+ *  Collects the secondary command buffers of one render pass in the order they
+ *  have to execute. record_parallel splits its items into contiguous chunks, one
+ *  per worker thread, and keeps the command buffers in chunk order, so the draw
+ *  order only depends on the order of the items.
+ */
+pub struct SecondaryRecorder<'a> {
+    pool: &'a mut ThreadCommandPool,
+    workers: &'a [Worker],
+    frame_index: usize,
+    generation: u64,
+    inheritance: SecondaryInheritance,
+    command_buffers: Vec<vk::CommandBuffer>,
+}
+
+struct ThreadCommandPool {
+    command_pool: vk::CommandPool,
+    command_buffers: Vec<vk::CommandBuffer>,
+    // Command buffers handed out since the last reset
+    used: usize,
+    // CommandRecorder::generation of the last reset
+    generation: u64,
+}
+
+struct Worker {
+    jobs: mpsc::Sender<Job>,
+    // None if the job panicked
+    results: mpsc::Receiver<Option<VkResult<vk::CommandBuffer>>>,
+    thread: JoinHandle<()>,
+}
+
+// Records chunk `chunk` of a record_parallel call into a command buffer
+type RecordChunk = dyn Fn(usize, &ash::Device, &vk::CommandBuffer) + Sync;
+
+struct Job {
+    frame_index: usize,
+    generation: u64,
+    inheritance: SecondaryInheritance,
+    chunk: usize,
+    // Borrowed from record_parallel, which waits for the result before returning
+    record: &'static RecordChunk,
+}
+
+impl CommandRecorder {
+    pub fn new(
+        device: &crate::Device,
+        worker_threads: usize,
+        frames_in_flight: usize,
+    ) -> Result<Self> {
+        let queue_family_index = device
+            .find_physical_queue_families()?
+            .graphics_family
+            .context("Failed to get graphics queue family")?;
+        let mut command_recorder = Self {
+            pools: Vec::with_capacity(frames_in_flight),
+            workers: Vec::with_capacity(worker_threads),
+            generation: 0,
+        };
+
+        if let Err(err) =
+            command_recorder.init(device, queue_family_index, worker_threads, frames_in_flight)
+        {
+            unsafe { command_recorder.destroy(device) };
+            return Err(err);
+        }
+
+        Ok(command_recorder)
+    }
+
+    // Stops the worker threads, which destroy their pools (and so their command buffers)
+    pub unsafe fn destroy(&mut self, device: &crate::Device) {
+        for worker in self.workers.drain(..) {
+            worker.join();
+        }
+        for pool in self.pools.iter_mut() {
+            pool.destroy(device.device());
+        }
+        self.pools.clear();
+    }
+
+    #[inline]
+    pub fn worker_threads(&self) -> usize {
+        self.workers.len()
+    }
+
+    // The previous submission of `frame_index` must have finished
+    pub unsafe fn begin_frame(
+        &mut self,
+        device: &crate::Device,
+        frame_index: usize,
+        inheritance: SecondaryInheritance,
+    ) -> Result<SecondaryRecorder<'_>> {
+        self.generation += 1;
+
+        let pool = &mut self.pools[frame_index];
+
+        pool.reset(device.device(), self.generation)?;
+
+        Ok(SecondaryRecorder {
+            pool,
+            workers: &self.workers,
+            frame_index,
+            generation: self.generation,
+            inheritance,
+            command_buffers: vec![],
+        })
+    }
+
+    /* --- Helper functions --- */
+    fn init(
+        &mut self,
+        device: &crate::Device,
+        queue_family_index: u32,
+        worker_threads: usize,
+        frames_in_flight: usize,
+    ) -> Result<()> {
+        for _ in 0..frames_in_flight {
+            self.pools
+                .push(ThreadCommandPool::new(device.device(), queue_family_index)?);
+        }
+        for index in 0..worker_threads {
+            let mut pools = Vec::with_capacity(frames_in_flight);
+
+            for _ in 0..frames_in_flight {
+                match ThreadCommandPool::new(device.device(), queue_family_index) {
+                    Ok(pool) => pools.push(pool),
+                    Err(err) => {
+                        for mut pool in pools {
+                            unsafe { pool.destroy(device.device()) };
+                        }
+                        return Err(err.into());
+                    }
+                }
+            }
+            self.workers
+                .push(Worker::spawn(device.device().clone(), index, pools)?);
+        }
+
+        Ok(())
+    }
+}
+
+impl SecondaryRecorder<'_> {
+    // record_parallel doesn't split its items any further than this
+    pub const MIN_CHUNK_LEN: usize = 64;
+
+    #[inline]
+    pub fn inheritance(&self) -> &SecondaryInheritance {
+        &self.inheritance
+    }
+
+    #[inline]
+    pub fn worker_threads(&self) -> usize {
+        self.workers.len()
+    }
+
+    // Recorded so far, in execution order
+    #[inline]
+    pub fn command_buffers(&self) -> &[vk::CommandBuffer] {
+        &self.command_buffers
+    }
+
+    // Records a secondary command buffer on the calling thread
+    pub unsafe fn record<F: FnOnce(&vk::CommandBuffer)>(
+        &mut self,
+        device: &crate::Device,
+        f: F,
+    ) -> Result<()> {
+        let command_buffer = self.pool.begin(device.device(), &self.inheritance)?;
+
+        f(&command_buffer);
+        device.device().end_command_buffer(command_buffer)?;
+        self.command_buffers.push(command_buffer);
+
+        Ok(())
+    }
+
+    /* MEMO
+     *  Calls `f` for contiguous chunks of `items` on the worker threads, each with a
+     *  secondary command buffer of its own, and returns the number of command
+     *  buffers recorded. Lists too short to be worth splitting, or a recorder
+     *  without worker threads, are recorded on the calling thread.
+     *
+     *  `f` only gets the ash::Device: crate::Device (like everything else behind
+     *  an Rc or a Cell) can't be shared with other threads, so collect what the
+     *  commands need into `items` first.
+     */
+    pub unsafe fn record_parallel<T, F>(
+        &mut self,
+        device: &crate::Device,
+        items: &[T],
+        f: F,
+    ) -> Result<usize>
+    where
+        T: Sync,
+        F: Fn(&ash::Device, &vk::CommandBuffer, &[T]) + Sync,
+    {
+        if items.is_empty() {
+            return Ok(0);
+        }
+
+        let threads = items
+            .len()
+            .div_ceil(Self::MIN_CHUNK_LEN)
+            .min(self.workers.len())
+            .max(1);
+        let chunk_len = items.len().div_ceil(threads);
+        let chunk_count = items.len().div_ceil(chunk_len);
+
+        if chunk_count == 1 {
+            let ash_device = device.device();
+
+            self.record(device, |command_buffer| {
+                f(ash_device, command_buffer, items)
+            })?;
+
+            return Ok(1);
+        }
+
+        let record_chunk =
+            |chunk: usize, device: &ash::Device, command_buffer: &vk::CommandBuffer| {
+                let start = chunk * chunk_len;
+
+                f(
+                    device,
+                    command_buffer,
+                    &items[start..(start + chunk_len).min(items.len())],
+                )
+            };
+        let record: &(dyn Fn(usize, &ash::Device, &vk::CommandBuffer) + Sync + '_) = &record_chunk;
+        // Every job sent below is waited for before record_chunk goes out of scope
+        let record = std::mem::transmute::<_, &'static RecordChunk>(record);
+        let mut sent = 0;
+
+        for (chunk, worker) in self.workers.iter().take(chunk_count).enumerate() {
+            let job = Job {
+                frame_index: self.frame_index,
+                generation: self.generation,
+                inheritance: self.inheritance,
+                chunk,
+                record,
+            };
+
+            if worker.jobs.send(job).is_err() {
+                break;
+            }
+            sent += 1;
+        }
+
+        let results = self.workers[..sent]
+            .iter()
+            .map(|worker| worker.results.recv().ok().flatten())
+            .collect::<Vec<_>>();
+
+        if sent < chunk_count {
+            bail!("Command recording thread exited");
+        }
+        for result in results {
+            match result {
+                Some(command_buffer) => self.command_buffers.push(command_buffer?),
+                None => bail!("Command recording thread panicked"),
+            }
+        }
+
+        Ok(chunk_count)
+    }
+
+    // Call inside a render pass begun for secondary command buffers
+    pub unsafe fn execute(&self, device: &crate::Device, command_buffer: &vk::CommandBuffer) {
+        if !self.command_buffers.is_empty() {
+            device
+                .device()
+                .cmd_execute_commands(*command_buffer, &self.command_buffers);
+        }
+    }
+}
+
+impl ThreadCommandPool {
+    fn new(device: &ash::Device, queue_family_index: u32) -> VkResult<Self> {
+        let create_info = vk::CommandPoolCreateInfo::builder()
+            .queue_family_index(queue_family_index)
+            .flags(vk::CommandPoolCreateFlags::TRANSIENT);
+        let command_pool = unsafe { device.create_command_pool(&create_info, None) }?;
+
+        Ok(Self {
+            command_pool,
+            command_buffers: vec![],
+            used: 0,
+            generation: 0,
+        })
+    }
+
+    unsafe fn destroy(&mut self, device: &ash::Device) {
+        device.destroy_command_pool(self.command_pool, None);
+        self.command_buffers.clear();
+    }
+
+    unsafe fn reset(&mut self, device: &ash::Device, generation: u64) -> VkResult<()> {
+        device.reset_command_pool(self.command_pool, vk::CommandPoolResetFlags::empty())?;
+        self.used = 0;
+        self.generation = generation;
+
+        Ok(())
+    }
+
+    // Hands out the next command buffer, begun for `inheritance`
+    unsafe fn begin(
+        &mut self,
+        device: &ash::Device,
+        inheritance: &SecondaryInheritance,
+    ) -> VkResult<vk::CommandBuffer> {
+        if self.used == self.command_buffers.len() {
+            let allocate_info = vk::CommandBufferAllocateInfo::builder()
+                .level(vk::CommandBufferLevel::SECONDARY)
+                .command_pool(self.command_pool)
+                .command_buffer_count(1);
+
+            self.command_buffers
+                .extend(device.allocate_command_buffers(&allocate_info)?);
+        }
+
+        let command_buffer = self.command_buffers[self.used];
+        let color_formats = [inheritance.color_format];
+        let mut rendering_info = vk::CommandBufferInheritanceRenderingInfo::builder()
+            .color_attachment_formats(&color_formats)
+            .depth_attachment_format(inheritance.depth_format)
+            .rasterization_samples(inheritance.samples);
+        let mut inheritance_info = vk::CommandBufferInheritanceInfo::builder()
+            .render_pass(inheritance.render_pass)
+            .subpass(0)
+            .framebuffer(inheritance.framebuffer)
+            .pipeline_statistics(inheritance.pipeline_statistics);
+
+        if inheritance.render_pass == vk::RenderPass::null() {
+            inheritance_info = inheritance_info.push_next(&mut rendering_info);
+        }
+
+        let begin_info = vk::CommandBufferBeginInfo::builder()
+            .flags(
+                vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT
+                    | vk::CommandBufferUsageFlags::RENDER_PASS_CONTINUE,
+            )
+            .inheritance_info(&inheritance_info);
+
+        device.begin_command_buffer(command_buffer, &begin_info)?;
+        device.cmd_set_viewport(
+            command_buffer,
+            0,
+            std::slice::from_ref(&inheritance.viewport),
+        );
+        device.cmd_set_scissor(
+            command_buffer,
+            0,
+            std::slice::from_ref(&inheritance.scissor),
+        );
+        self.used += 1;
+
+        Ok(command_buffer)
+    }
+}
+
+impl Worker {
+    fn spawn(
+        device: ash::Device,
+        index: usize,
+        pools: Vec<ThreadCommandPool>,
+    ) -> std::io::Result<Self> {
+        let (jobs, job_receiver) = mpsc::channel::<Job>();
+        let (result_sender, results) = mpsc::channel();
+        let thread = std::thread::Builder::new()
+            .name(format!("command recorder {}", index))
+            .spawn(move || Self::run(device, pools, job_receiver, result_sender))?;
+
+        Ok(Self {
+            jobs,
+            results,
+            thread,
+        })
+    }
+
+    // Closing the job channel makes the worker destroy its pools and exit
+    fn join(self) {
+        drop(self.jobs);
+        if self.thread.join().is_err() {
+            eprintln!("Command recording thread panicked while shutting down");
+        }
+    }
+
+    fn run(
+        device: ash::Device,
+        mut pools: Vec<ThreadCommandPool>,
+        jobs: mpsc::Receiver<Job>,
+        results: mpsc::Sender<Option<VkResult<vk::CommandBuffer>>>,
+    ) {
+        for job in jobs.iter() {
+            let pool = &mut pools[job.frame_index];
+            let result = panic::catch_unwind(AssertUnwindSafe(|| unsafe {
+                if pool.generation != job.generation {
+                    pool.reset(&device, job.generation)?;
+                }
+
+                let command_buffer = pool.begin(&device, &job.inheritance)?;
+
+                (job.record)(job.chunk, &device, &command_buffer);
+                device.end_command_buffer(command_buffer)?;
+
+                Ok(command_buffer)
+            }));
+
+            if results.send(result.ok()).is_err() {
+                break;
+            }
+        }
+        for pool in pools.iter_mut() {
+            unsafe { pool.destroy(&device) };
+        }
+    }
+}
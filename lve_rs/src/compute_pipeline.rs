@@ -0,0 +1,142 @@
+use anyhow::{Context, Result};
+use ash::vk;
+use std::ffi::CStr;
+
+pub struct ComputePipeline {
+    pipeline: vk::Pipeline,
+    pipeline_layout: vk::PipelineLayout,
+    shader_module: vk::ShaderModule,
+}
+
+impl ComputePipeline {
+    pub fn new(
+        device: &crate::Device,
+        source: &crate::ShaderSource,
+        set_layouts: &[vk::DescriptorSetLayout],
+        push_constant_size: Option<u32>,
+    ) -> Result<Self> {
+        let pipeline_layout =
+            Self::create_pipeline_layout(device, set_layouts, push_constant_size)?;
+        let shader_module = {
+            let code = source.load(vk::ShaderStageFlags::COMPUTE, "main")?;
+            let create_info = vk::ShaderModuleCreateInfo::builder().code(&code);
+
+            unsafe { device.device().create_shader_module(&create_info, None) }?
+        };
+        let pipeline = {
+            let stage = vk::PipelineShaderStageCreateInfo::builder()
+                .stage(vk::ShaderStageFlags::COMPUTE)
+                .module(shader_module)
+                .name(unsafe { CStr::from_bytes_with_nul_unchecked(b"main\0") })
+                .build();
+            let create_info = vk::ComputePipelineCreateInfo::builder()
+                .stage(stage)
+                .layout(pipeline_layout)
+                .base_pipeline_index(-1)
+                .base_pipeline_handle(vk::Pipeline::null());
+
+            match unsafe {
+                device.device().create_compute_pipelines(
+                    vk::PipelineCache::null(),
+                    std::slice::from_ref(&create_info),
+                    None,
+                )
+            } {
+                Ok(pipelines) => Ok(pipelines),
+                Err((_, e)) => Err(e),
+            }?
+        }
+        .into_iter()
+        .next()
+        .context("Failed to create compute pipeline")?;
+
+        Ok(Self {
+            pipeline,
+            pipeline_layout,
+            shader_module,
+        })
+    }
+
+    pub unsafe fn destroy(&mut self, device: &crate::Device) {
+        device
+            .device()
+            .destroy_shader_module(self.shader_module, None);
+        device.device().destroy_pipeline(self.pipeline, None);
+        device
+            .device()
+            .destroy_pipeline_layout(self.pipeline_layout, None);
+    }
+
+    #[inline]
+    pub fn pipeline_layout(&self) -> vk::PipelineLayout {
+        self.pipeline_layout
+    }
+
+    #[inline]
+    pub unsafe fn bind(&self, device: &crate::Device, command_buffer: &vk::CommandBuffer) {
+        device.device().cmd_bind_pipeline(
+            *command_buffer,
+            vk::PipelineBindPoint::COMPUTE,
+            self.pipeline,
+        )
+    }
+
+    pub unsafe fn bind_descriptor_sets(
+        &self,
+        device: &crate::Device,
+        command_buffer: &vk::CommandBuffer,
+        first_set: u32,
+        descriptor_sets: &[vk::DescriptorSet],
+    ) {
+        device.device().cmd_bind_descriptor_sets(
+            *command_buffer,
+            vk::PipelineBindPoint::COMPUTE,
+            self.pipeline_layout,
+            first_set,
+            descriptor_sets,
+            &[],
+        )
+    }
+
+    pub unsafe fn push_constants(
+        &self,
+        device: &crate::Device,
+        command_buffer: &vk::CommandBuffer,
+        data: &[u8],
+    ) {
+        device.device().cmd_push_constants(
+            *command_buffer,
+            self.pipeline_layout,
+            vk::ShaderStageFlags::COMPUTE,
+            0,
+            data,
+        )
+    }
+
+    #[inline]
+    pub fn group_count(invocations: u32, local_size: u32) -> u32 {
+        invocations.div_ceil(local_size)
+    }
+
+    fn create_pipeline_layout(
+        device: &crate::Device,
+        set_layouts: &[vk::DescriptorSetLayout],
+        push_constant_size: Option<u32>,
+    ) -> Result<vk::PipelineLayout> {
+        let push_constant_ranges = match push_constant_size {
+            Some(size) => vec![vk::PushConstantRange::builder()
+                .stage_flags(vk::ShaderStageFlags::COMPUTE)
+                .offset(0)
+                .size(size)
+                .build()],
+            None => vec![],
+        };
+        let create_info = vk::PipelineLayoutCreateInfo::builder()
+            .set_layouts(set_layouts)
+            .push_constant_ranges(&push_constant_ranges);
+        let pipeline_layout =
+            unsafe { device.device().create_pipeline_layout(&create_info, None) }?;
+
+        Ok(pipeline_layout)
+    }
+}
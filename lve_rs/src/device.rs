@@ -1,12 +1,18 @@
 use crate::__utils as lve_utils;
 use anyhow::{bail, Context, Result};
-use ash::{extensions::khr as vk_khr, vk};
+use ash::{
+    extensions::{ext as vk_ext, khr as vk_khr},
+    vk,
+};
 use raw_window_handle::HasRawDisplayHandle;
-use std::{collections::HashSet, ffi::CStr};
+use std::{cell::RefCell, collections::HashSet, ffi::CStr};
 
 pub struct QueryFamilyIndices {
     pub graphics_family: Option<u32>,
     pub present_family: Option<u32>,
+    // Dedicated families when the GPU has them, otherwise the graphics family
+    pub transfer_family: Option<u32>,
+    pub compute_family: Option<u32>,
 }
 
 pub struct Device {
@@ -19,7 +25,20 @@ pub struct Device {
     device: ash::Device,
     graphics_queue: vk::Queue,
     present_queue: vk::Queue,
+    transfer_queue: vk::Queue,
+    compute_queue: vk::Queue,
     command_pool: vk::CommandPool,
+    enabled_features: vk::PhysicalDeviceFeatures,
+    mesh_shader: Option<vk_ext::MeshShader>,
+    hdr_metadata: Option<vk::ExtHdrMetadataFn>,
+    dynamic_rendering: bool,
+    // Loaded when timeline semaphores come from VK_KHR_timeline_semaphore instead of 1.2
+    timeline_semaphore_fn: Option<vk_khr::TimelineSemaphore>,
+    graphics_timeline: crate::TimelineSemaphore,
+    transfer_timeline: crate::TimelineSemaphore,
+    compute_timeline: crate::TimelineSemaphore,
+    // Submitted by submit_single_time_commands, freed once the timeline passes them
+    pending_single_time_commands: RefCell<Vec<(u64, vk::CommandBuffer)>>,
 }
 
 impl QueryFamilyIndices {
@@ -27,6 +46,8 @@ impl QueryFamilyIndices {
         Self {
             graphics_family: None,
             present_family: None,
+            transfer_family: None,
+            compute_family: None,
         }
     }
 
@@ -36,6 +57,10 @@ impl QueryFamilyIndices {
                 .context("Graphics queue family missing")?,
             self.present_family
                 .context("Present queue family missing")?,
+            self.transfer_family
+                .context("Transfer queue family missing")?,
+            self.compute_family
+                .context("Compute queue family missing")?,
         ]))
     }
 
@@ -62,10 +87,64 @@ impl Device {
         };
         let surface = window.create_surface(&entry, &instance)?;
         let (properties, physical_device) = Self::pick_physical_device(&instance, &surface)?;
-        let (device, graphics_queue, present_queue) =
-            Self::create_device(&instance, &surface, &physical_device)?;
+        let (
+            device,
+            graphics_queue,
+            present_queue,
+            enabled_features,
+            mesh_shader_enabled,
+            hdr_metadata_enabled,
+            dynamic_rendering,
+            timeline_semaphore_extension,
+        ) = Self::create_device(
+            &instance,
+            &surface,
+            &physical_device,
+            // Both the instance and the device have to speak 1.3
+            app_info.api_version.min(properties.api_version),
+        )?;
+        let mesh_shader = if mesh_shader_enabled {
+            Some(vk_ext::MeshShader::new(&instance, &device))
+        } else {
+            None
+        };
+        let hdr_metadata = if hdr_metadata_enabled {
+            Some(vk::ExtHdrMetadataFn::load(|name| unsafe {
+                std::mem::transmute(instance.get_device_proc_addr(device.handle(), name.as_ptr()))
+            }))
+        } else {
+            None
+        };
+        let timeline_semaphore_fn = if timeline_semaphore_extension {
+            Some(vk_khr::TimelineSemaphore::new(&instance, &device))
+        } else {
+            None
+        };
+        let (transfer_queue, compute_queue) = {
+            let indices = Self::find_queue_families(&instance, &surface, &physical_device)?;
+
+            unsafe {
+                (
+                    device.get_device_queue(
+                        indices
+                            .transfer_family
+                            .context("Failed to get transfer queue")?,
+                        0,
+                    ),
+                    device.get_device_queue(
+                        indices
+                            .compute_family
+                            .context("Failed to get compute queue")?,
+                        0,
+                    ),
+                )
+            }
+        };
         let command_pool =
             Self::create_command_pool(&instance, &surface, &physical_device, &device)?;
+        let graphics_timeline = crate::TimelineSemaphore::with_ash_device(&device)?;
+        let transfer_timeline = crate::TimelineSemaphore::with_ash_device(&device)?;
+        let compute_timeline = crate::TimelineSemaphore::with_ash_device(&device)?;
 
         Ok(Self {
             properties,
@@ -77,11 +156,25 @@ impl Device {
             device,
             graphics_queue,
             present_queue,
+            transfer_queue,
+            compute_queue,
             command_pool,
+            enabled_features,
+            mesh_shader,
+            hdr_metadata,
+            dynamic_rendering,
+            timeline_semaphore_fn,
+            graphics_timeline,
+            transfer_timeline,
+            compute_timeline,
+            pending_single_time_commands: RefCell::new(vec![]),
         })
     }
 
     pub unsafe fn destroy(&self) {
+        self.graphics_timeline.destroy(self);
+        self.transfer_timeline.destroy(self);
+        self.compute_timeline.destroy(self);
         self.device.destroy_command_pool(self.command_pool, None);
         self.device.destroy_device(None);
         self.surface.destroy_surface();
@@ -123,14 +216,205 @@ impl Device {
         &self.present_queue
     }
 
+    // Same queue as graphics_queue when the GPU has no dedicated transfer family
+    #[inline]
+    pub fn transfer_queue(&self) -> &vk::Queue {
+        &self.transfer_queue
+    }
+
+    // Same queue as graphics_queue when the GPU has no dedicated compute family
+    #[inline]
+    pub fn compute_queue(&self) -> &vk::Queue {
+        &self.compute_queue
+    }
+
     #[inline]
     pub fn command_pool(&self) -> &vk::CommandPool {
         &self.command_pool
     }
 
+    #[inline]
+    pub fn enabled_features(&self) -> &vk::PhysicalDeviceFeatures {
+        &self.enabled_features
+    }
+
+    #[inline]
+    pub fn mesh_shader(&self) -> Option<&vk_ext::MeshShader> {
+        self.mesh_shader.as_ref()
+    }
+
+    // Present when VK_EXT_hdr_metadata is enabled
+    #[inline]
+    pub fn hdr_metadata(&self) -> Option<&vk::ExtHdrMetadataFn> {
+        self.hdr_metadata.as_ref()
+    }
+
+    // Core in Vulkan 1.3; enabled whenever both the API version and the GPU allow it
+    #[inline]
+    pub fn supports_dynamic_rendering(&self) -> bool {
+        self.dynamic_rendering
+    }
+
+    // Signaled by every submission to the graphics queue made through this crate
+    #[inline]
+    pub fn graphics_timeline(&self) -> &crate::TimelineSemaphore {
+        &self.graphics_timeline
+    }
+
+    // Signaled by every submit_transfer
+    #[inline]
+    pub fn transfer_timeline(&self) -> &crate::TimelineSemaphore {
+        &self.transfer_timeline
+    }
+
+    // Signaled by every submit_compute
+    #[inline]
+    pub fn compute_timeline(&self) -> &crate::TimelineSemaphore {
+        &self.compute_timeline
+    }
+
+    pub unsafe fn get_semaphore_counter_value(&self, semaphore: vk::Semaphore) -> Result<u64> {
+        Ok(match &self.timeline_semaphore_fn {
+            Some(timeline_semaphore_fn) => {
+                timeline_semaphore_fn.get_semaphore_counter_value(semaphore)?
+            }
+            None => self.device.get_semaphore_counter_value(semaphore)?,
+        })
+    }
+
+    pub unsafe fn wait_semaphores(
+        &self,
+        wait_info: &vk::SemaphoreWaitInfo,
+        timeout: u64,
+    ) -> ash::prelude::VkResult<()> {
+        match &self.timeline_semaphore_fn {
+            Some(timeline_semaphore_fn) => {
+                timeline_semaphore_fn.wait_semaphores(wait_info, timeout)
+            }
+            None => self.device.wait_semaphores(wait_info, timeout),
+        }
+    }
+
+    pub unsafe fn signal_semaphore(&self, signal_info: &vk::SemaphoreSignalInfo) -> Result<()> {
+        Ok(match &self.timeline_semaphore_fn {
+            Some(timeline_semaphore_fn) => timeline_semaphore_fn.signal_semaphore(signal_info)?,
+            None => self.device.signal_semaphore(signal_info)?,
+        })
+    }
+
+    /* MEMO
+     *  Submits to the graphics queue and signals the returned value on the graphics
+     *  timeline. `waits` are (timeline, value, stage) triples, so work on another
+     *  queue's timeline (submit_transfer, submit_compute) is waited for on the GPU
+     *  without any fence.
+     */
+    pub unsafe fn submit_graphics(
+        &self,
+        command_buffers: &[vk::CommandBuffer],
+        waits: &[(&crate::TimelineSemaphore, u64, vk::PipelineStageFlags)],
+    ) -> Result<u64> {
+        self.submit(
+            self.graphics_queue,
+            &self.graphics_timeline,
+            command_buffers,
+            waits,
+        )
+    }
+
+    /* MEMO
+     *  Like submit_graphics, but for the transfer and compute queues and their own
+     *  timelines. The command buffers have to come from a pool of the matching
+     *  family (see find_physical_queue_families), and resources shared with the
+     *  graphics queue need queue family ownership transfers when the families
+     *  differ.
+     */
+    pub unsafe fn submit_transfer(
+        &self,
+        command_buffers: &[vk::CommandBuffer],
+        waits: &[(&crate::TimelineSemaphore, u64, vk::PipelineStageFlags)],
+    ) -> Result<u64> {
+        self.submit(
+            self.transfer_queue,
+            &self.transfer_timeline,
+            command_buffers,
+            waits,
+        )
+    }
+
+    pub unsafe fn submit_compute(
+        &self,
+        command_buffers: &[vk::CommandBuffer],
+        waits: &[(&crate::TimelineSemaphore, u64, vk::PipelineStageFlags)],
+    ) -> Result<u64> {
+        self.submit(
+            self.compute_queue,
+            &self.compute_timeline,
+            command_buffers,
+            waits,
+        )
+    }
+
+    // 0 when the graphics queue can't write timestamps
+    pub fn timestamp_valid_bits(&self) -> Result<u32> {
+        let graphics_family = self
+            .find_physical_queue_families()?
+            .graphics_family
+            .context("No graphics queue family")?;
+        let queue_families = unsafe {
+            self.instance
+                .get_physical_device_queue_family_properties(self.physical_device)
+        };
+
+        Ok(queue_families[graphics_family as usize].timestamp_valid_bits)
+    }
+
     #[inline]
     pub unsafe fn swap_chain_support(&self) -> Result<crate::SwapChainSupportDetails> {
-        self.surface.query_swap_chain_support(&self.physical_device)
+        self.swap_chain_support_for(&self.surface)
+    }
+
+    #[inline]
+    pub unsafe fn swap_chain_support_for(
+        &self,
+        surface: &crate::Surface,
+    ) -> Result<crate::SwapChainSupportDetails> {
+        surface.query_swap_chain_support(&self.physical_device)
+    }
+
+    /* MEMO
+     *  Surfaces for additional windows. The queues were picked for the surface of the
+     *  window the device was created with, so a new surface is only accepted if the
+     *  present queue can present to it too. The caller owns the surface and destroys it
+     *  after every swap chain created for it, e.g. through Renderer::with_surface.
+     */
+    pub fn create_surface(&self, window: &crate::Window) -> Result<crate::Surface> {
+        let surface = window.create_surface(&self.entry, &self.instance)?;
+
+        match self.supports_present(&surface) {
+            Ok(true) => Ok(surface),
+            Ok(false) => {
+                unsafe { surface.destroy_surface() };
+                bail!("The present queue can't present to this window's surface");
+            }
+            Err(err) => {
+                unsafe { surface.destroy_surface() };
+                Err(err)
+            }
+        }
+    }
+
+    // Whether the device's present queue family can present to `surface`
+    pub fn supports_present(&self, surface: &crate::Surface) -> Result<bool> {
+        let present_family = self
+            .find_physical_queue_families()?
+            .present_family
+            .context("No present queue family")?;
+        let support_details = unsafe { surface.query_swap_chain_support(&self.physical_device) }?;
+
+        Ok(unsafe {
+            surface.get_physical_device_surface_support(&self.physical_device, present_family)
+        }? && !support_details.formats.is_empty()
+            && !support_details.present_modes.is_empty())
     }
 
     pub fn find_memory_type(
@@ -248,21 +532,29 @@ impl Device {
         &self,
         command_buffer: &vk::CommandBuffer,
     ) -> Result<()> {
+        let value = self.submit_single_time_commands(command_buffer)?;
+
+        self.graphics_timeline.wait(self, value, u64::MAX)?;
+        self.free_finished_single_time_commands()?;
+
+        Ok(())
+    }
+
+    // Returns the graphics timeline value to wait for instead of blocking right away
+    pub unsafe fn submit_single_time_commands(
+        &self,
+        command_buffer: &vk::CommandBuffer,
+    ) -> Result<u64> {
         self.device.end_command_buffer(*command_buffer)?;
 
-        let submit_info =
-            vk::SubmitInfo::builder().command_buffers(std::slice::from_ref(command_buffer));
+        let value = self.submit_graphics(std::slice::from_ref(command_buffer), &[])?;
 
-        self.device.queue_submit(
-            self.graphics_queue,
-            std::slice::from_ref(&submit_info),
-            vk::Fence::null(),
-        )?;
-        self.device.queue_wait_idle(self.graphics_queue)?;
-        self.device
-            .free_command_buffers(self.command_pool, std::slice::from_ref(command_buffer));
+        self.pending_single_time_commands
+            .borrow_mut()
+            .push((value, *command_buffer));
+        self.free_finished_single_time_commands()?;
 
-        Ok(())
+        Ok(value)
     }
 
     pub unsafe fn copy_buffer(
@@ -330,6 +622,24 @@ impl Device {
         Ok(())
     }
 
+    // Highest sample count usable for both color and depth attachments
+    pub fn max_usable_sample_count(&self) -> vk::SampleCountFlags {
+        let counts = self.properties.limits.framebuffer_color_sample_counts
+            & self.properties.limits.framebuffer_depth_sample_counts;
+
+        [
+            vk::SampleCountFlags::TYPE_64,
+            vk::SampleCountFlags::TYPE_32,
+            vk::SampleCountFlags::TYPE_16,
+            vk::SampleCountFlags::TYPE_8,
+            vk::SampleCountFlags::TYPE_4,
+            vk::SampleCountFlags::TYPE_2,
+        ]
+        .into_iter()
+        .find(|samples| counts.contains(*samples))
+        .unwrap_or(vk::SampleCountFlags::TYPE_1)
+    }
+
     pub fn create_image_with_info(
         &self,
         image_info: &vk::ImageCreateInfo,
@@ -368,7 +678,26 @@ impl Device {
                 .engine_name(app_info.engine_name)
                 .engine_version(app_info.engine_version)
                 .api_version(app_info.api_version);
-            let extensions = Self::get_required_extensions(window)?;
+            // Checked up front, create_instance would only say ERROR_EXTENSION_NOT_PRESENT
+            if window.window().is_none()
+                && !Self::check_instance_extension_support_for(
+                    entry,
+                    &[ash::extensions::ext::HeadlessSurface::name()],
+                )?
+            {
+                bail!(
+                    "Headless rendering needs {:?}, which this Vulkan instance doesn't support",
+                    ash::extensions::ext::HeadlessSurface::name()
+                );
+            }
+            let mut extensions = Self::get_required_extensions(window)?;
+            // Exposes the extended sRGB and HDR10 color spaces on the surface, if available
+            if Self::check_instance_extension_support_for(
+                entry,
+                &[vk::ExtSwapchainColorspaceFn::name()],
+            )? {
+                extensions.push(vk::ExtSwapchainColorspaceFn::name().as_ptr());
+            }
             let layers = Self::VALIDATION_LAYERS.to_vec();
             let mut debug_create_info =
                 crate::DebugUtilsMessenger::populate_debug_message_create_info();
@@ -430,7 +759,17 @@ impl Device {
         instance: &ash::Instance,
         surface: &crate::Surface,
         physical_device: &vk::PhysicalDevice,
-    ) -> Result<(ash::Device, vk::Queue, vk::Queue)> {
+        api_version: u32,
+    ) -> Result<(
+        ash::Device,
+        vk::Queue,
+        vk::Queue,
+        vk::PhysicalDeviceFeatures,
+        bool,
+        bool,
+        bool,
+        bool,
+    )> {
         let indices = Self::find_queue_families(instance, surface, physical_device)?;
         let queue_create_infos = {
             let queue_priority = 1.0f32;
@@ -446,10 +785,103 @@ impl Device {
                 })
                 .collect::<Vec<_>>()
         };
+        // Optional shader stages are enabled whenever the GPU offers them
+        let enabled_features = {
+            let supported = unsafe { instance.get_physical_device_features(*physical_device) };
+
+            vk::PhysicalDeviceFeatures::builder()
+                .sampler_anisotropy(supported.sampler_anisotropy != 0)
+                .geometry_shader(supported.geometry_shader != 0)
+                .tessellation_shader(supported.tessellation_shader != 0)
+                .fill_mode_non_solid(supported.fill_mode_non_solid != 0)
+                .pipeline_statistics_query(supported.pipeline_statistics_query != 0)
+                // Lets queries stay active across secondary command buffers
+                .inherited_queries(supported.inherited_queries != 0)
+                .build()
+        };
+        let supported_mesh_shader_features = {
+            let mut mesh_shader_features = vk::PhysicalDeviceMeshShaderFeaturesEXT::default();
+
+            if Self::check_device_extension_support_for(
+                instance,
+                physical_device,
+                &[vk_ext::MeshShader::name()],
+            )? {
+                let mut features =
+                    vk::PhysicalDeviceFeatures2::builder().push_next(&mut mesh_shader_features);
+
+                unsafe { instance.get_physical_device_features2(*physical_device, &mut features) };
+            }
+
+            mesh_shader_features
+        };
+        let mesh_shader_enabled = supported_mesh_shader_features.mesh_shader != 0;
+        let hdr_metadata_enabled = Self::check_device_extension_support_for(
+            instance,
+            physical_device,
+            &[vk::ExtHdrMetadataFn::name()],
+        )?;
+        let dynamic_rendering_enabled = api_version >= vk::API_VERSION_1_3 && {
+            let mut vulkan_13_features = vk::PhysicalDeviceVulkan13Features::default();
+            let mut features =
+                vk::PhysicalDeviceFeatures2::builder().push_next(&mut vulkan_13_features);
+
+            unsafe { instance.get_physical_device_features2(*physical_device, &mut features) };
+
+            vulkan_13_features.dynamic_rendering != 0
+        };
+        // Core in 1.2, otherwise it has to come from the extension
+        let timeline_semaphore_extension = api_version < vk::API_VERSION_1_2;
+
+        if timeline_semaphore_extension
+            && !Self::check_device_extension_support_for(
+                instance,
+                physical_device,
+                &[vk_khr::TimelineSemaphore::name()],
+            )?
+        {
+            bail!("Timeline semaphores need Vulkan 1.2 or VK_KHR_timeline_semaphore");
+        }
+        {
+            let mut timeline_semaphore_features =
+                vk::PhysicalDeviceTimelineSemaphoreFeatures::default();
+            let mut features =
+                vk::PhysicalDeviceFeatures2::builder().push_next(&mut timeline_semaphore_features);
+
+            unsafe { instance.get_physical_device_features2(*physical_device, &mut features) };
+            if timeline_semaphore_features.timeline_semaphore == 0 {
+                bail!("The GPU does not support timeline semaphores");
+            }
+        }
         let device = {
-            let create_info = vk::DeviceCreateInfo::builder()
+            let mut extensions = Self::DEVICE_EXTENSIONS.to_vec();
+            let mut mesh_shader_features = vk::PhysicalDeviceMeshShaderFeaturesEXT::builder()
+                .mesh_shader(true)
+                .task_shader(supported_mesh_shader_features.task_shader != 0);
+            let mut vulkan_13_features =
+                vk::PhysicalDeviceVulkan13Features::builder().dynamic_rendering(true);
+            let mut timeline_semaphore_features =
+                vk::PhysicalDeviceTimelineSemaphoreFeatures::builder().timeline_semaphore(true);
+            let mut create_info = vk::DeviceCreateInfo::builder()
                 .queue_create_infos(&queue_create_infos)
-                .enabled_extension_names(&Self::DEVICE_EXTENSIONS);
+                .enabled_features(&enabled_features)
+                .push_next(&mut timeline_semaphore_features);
+
+            if timeline_semaphore_extension {
+                extensions.push(vk_khr::TimelineSemaphore::name().as_ptr());
+            }
+
+            if mesh_shader_enabled {
+                extensions.push(vk_ext::MeshShader::name().as_ptr());
+                create_info = create_info.push_next(&mut mesh_shader_features);
+            }
+            if hdr_metadata_enabled {
+                extensions.push(vk::ExtHdrMetadataFn::name().as_ptr());
+            }
+            if dynamic_rendering_enabled {
+                create_info = create_info.push_next(&mut vulkan_13_features);
+            }
+            let create_info = create_info.enabled_extension_names(&extensions);
 
             unsafe { instance.create_device(*physical_device, &create_info, None) }?
         };
@@ -470,7 +902,16 @@ impl Device {
             )
         };
 
-        Ok((device, graphics_queue, present_queue))
+        Ok((
+            device,
+            graphics_queue,
+            present_queue,
+            enabled_features,
+            mesh_shader_enabled,
+            hdr_metadata_enabled,
+            dynamic_rendering_enabled,
+            timeline_semaphore_extension,
+        ))
     }
 
     fn create_command_pool(
@@ -499,6 +940,56 @@ impl Device {
     }
 
     /* Helper functions */
+    unsafe fn submit(
+        &self,
+        queue: vk::Queue,
+        timeline: &crate::TimelineSemaphore,
+        command_buffers: &[vk::CommandBuffer],
+        waits: &[(&crate::TimelineSemaphore, u64, vk::PipelineStageFlags)],
+    ) -> Result<u64> {
+        let wait_semaphores = waits
+            .iter()
+            .map(|(timeline, _, _)| timeline.semaphore())
+            .collect::<Vec<_>>();
+        let wait_values = waits.iter().map(|(_, value, _)| *value).collect::<Vec<_>>();
+        let wait_stages = waits.iter().map(|(_, _, stage)| *stage).collect::<Vec<_>>();
+        let signal_semaphore = timeline.semaphore();
+        let signal_value = timeline.next_value();
+        let mut timeline_info = vk::TimelineSemaphoreSubmitInfo::builder()
+            .wait_semaphore_values(&wait_values)
+            .signal_semaphore_values(std::slice::from_ref(&signal_value));
+        let submit_info = vk::SubmitInfo::builder()
+            .wait_semaphores(&wait_semaphores)
+            .wait_dst_stage_mask(&wait_stages)
+            .command_buffers(command_buffers)
+            .signal_semaphores(std::slice::from_ref(&signal_semaphore))
+            .push_next(&mut timeline_info);
+
+        self.device
+            .queue_submit(queue, std::slice::from_ref(&submit_info), vk::Fence::null())?;
+        timeline.submitted(signal_value);
+
+        Ok(signal_value)
+    }
+
+    unsafe fn free_finished_single_time_commands(&self) -> Result<()> {
+        let completed_value = self.graphics_timeline.completed_value(self)?;
+        let mut pending = self.pending_single_time_commands.borrow_mut();
+        let finished = pending
+            .iter()
+            .filter(|(value, _)| *value <= completed_value)
+            .map(|(_, command_buffer)| *command_buffer)
+            .collect::<Vec<_>>();
+
+        if !finished.is_empty() {
+            self.device
+                .free_command_buffers(self.command_pool, &finished);
+            pending.retain(|(value, _)| *value > completed_value);
+        }
+
+        Ok(())
+    }
+
     fn is_device_suitable(
         instance: &ash::Instance,
         surface: &crate::Surface,
@@ -524,9 +1015,15 @@ impl Device {
     }
 
     fn get_required_extensions(window: &crate::Window) -> Result<Vec<*const i8>> {
-        let mut extensions =
-            ash_window::enumerate_required_extensions(window.window().raw_display_handle())?
-                .to_vec();
+        let mut extensions = match window.window() {
+            Some(window) => {
+                ash_window::enumerate_required_extensions(window.raw_display_handle())?.to_vec()
+            }
+            None => vec![
+                crate::Surface::extension_name().as_ptr(),
+                ash::extensions::ext::HeadlessSurface::name().as_ptr(),
+            ],
+        };
 
         if lve_utils::is_debug_build() {
             extensions.push(crate::DebugUtilsMessenger::extension_name().as_ptr());
@@ -576,20 +1073,37 @@ impl Device {
                 surface.get_physical_device_surface_support(physical_device, idx as u32)
             }?;
 
-            if queue_family.queue_count > 0 {
-                if (queue_family.queue_flags & vk::QueueFlags::GRAPHICS) == vk::QueueFlags::GRAPHICS
-                {
+            if queue_family.queue_count == 0 {
+                continue;
+            }
+            let flags = queue_family.queue_flags;
+
+            if !indices.is_complete() {
+                // Compute work is recorded into the frame command buffer,
+                //  so the graphics queue has to support compute as well
+                if flags.contains(vk::QueueFlags::GRAPHICS | vk::QueueFlags::COMPUTE) {
                     indices.graphics_family = Some(idx as u32);
                 }
                 if present_support {
                     indices.present_family = Some(idx as u32);
                 }
             }
-
-            if indices.is_complete() {
-                break;
+            if indices.transfer_family.is_none()
+                && flags.contains(vk::QueueFlags::TRANSFER)
+                && !flags.intersects(vk::QueueFlags::GRAPHICS | vk::QueueFlags::COMPUTE)
+            {
+                indices.transfer_family = Some(idx as u32);
+            }
+            if indices.compute_family.is_none()
+                && flags.contains(vk::QueueFlags::COMPUTE)
+                && !flags.contains(vk::QueueFlags::GRAPHICS)
+            {
+                indices.compute_family = Some(idx as u32);
             }
         }
+        // Graphics families support transfer and compute too
+        indices.transfer_family = indices.transfer_family.or(indices.graphics_family);
+        indices.compute_family = indices.compute_family.or(indices.graphics_family);
 
         Ok(indices)
     }
@@ -628,6 +1142,23 @@ impl Device {
         Ok(())
     }
 
+    fn check_instance_extension_support_for(
+        entry: &ash::Entry,
+        requested_extensions: &[&CStr],
+    ) -> Result<bool> {
+        let requested_extensions_available = entry
+            .enumerate_instance_extension_properties(None)?
+            .iter()
+            .filter(|extension| {
+                let extension_name = unsafe { CStr::from_ptr(extension.extension_name.as_ptr()) };
+
+                requested_extensions.contains(&extension_name)
+            })
+            .count();
+
+        Ok(requested_extensions_available == requested_extensions.len())
+    }
+
     fn check_device_extension_support(
         instance: &ash::Instance,
         physical_device: &vk::PhysicalDevice,
@@ -636,6 +1167,15 @@ impl Device {
             .iter()
             .map(|extension| unsafe { CStr::from_ptr(*extension) })
             .collect::<Vec<_>>();
+
+        Self::check_device_extension_support_for(instance, physical_device, &required_extensions)
+    }
+
+    fn check_device_extension_support_for(
+        instance: &ash::Instance,
+        physical_device: &vk::PhysicalDevice,
+        required_extensions: &[&CStr],
+    ) -> Result<bool> {
         let required_extensions_available =
             unsafe { instance.enumerate_device_extension_properties(*physical_device) }?
                 .iter()
@@ -0,0 +1,266 @@
+use ash::vk;
+
+pub(crate) struct ColorAttachment {
+    pub image: vk::Image,
+    pub view: vk::ImageView,
+    // Single-sampled image the multisampled `image` resolves into
+    pub resolve: Option<(vk::Image, vk::ImageView)>,
+    pub clear_color: [f32; 4],
+    pub load_op: vk::AttachmentLoadOp,
+    // Applies to `image`; a resolve target is always stored
+    pub store_op: vk::AttachmentStoreOp,
+    // Layout `image` was left in by an earlier pass, only used with LOAD
+    pub initial_layout: vk::ImageLayout,
+    // Layout of the final (resolved) image once rendering has ended
+    pub final_layout: vk::ImageLayout,
+}
+
+pub(crate) struct DepthAttachment {
+    pub image: vk::Image,
+    pub view: vk::ImageView,
+    pub format: vk::Format,
+    pub clear_depth: f32,
+    pub load_op: vk::AttachmentLoadOp,
+    pub store_op: vk::AttachmentStoreOp,
+    // Only used with LOAD
+    pub initial_layout: vk::ImageLayout,
+    // None when the image stays in DEPTH_STENCIL_ATTACHMENT_OPTIMAL
+    pub final_layout: Option<vk::ImageLayout>,
+}
+
+/* MEMO
+ *  Records what a single-subpass VkRenderPass would otherwise do implicitly:
+ *  attachments start out UNDEFINED unless they are loaded, move to attachment layouts for vkCmdBeginRendering and to their final layouts
+ *  once vkCmdEndRendering has been recorded.
+ */
+pub(crate) unsafe fn begin_rendering(
+    device: &crate::Device,
+    command_buffer: &vk::CommandBuffer,
+    extent: vk::Extent2D,
+    color: Option<&ColorAttachment>,
+    depth: Option<&DepthAttachment>,
+    flags: vk::RenderingFlags,
+) {
+    let mut barriers = vec![];
+
+    if let Some(color) = color {
+        barriers.push(image_barrier(
+            color.image,
+            vk::ImageAspectFlags::COLOR,
+            old_layout(color.load_op, color.initial_layout),
+            vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+            vk::AccessFlags::COLOR_ATTACHMENT_READ | vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+        ));
+        // Overwritten by the resolve, so its contents never matter
+        if let Some((resolve_image, _)) = color.resolve {
+            barriers.push(image_barrier(
+                resolve_image,
+                vk::ImageAspectFlags::COLOR,
+                vk::ImageLayout::UNDEFINED,
+                vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+                vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+            ));
+        }
+    }
+    if let Some(depth) = depth {
+        barriers.push(image_barrier(
+            depth.image,
+            depth_aspect_mask(depth.format),
+            old_layout(depth.load_op, depth.initial_layout),
+            vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+            vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+            vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ
+                | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+        ));
+    }
+
+    // Waits for earlier writes to and sampling of these images
+    device.device().cmd_pipeline_barrier(
+        *command_buffer,
+        vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+            | vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS
+            | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS
+            | vk::PipelineStageFlags::FRAGMENT_SHADER,
+        vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+            | vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS
+            | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+        vk::DependencyFlags::empty(),
+        &[],
+        &[],
+        &barriers,
+    );
+
+    let color_attachment = color.map(|color| {
+        let mut attachment = vk::RenderingAttachmentInfo::builder()
+            .image_view(color.view)
+            .image_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+            .load_op(color.load_op)
+            .store_op(color.store_op)
+            .clear_value(vk::ClearValue {
+                color: vk::ClearColorValue {
+                    float32: color.clear_color,
+                },
+            });
+
+        if let Some((_, resolve_view)) = color.resolve {
+            attachment = attachment
+                .resolve_mode(vk::ResolveModeFlags::AVERAGE)
+                .resolve_image_view(resolve_view)
+                .resolve_image_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL);
+        }
+
+        attachment.build()
+    });
+    let depth_attachment = depth.map(|depth| {
+        vk::RenderingAttachmentInfo::builder()
+            .image_view(depth.view)
+            .image_layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL)
+            .load_op(depth.load_op)
+            .store_op(depth.store_op)
+            .clear_value(vk::ClearValue {
+                depth_stencil: vk::ClearDepthStencilValue::builder()
+                    .depth(depth.clear_depth)
+                    .stencil(0)
+                    .build(),
+            })
+            .build()
+    });
+    let mut rendering_info = vk::RenderingInfo::builder()
+        .flags(flags)
+        .render_area(vk::Rect2D {
+            offset: vk::Offset2D { x: 0, y: 0 },
+            extent,
+        })
+        .layer_count(1);
+
+    if let Some(color_attachment) = color_attachment.as_ref() {
+        rendering_info = rendering_info.color_attachments(std::slice::from_ref(color_attachment));
+    }
+    if let Some(depth_attachment) = depth_attachment.as_ref() {
+        rendering_info = rendering_info.depth_attachment(depth_attachment);
+    }
+
+    device
+        .device()
+        .cmd_begin_rendering(*command_buffer, &rendering_info);
+}
+
+pub(crate) unsafe fn end_rendering(
+    device: &crate::Device,
+    command_buffer: &vk::CommandBuffer,
+    color: Option<&ColorAttachment>,
+    depth: Option<&DepthAttachment>,
+) {
+    device.device().cmd_end_rendering(*command_buffer);
+
+    let mut barriers = vec![];
+    let mut dst_stage = vk::PipelineStageFlags::empty();
+
+    if let Some(color) = color {
+        let (dst, dst_access) = final_stage_and_access(color.final_layout);
+
+        dst_stage |= dst;
+        barriers.push(image_barrier(
+            color.resolve.map_or(color.image, |(image, _)| image),
+            vk::ImageAspectFlags::COLOR,
+            vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            color.final_layout,
+            vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+            dst_access,
+        ));
+    }
+    if let Some((depth, final_layout)) =
+        depth.and_then(|depth| depth.final_layout.map(|final_layout| (depth, final_layout)))
+    {
+        let (dst, dst_access) = final_stage_and_access(final_layout);
+
+        dst_stage |= dst;
+        barriers.push(image_barrier(
+            depth.image,
+            depth_aspect_mask(depth.format),
+            vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+            final_layout,
+            vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+            dst_access,
+        ));
+    }
+
+    if barriers.is_empty() {
+        return;
+    }
+
+    device.device().cmd_pipeline_barrier(
+        *command_buffer,
+        vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+            | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+        dst_stage,
+        vk::DependencyFlags::empty(),
+        &[],
+        &[],
+        &barriers,
+    );
+}
+
+/* --- Helper functions --- */
+fn image_barrier(
+    image: vk::Image,
+    aspect_mask: vk::ImageAspectFlags,
+    old_layout: vk::ImageLayout,
+    new_layout: vk::ImageLayout,
+    src_access: vk::AccessFlags,
+    dst_access: vk::AccessFlags,
+) -> vk::ImageMemoryBarrier {
+    vk::ImageMemoryBarrier::builder()
+        .src_access_mask(src_access)
+        .dst_access_mask(dst_access)
+        .old_layout(old_layout)
+        .new_layout(new_layout)
+        .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+        .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+        .image(image)
+        .subresource_range(vk::ImageSubresourceRange {
+            aspect_mask,
+            base_mip_level: 0,
+            level_count: 1,
+            base_array_layer: 0,
+            layer_count: 1,
+        })
+        .build()
+}
+
+#[inline]
+fn old_layout(load_op: vk::AttachmentLoadOp, initial_layout: vk::ImageLayout) -> vk::ImageLayout {
+    if load_op == vk::AttachmentLoadOp::LOAD {
+        initial_layout
+    } else {
+        vk::ImageLayout::UNDEFINED
+    }
+}
+
+// Barriers on combined formats have to name both aspects
+pub(crate) fn depth_aspect_mask(format: vk::Format) -> vk::ImageAspectFlags {
+    match format {
+        vk::Format::D16_UNORM_S8_UINT
+        | vk::Format::D24_UNORM_S8_UINT
+        | vk::Format::D32_SFLOAT_S8_UINT => {
+            vk::ImageAspectFlags::DEPTH | vk::ImageAspectFlags::STENCIL
+        }
+        _ => vk::ImageAspectFlags::DEPTH,
+    }
+}
+
+fn final_stage_and_access(layout: vk::ImageLayout) -> (vk::PipelineStageFlags, vk::AccessFlags) {
+    match layout {
+        // Presentation is ordered by the render finished semaphore
+        vk::ImageLayout::PRESENT_SRC_KHR => (
+            vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+            vk::AccessFlags::empty(),
+        ),
+        _ => (
+            vk::PipelineStageFlags::FRAGMENT_SHADER,
+            vk::AccessFlags::SHADER_READ,
+        ),
+    }
+}
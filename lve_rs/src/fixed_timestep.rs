@@ -0,0 +1,150 @@
+/* MEMO
+ *  Splits variable frame times into fixed simulation steps. Every rendered frame
+ *  calls advance with its delta time and runs the returned number of steps (zero
+ *  or more), each covering exactly `step` seconds, so game logic does not depend
+ *  on the frame rate. What is left over is exposed as `alpha`, the fraction of a
+ *  step the rendered frame lies past the last simulated state, for interpolating
+ *  between the previous and the current state (see GameObject::interpolated_transform).
+ *
+ *  for _ in 0..fixed_timestep.advance(delta_time) {
+ *      simulate(fixed_timestep.step());
+ *  }
+ *  render(fixed_timestep.alpha());
+ */
+#[derive(Debug, Clone, Copy)]
+pub struct FixedTimestep {
+    step: f64,
+    accumulator: f64,
+    max_steps: u32,
+    ticks: u64,
+}
+
+impl FixedTimestep {
+    pub const DEFAULT_RATE: f64 = 60.0;
+    // Frames longer than this many steps drop the rest instead of catching up
+    pub const DEFAULT_MAX_STEPS: u32 = 8;
+    // Absorbs rounding so e.g. 1/60 s frames always run exactly one 1/60 s step
+    const TOLERANCE: f64 = 1e-6;
+
+    pub fn new(step: f32) -> Self {
+        assert!(step > 0.0, "Fixed timestep must be positive");
+
+        Self {
+            step: step as f64,
+            accumulator: 0.0,
+            max_steps: Self::DEFAULT_MAX_STEPS,
+            ticks: 0,
+        }
+    }
+
+    // `rate` steps per second
+    pub fn with_rate(rate: f64) -> Self {
+        Self::new((1.0 / rate) as f32)
+    }
+
+    pub fn max_steps(&self, max_steps: u32) -> Self {
+        Self { max_steps, ..*self }
+    }
+
+    // Returns how many steps to run for a frame that took `delta_time` seconds
+    pub fn advance(&mut self, delta_time: f32) -> u32 {
+        self.accumulator += delta_time.max(0.0) as f64;
+
+        let mut steps = 0;
+
+        while self.accumulator + self.step * Self::TOLERANCE >= self.step {
+            if steps == self.max_steps {
+                self.accumulator = 0.0;
+                break;
+            }
+            self.accumulator = (self.accumulator - self.step).max(0.0);
+            steps += 1;
+        }
+        self.ticks += steps as u64;
+
+        steps
+    }
+
+    #[inline]
+    pub fn step(&self) -> f32 {
+        self.step as f32
+    }
+
+    // Between 0 and 1; how far the frame lies past the last step
+    #[inline]
+    pub fn alpha(&self) -> f32 {
+        (self.accumulator / self.step).clamp(0.0, 1.0) as f32
+    }
+
+    // Number of steps run so far
+    #[inline]
+    pub fn ticks(&self) -> u64 {
+        self.ticks
+    }
+}
+
+impl Default for FixedTimestep {
+    fn default() -> Self {
+        Self::with_rate(Self::DEFAULT_RATE)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn runs_one_step_per_frame_at_the_step_rate() {
+        let mut fixed_timestep = FixedTimestep::default();
+
+        for _ in 0..600 {
+            assert_eq!(fixed_timestep.advance(1.0 / 60.0), 1);
+        }
+        assert_eq!(fixed_timestep.ticks(), 600);
+    }
+
+    #[test]
+    fn clamps_long_frames_to_max_steps() {
+        let mut fixed_timestep = FixedTimestep::with_rate(60.0).max_steps(3);
+
+        assert_eq!(fixed_timestep.advance(1.0), 3);
+        // The rest of the frame is dropped rather than carried over
+        assert_eq!(fixed_timestep.alpha(), 0.0);
+        assert_eq!(fixed_timestep.advance(1.0 / 60.0), 1);
+        assert_eq!(fixed_timestep.ticks(), 4);
+    }
+
+    #[test]
+    fn accumulates_short_frames() {
+        let mut fixed_timestep = FixedTimestep::with_rate(60.0);
+
+        assert_eq!(fixed_timestep.advance(1.0 / 120.0), 0);
+        assert!((fixed_timestep.alpha() - 0.5).abs() < 1e-4);
+        assert_eq!(fixed_timestep.advance(1.0 / 120.0), 1);
+        assert_eq!(fixed_timestep.advance(-1.0), 0);
+    }
+
+    #[test]
+    fn carries_the_remainder_over_to_alpha() {
+        let mut fixed_timestep = FixedTimestep::with_rate(10.0);
+
+        // (delta time, steps, alpha afterwards) with 0.1 s steps
+        for (delta_time, steps, alpha) in [
+            (0.25, 2, 0.5),
+            (0.07, 1, 0.2),
+            (0.09, 1, 0.1),
+            (0.0, 0, 0.1),
+            (0.05, 0, 0.6),
+        ] {
+            assert_eq!(fixed_timestep.advance(delta_time), steps);
+            assert!(
+                (fixed_timestep.alpha() - alpha).abs() < 1e-4,
+                "alpha {} after {} s, expected {}",
+                fixed_timestep.alpha(),
+                delta_time,
+                alpha
+            );
+        }
+        assert_eq!(fixed_timestep.ticks(), 4);
+    }
+}
@@ -9,24 +9,202 @@ pub struct PointLight {
     pub color: glm::Vec4,
 }
 
+// std140 layout of the shaders' GlobalUbo block, whose MAX_LIGHTS specialization
+//  constant has to be set to LIGHTS (see GlobalUbo::MAX_LIGHTS)
 #[derive(Debug, Clone, Copy)]
 #[repr(C, align(16))]
-pub struct GlobalUbo {
+pub struct GlobalUbo<const LIGHTS: usize = MAX_LIGHT> {
     pub projection: glm::Mat4,
     pub view: glm::Mat4,
     pub inverse_view: glm::Mat4,
     pub ambient_light_color: glm::Vec4,
-    pub point_lights: [PointLight; MAX_LIGHT],
     pub num_lights: i32,
+    // Last, so its length is the only thing that depends on LIGHTS
+    pub point_lights: [PointLight; LIGHTS],
 }
 
 pub struct FrameInfo<'a> {
     pub frame_index: usize,
     pub frame_time: f32,
+    // FixedTimestep::alpha, for GameObject::interpolated_transform
+    pub alpha: f32,
     pub command_buffer: vk::CommandBuffer,
     pub camera: &'a crate::Camera,
     pub global_descriptor_set: vk::DescriptorSet,
     pub game_objects: &'a mut crate::Map,
+    // Render systems add to these as they record
+    pub draw_counters: &'a crate::DrawCounters,
+    // Stats of the most recently completed frame, if any
+    pub last_frame_stats: Option<crate::FrameStats>,
+}
+
+impl<const LIGHTS: usize> GlobalUbo<LIGHTS> {
+    // Value of the shaders' MAX_LIGHTS specialization constant
+    pub const MAX_LIGHTS: usize = LIGHTS;
+}
+
+impl FrameInfo<'_> {
+    #[inline]
+    pub unsafe fn begin_render_target(
+        &self,
+        device: &crate::Device,
+        render_target: &crate::RenderTarget,
+    ) {
+        render_target.begin_render_pass(device, &self.command_buffer)
+    }
+
+    #[inline]
+    pub unsafe fn end_render_target(
+        &self,
+        device: &crate::Device,
+        render_target: &crate::RenderTarget,
+    ) {
+        render_target.end_render_pass(device, &self.command_buffer)
+    }
+
+    pub unsafe fn dispatch(
+        &self,
+        device: &crate::Device,
+        pipeline: &crate::ComputePipeline,
+        group_count_x: u32,
+        group_count_y: u32,
+        group_count_z: u32,
+    ) {
+        pipeline.bind(device, &self.command_buffer);
+        device.device().cmd_dispatch(
+            self.command_buffer,
+            group_count_x,
+            group_count_y,
+            group_count_z,
+        );
+    }
+
+    pub unsafe fn dispatch_indirect(
+        &self,
+        device: &crate::Device,
+        pipeline: &crate::ComputePipeline,
+        buffer: &vk::Buffer,
+        offset: vk::DeviceSize,
+    ) {
+        pipeline.bind(device, &self.command_buffer);
+        device
+            .device()
+            .cmd_dispatch_indirect(self.command_buffer, *buffer, offset);
+    }
+
+    // Makes compute shader writes visible to a following dispatch
+    pub unsafe fn compute_to_compute_barrier(&self, device: &crate::Device) {
+        self.memory_barrier(
+            device,
+            vk::PipelineStageFlags::COMPUTE_SHADER,
+            vk::AccessFlags::SHADER_WRITE,
+            vk::PipelineStageFlags::COMPUTE_SHADER,
+            vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE,
+        );
+    }
+
+    // Makes compute shader writes to `buffer` visible to graphics reads at `dst_stage`,
+    // e.g. VERTEX_INPUT + VERTEX_ATTRIBUTE_READ for a particle vertex buffer.
+    pub unsafe fn compute_to_graphics_barrier(
+        &self,
+        device: &crate::Device,
+        buffer: &vk::Buffer,
+        dst_stage: vk::PipelineStageFlags,
+        dst_access: vk::AccessFlags,
+    ) {
+        let barrier = vk::BufferMemoryBarrier::builder()
+            .src_access_mask(vk::AccessFlags::SHADER_WRITE)
+            .dst_access_mask(dst_access)
+            .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .buffer(*buffer)
+            .offset(0)
+            .size(vk::WHOLE_SIZE)
+            .build();
+
+        device.device().cmd_pipeline_barrier(
+            self.command_buffer,
+            vk::PipelineStageFlags::COMPUTE_SHADER,
+            dst_stage,
+            vk::DependencyFlags::empty(),
+            &[],
+            std::slice::from_ref(&barrier),
+            &[],
+        );
+    }
+
+    // Makes compute shader writes to a storage image visible to fragment shader sampling
+    pub unsafe fn compute_to_graphics_image_barrier(
+        &self,
+        device: &crate::Device,
+        image: &vk::Image,
+        old_layout: vk::ImageLayout,
+        new_layout: vk::ImageLayout,
+    ) {
+        let barrier = vk::ImageMemoryBarrier::builder()
+            .src_access_mask(vk::AccessFlags::SHADER_WRITE)
+            .dst_access_mask(vk::AccessFlags::SHADER_READ)
+            .old_layout(old_layout)
+            .new_layout(new_layout)
+            .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .image(*image)
+            .subresource_range(vk::ImageSubresourceRange {
+                aspect_mask: vk::ImageAspectFlags::COLOR,
+                base_mip_level: 0,
+                level_count: vk::REMAINING_MIP_LEVELS,
+                base_array_layer: 0,
+                layer_count: vk::REMAINING_ARRAY_LAYERS,
+            })
+            .build();
+
+        device.device().cmd_pipeline_barrier(
+            self.command_buffer,
+            vk::PipelineStageFlags::COMPUTE_SHADER,
+            vk::PipelineStageFlags::FRAGMENT_SHADER,
+            vk::DependencyFlags::empty(),
+            &[],
+            &[],
+            std::slice::from_ref(&barrier),
+        );
+    }
+
+    // Keeps compute from overwriting data the graphics queue is still reading
+    pub unsafe fn graphics_to_compute_barrier(&self, device: &crate::Device) {
+        self.memory_barrier(
+            device,
+            vk::PipelineStageFlags::VERTEX_INPUT
+                | vk::PipelineStageFlags::VERTEX_SHADER
+                | vk::PipelineStageFlags::FRAGMENT_SHADER,
+            vk::AccessFlags::empty(),
+            vk::PipelineStageFlags::COMPUTE_SHADER,
+            vk::AccessFlags::SHADER_WRITE,
+        );
+    }
+
+    unsafe fn memory_barrier(
+        &self,
+        device: &crate::Device,
+        src_stage: vk::PipelineStageFlags,
+        src_access: vk::AccessFlags,
+        dst_stage: vk::PipelineStageFlags,
+        dst_access: vk::AccessFlags,
+    ) {
+        let barrier = vk::MemoryBarrier::builder()
+            .src_access_mask(src_access)
+            .dst_access_mask(dst_access)
+            .build();
+
+        device.device().cmd_pipeline_barrier(
+            self.command_buffer,
+            src_stage,
+            dst_stage,
+            vk::DependencyFlags::empty(),
+            std::slice::from_ref(&barrier),
+            &[],
+            &[],
+        );
+    }
 }
 
 impl Default for PointLight {
@@ -38,26 +216,15 @@ impl Default for PointLight {
     }
 }
 
-impl Default for GlobalUbo {
+impl<const LIGHTS: usize> Default for GlobalUbo<LIGHTS> {
     fn default() -> Self {
         Self {
             projection: glm::Mat4::identity(),
             view: glm::Mat4::identity(),
             inverse_view: glm::Mat4::identity(),
             ambient_light_color: glm::vec4(1.0, 1.0, 1.0, 0.02),
-            point_lights: [
-                PointLight::default(),
-                PointLight::default(),
-                PointLight::default(),
-                PointLight::default(),
-                PointLight::default(),
-                PointLight::default(),
-                PointLight::default(),
-                PointLight::default(),
-                PointLight::default(),
-                PointLight::default(),
-            ],
             num_lights: 0,
+            point_lights: [PointLight::default(); LIGHTS],
         }
     }
 }
@@ -0,0 +1,341 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+// Frame time statistics over FramePacer's history, in milliseconds
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct FrameTimingStats {
+    pub frames: usize,
+    pub fps: f64,
+    pub average_ms: f64,
+    pub min_ms: f64,
+    pub max_ms: f64,
+    pub p50_ms: f64,
+    pub p95_ms: f64,
+    pub p99_ms: f64,
+}
+
+/* MEMO
+ *  Decides when the next frame starts and how much time it covers.
+ *
+ *  With a target FPS the frames are scheduled on a fixed grid: the event loop
+ *  sleeps with ControlFlow::WaitUntil(next_deadline) and is_frame_due spins only
+ *  for the last SPIN_THRESHOLD, which the OS timer can't hit reliably. Nothing
+ *  waits for the GPU to go idle, so the frames in flight keep it busy.
+ *
+ *  The delta handed to the frame is clamped to `max_delta` (a breakpoint or a
+ *  window drag shouldn't teleport the camera) and smoothed with an exponential
+ *  moving average to hide scheduler jitter.
+ *
+ *  if pacer.is_frame_due() {
+ *      let delta_time = pacer.begin_frame();
+ *      ...
+ *  }
+ *  match pacer.next_deadline() {
+ *      Some(deadline) => control_flow.set_wait_until(deadline),
+ *      None => control_flow.set_poll(),
+ *  }
+ */
+pub struct FramePacer {
+    target_frame_time: Option<Duration>,
+    deadline: Option<Instant>,
+    last_frame: Option<Instant>,
+    raw_delta: f32,
+    smoothed_delta: f32,
+    smoothing: f32,
+    max_delta: f32,
+    history: VecDeque<f32>,
+    history_len: usize,
+}
+
+impl FramePacer {
+    pub const DEFAULT_SMOOTHING: f32 = 0.1;
+    pub const DEFAULT_MAX_DELTA: f32 = 0.1;
+    pub const DEFAULT_HISTORY_LEN: usize = 240;
+    // Below this, is_frame_due busy-waits instead of handing control back to the OS
+    const SPIN_THRESHOLD: Duration = Duration::from_millis(1);
+
+    pub fn new() -> Self {
+        Self {
+            target_frame_time: None,
+            deadline: None,
+            last_frame: None,
+            raw_delta: 0.0,
+            smoothed_delta: 0.0,
+            smoothing: Self::DEFAULT_SMOOTHING,
+            max_delta: Self::DEFAULT_MAX_DELTA,
+            history: VecDeque::with_capacity(Self::DEFAULT_HISTORY_LEN),
+            history_len: Self::DEFAULT_HISTORY_LEN,
+        }
+    }
+
+    // None, or a non-positive value, removes the cap
+    pub fn set_target_fps(&mut self, target_fps: Option<f64>) {
+        self.target_frame_time = target_fps
+            .filter(|target_fps| *target_fps > 0.0)
+            .map(|target_fps| Duration::from_secs_f64(1.0 / target_fps));
+        self.deadline = None;
+    }
+
+    #[inline]
+    pub fn target_fps(&self) -> Option<f64> {
+        self.target_frame_time
+            .map(|target_frame_time| 1.0 / target_frame_time.as_secs_f64())
+    }
+
+    // Weight of the newest frame in the moving average; 1.0 disables smoothing
+    pub fn set_smoothing(&mut self, smoothing: f32) {
+        self.smoothing = smoothing.clamp(f32::EPSILON, 1.0);
+    }
+
+    pub fn set_max_delta(&mut self, max_delta: f32) {
+        self.max_delta = max_delta;
+    }
+
+    pub fn set_history_len(&mut self, history_len: usize) {
+        self.history_len = history_len;
+        while self.history.len() > history_len {
+            self.history.pop_front();
+        }
+    }
+
+    // When the event loop should wake up for the next frame; None means right away
+    #[inline]
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.deadline.map(|deadline| {
+            deadline
+                .checked_sub(Self::SPIN_THRESHOLD)
+                .unwrap_or(deadline)
+        })
+    }
+
+    // Spins out the last moment before the deadline so the frame starts on time
+    pub fn is_frame_due(&self) -> bool {
+        let deadline = match self.deadline {
+            Some(deadline) => deadline,
+            None => return true,
+        };
+        let now = Instant::now();
+
+        if now >= deadline {
+            return true;
+        }
+        if deadline - now > Self::SPIN_THRESHOLD {
+            return false;
+        }
+        while Instant::now() < deadline {
+            std::hint::spin_loop();
+        }
+
+        true
+    }
+
+    // Starts a frame and returns its smoothed delta time in seconds
+    pub fn begin_frame(&mut self) -> f32 {
+        let now = Instant::now();
+
+        self.raw_delta = match self.last_frame {
+            Some(last_frame) => (now - last_frame).as_secs_f32(),
+            None => 0.0,
+        };
+        self.last_frame = Some(now);
+
+        if self.history.len() == self.history_len && self.history_len > 0 {
+            self.history.pop_front();
+        }
+        if self.history_len > 0 && self.raw_delta > 0.0 {
+            self.history.push_back(self.raw_delta);
+        }
+
+        let delta = self.raw_delta.min(self.max_delta);
+
+        self.smoothed_delta = if self.smoothed_delta == 0.0 {
+            delta
+        } else {
+            self.smoothed_delta + (delta - self.smoothed_delta) * self.smoothing
+        };
+
+        // Stays on the grid unless a frame ran late, which restarts it from now
+        self.deadline = self
+            .target_frame_time
+            .map(|target_frame_time| match self.deadline {
+                Some(deadline) if now < deadline + target_frame_time => {
+                    deadline + target_frame_time
+                }
+                _ => now + target_frame_time,
+            });
+
+        self.smoothed_delta
+    }
+
+    // After a pause (e.g. while minimized), so the next delta doesn't include it
+    pub fn reset(&mut self) {
+        self.last_frame = None;
+        self.deadline = None;
+    }
+
+    #[inline]
+    pub fn delta_time(&self) -> f32 {
+        self.smoothed_delta
+    }
+
+    // Unsmoothed and unclamped time since the previous frame
+    #[inline]
+    pub fn raw_delta_time(&self) -> f32 {
+        self.raw_delta
+    }
+
+    pub fn stats(&self) -> FrameTimingStats {
+        if self.history.is_empty() {
+            return FrameTimingStats::default();
+        }
+
+        let mut frame_times = self
+            .history
+            .iter()
+            .map(|delta| *delta as f64 * 1000.0)
+            .collect::<Vec<_>>();
+
+        frame_times.sort_by(|a, b| a.total_cmp(b));
+
+        let average_ms = frame_times.iter().sum::<f64>() / frame_times.len() as f64;
+        let percentile = |p: f64| {
+            let index = ((frame_times.len() - 1) as f64 * p).round() as usize;
+
+            frame_times[index]
+        };
+
+        FrameTimingStats {
+            frames: frame_times.len(),
+            fps: 1000.0 / average_ms,
+            average_ms,
+            min_ms: frame_times[0],
+            max_ms: frame_times[frame_times.len() - 1],
+            p50_ms: percentile(0.50),
+            p95_ms: percentile(0.95),
+            p99_ms: percentile(0.99),
+        }
+    }
+}
+
+impl Default for FramePacer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-3,
+            "{} is not close to {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn computes_stats_over_history() {
+        let mut pacer = FramePacer::new();
+
+        // 1..=100 ms, out of order
+        pacer.history = (1..=100)
+            .map(|ms| ((ms * 37) % 100 + 1) as f32 / 1000.0)
+            .collect();
+
+        let stats = pacer.stats();
+
+        assert_eq!(stats.frames, 100);
+        assert_close(stats.average_ms, 50.5);
+        assert_close(stats.fps, 1000.0 / 50.5);
+        assert_close(stats.min_ms, 1.0);
+        assert_close(stats.max_ms, 100.0);
+        assert_close(stats.p50_ms, 51.0);
+        assert_close(stats.p95_ms, 95.0);
+        assert_close(stats.p99_ms, 99.0);
+    }
+
+    #[test]
+    fn has_empty_stats_without_history() {
+        assert_eq!(FramePacer::new().stats(), FrameTimingStats::default());
+    }
+
+    #[test]
+    fn keeps_history_len_frames() {
+        let mut pacer = FramePacer::new();
+
+        pacer.history = (1..=10).map(|ms| ms as f32 / 1000.0).collect();
+        pacer.set_history_len(4);
+
+        let stats = pacer.stats();
+
+        assert_eq!(stats.frames, 4);
+        assert_close(stats.min_ms, 7.0);
+    }
+
+    #[test]
+    fn clamps_and_smooths_delta_time() {
+        let mut pacer = FramePacer::new();
+
+        pacer.last_frame = Some(Instant::now() - Duration::from_secs(1));
+        assert_eq!(pacer.begin_frame(), FramePacer::DEFAULT_MAX_DELTA);
+        assert!(pacer.raw_delta_time() >= 1.0);
+
+        pacer.set_smoothing(0.5);
+        pacer.last_frame = Some(Instant::now() - Duration::from_millis(50));
+
+        let delta_time = pacer.begin_frame();
+
+        assert!(delta_time > 0.07 && delta_time < 0.1);
+    }
+
+    #[test]
+    fn schedules_frames_on_a_fixed_grid() {
+        let mut pacer = FramePacer::new();
+
+        pacer.set_target_fps(Some(100.0));
+        let start = Instant::now();
+        pacer.begin_frame();
+        let first = pacer.deadline.unwrap();
+
+        assert!(first >= start + Duration::from_millis(10));
+        assert_eq!(
+            pacer.next_deadline(),
+            Some(first - FramePacer::SPIN_THRESHOLD)
+        );
+
+        // Starting early keeps the grid instead of drifting with the start time
+        pacer.begin_frame();
+        assert_eq!(pacer.deadline, Some(first + Duration::from_millis(10)));
+    }
+
+    #[test]
+    fn restarts_the_grid_after_a_late_frame() {
+        let mut pacer = FramePacer::new();
+
+        pacer.set_target_fps(Some(100.0));
+        pacer.begin_frame();
+        pacer.deadline = Some(Instant::now() - Duration::from_secs(1));
+
+        let start = Instant::now();
+        pacer.begin_frame();
+
+        assert!(pacer.deadline.unwrap() >= start + Duration::from_millis(10));
+    }
+
+    #[test]
+    fn runs_uncapped_without_target_fps() {
+        let mut pacer = FramePacer::new();
+
+        pacer.set_target_fps(Some(0.0));
+        pacer.begin_frame();
+
+        assert_eq!(pacer.target_fps(), None);
+        assert_eq!(pacer.next_deadline(), None);
+        assert!(pacer.is_frame_due());
+    }
+}
@@ -0,0 +1,279 @@
+use anyhow::Result;
+use ash::vk;
+use std::cell::Cell;
+
+// Incremented by render systems while they record; see FrameInfo::draw_counters
+#[derive(Default)]
+pub struct DrawCounters {
+    draw_calls: Cell<u64>,
+    triangles: Cell<u64>,
+    pipeline_binds: Cell<u64>,
+    descriptor_binds: Cell<u64>,
+    push_constant_uploads: Cell<u64>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DrawStats {
+    pub draw_calls: u64,
+    pub triangles: u64,
+    pub pipeline_binds: u64,
+    pub descriptor_binds: u64,
+    pub push_constant_uploads: u64,
+}
+
+// VK_QUERY_TYPE_PIPELINE_STATISTICS results, in the order of their flag bits
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[repr(C)]
+pub struct PipelineStatistics {
+    pub input_assembly_vertices: u64,
+    pub input_assembly_primitives: u64,
+    pub vertex_shader_invocations: u64,
+    pub clipping_invocations: u64,
+    pub clipping_primitives: u64,
+    pub fragment_shader_invocations: u64,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FrameStats {
+    pub frame: u64,
+    pub draws: DrawStats,
+    // None when the device lacks the pipelineStatisticsQuery feature
+    pub pipeline_statistics: Option<PipelineStatistics>,
+}
+
+/* MEMO
+ *  Like GpuProfiler, keeps one set of counters and one query pool per frame in
+ *  flight. A frame's stats are read back the next time its frame index comes
+ *  around, after the renderer has waited for it, and are then handed to the
+ *  following frames through FrameInfo::last_frame_stats.
+ */
+pub struct FrameStatsCollector {
+    // Empty when pipeline statistics queries are not supported
+    query_pools: Vec<vk::QueryPool>,
+    // Set while secondary command buffers can't inherit the query
+    queries_paused: bool,
+    counters: Vec<DrawCounters>,
+    // Frame number and whether its query was recorded
+    pending_frames: Vec<Option<(u64, bool)>>,
+    current_frame_index: Option<usize>,
+    frame_count: u64,
+    last_frame: Option<FrameStats>,
+}
+
+impl DrawCounters {
+    #[inline]
+    pub fn record_draw(&self, triangles: u64) {
+        Self::add(&self.draw_calls, 1);
+        Self::add(&self.triangles, triangles);
+    }
+
+    #[inline]
+    pub fn record_pipeline_bind(&self) {
+        Self::add(&self.pipeline_binds, 1);
+    }
+
+    #[inline]
+    pub fn record_descriptor_bind(&self) {
+        Self::add(&self.descriptor_binds, 1);
+    }
+
+    #[inline]
+    pub fn record_push_constants(&self, uploads: u64) {
+        Self::add(&self.push_constant_uploads, uploads);
+    }
+
+    pub fn stats(&self) -> DrawStats {
+        DrawStats {
+            draw_calls: self.draw_calls.get(),
+            triangles: self.triangles.get(),
+            pipeline_binds: self.pipeline_binds.get(),
+            descriptor_binds: self.descriptor_binds.get(),
+            push_constant_uploads: self.push_constant_uploads.get(),
+        }
+    }
+
+    pub fn reset(&self) {
+        for counter in [
+            &self.draw_calls,
+            &self.triangles,
+            &self.pipeline_binds,
+            &self.descriptor_binds,
+            &self.push_constant_uploads,
+        ] {
+            counter.set(0);
+        }
+    }
+
+    /* --- Helper functions --- */
+    #[inline]
+    fn add(counter: &Cell<u64>, value: u64) {
+        counter.set(counter.get() + value);
+    }
+}
+
+impl FrameStatsCollector {
+    // Also inherited by secondary command buffers, see SecondaryInheritance
+    pub(crate) const STATISTICS: vk::QueryPipelineStatisticFlags =
+        vk::QueryPipelineStatisticFlags::from_raw(
+            vk::QueryPipelineStatisticFlags::INPUT_ASSEMBLY_VERTICES.as_raw()
+                | vk::QueryPipelineStatisticFlags::INPUT_ASSEMBLY_PRIMITIVES.as_raw()
+                | vk::QueryPipelineStatisticFlags::VERTEX_SHADER_INVOCATIONS.as_raw()
+                | vk::QueryPipelineStatisticFlags::CLIPPING_INVOCATIONS.as_raw()
+                | vk::QueryPipelineStatisticFlags::CLIPPING_PRIMITIVES.as_raw()
+                | vk::QueryPipelineStatisticFlags::FRAGMENT_SHADER_INVOCATIONS.as_raw(),
+        );
+
+    // Picks up the renderer's current worker threads, see set_secondary_command_buffers
+    pub fn new(device: &crate::Device, renderer: &crate::Renderer) -> Result<Self> {
+        let frames_in_flight = renderer.frames_in_flight();
+        let mut query_pools = Vec::with_capacity(frames_in_flight);
+
+        if device.enabled_features().pipeline_statistics_query != 0 {
+            let create_info = vk::QueryPoolCreateInfo::builder()
+                .query_type(vk::QueryType::PIPELINE_STATISTICS)
+                .query_count(1)
+                .pipeline_statistics(Self::STATISTICS);
+
+            for _ in 0..frames_in_flight {
+                match unsafe { device.device().create_query_pool(&create_info, None) } {
+                    Ok(query_pool) => query_pools.push(query_pool),
+                    Err(err) => {
+                        for query_pool in query_pools {
+                            unsafe { device.device().destroy_query_pool(query_pool, None) };
+                        }
+                        return Err(err.into());
+                    }
+                }
+            }
+        }
+
+        let mut frame_stats = Self {
+            query_pools,
+            queries_paused: false,
+            counters: (0..frames_in_flight)
+                .map(|_| DrawCounters::default())
+                .collect(),
+            pending_frames: vec![None; frames_in_flight],
+            current_frame_index: None,
+            frame_count: 0,
+            last_frame: None,
+        };
+        frame_stats.set_secondary_command_buffers(device, renderer.worker_threads() > 0);
+
+        Ok(frame_stats)
+    }
+
+    pub unsafe fn destroy(&mut self, device: &crate::Device) {
+        for query_pool in self.query_pools.drain(..) {
+            device.device().destroy_query_pool(query_pool, None);
+        }
+    }
+
+    #[inline]
+    pub fn supports_pipeline_statistics(&self) -> bool {
+        !self.query_pools.is_empty() && !self.queries_paused
+    }
+
+    /* MEMO
+     *  The query stays active while the swap chain render pass executes, so once
+     *  Renderer records into secondary command buffers they have to inherit it,
+     *  which needs the inheritedQueries feature. Without it pipeline statistics
+     *  are left out for as long as worker threads are in use. Kept in sync by
+     *  new and Renderer::set_worker_threads.
+     */
+    pub(crate) fn set_secondary_command_buffers(&mut self, device: &crate::Device, enabled: bool) {
+        self.queries_paused = enabled && device.enabled_features().inherited_queries == 0;
+    }
+
+    // Call right after Renderer::begin_frame, outside of any render pass
+    pub unsafe fn begin_frame(
+        &mut self,
+        device: &crate::Device,
+        command_buffer: &vk::CommandBuffer,
+        frame_index: usize,
+    ) {
+        self.resolve(device, frame_index);
+        self.counters[frame_index].reset();
+        let query_pool = self
+            .query_pools
+            .get(frame_index)
+            .filter(|_| !self.queries_paused);
+        if let Some(query_pool) = query_pool {
+            device
+                .device()
+                .cmd_reset_query_pool(*command_buffer, *query_pool, 0, 1);
+            device.device().cmd_begin_query(
+                *command_buffer,
+                *query_pool,
+                0,
+                vk::QueryControlFlags::empty(),
+            );
+        }
+        self.pending_frames[frame_index] = Some((self.frame_count, query_pool.is_some()));
+        self.current_frame_index = Some(frame_index);
+        self.frame_count += 1;
+    }
+
+    // Call before Renderer::end_frame, outside of any render pass
+    pub unsafe fn end_frame(&mut self, device: &crate::Device, command_buffer: &vk::CommandBuffer) {
+        let frame_index = self
+            .current_frame_index
+            .take()
+            .expect("end_frame called before begin_frame");
+
+        let query_recorded = matches!(self.pending_frames[frame_index], Some((_, true)));
+        if let Some(query_pool) = self.query_pools.get(frame_index).filter(|_| query_recorded) {
+            device
+                .device()
+                .cmd_end_query(*command_buffer, *query_pool, 0);
+        }
+    }
+
+    // Counters of the frame being recorded
+    pub fn counters(&self) -> &DrawCounters {
+        let frame_index = self
+            .current_frame_index
+            .expect("counters requested outside of a frame");
+
+        &self.counters[frame_index]
+    }
+
+    // Most recently completed frame
+    #[inline]
+    pub fn last_frame(&self) -> Option<&FrameStats> {
+        self.last_frame.as_ref()
+    }
+
+    /* --- Helper functions --- */
+    unsafe fn resolve(&mut self, device: &crate::Device, frame_index: usize) {
+        let (frame, query_recorded) = match self.pending_frames[frame_index].take() {
+            Some(pending_frame) => pending_frame,
+            None => return,
+        };
+        let query_pool = self.query_pools.get(frame_index).filter(|_| query_recorded);
+        let pipeline_statistics = match query_pool {
+            Some(query_pool) => {
+                let mut results = [PipelineStatistics::default()];
+
+                // NOT_READY means the frame was never submitted
+                match device.device().get_query_pool_results(
+                    *query_pool,
+                    0,
+                    1,
+                    &mut results,
+                    vk::QueryResultFlags::TYPE_64,
+                ) {
+                    Ok(()) => Some(results[0]),
+                    Err(_) => return,
+                }
+            }
+            None => None,
+        };
+
+        self.last_frame = Some(FrameStats {
+            frame,
+            draws: self.counters[frame_index].stats(),
+            pipeline_statistics,
+        });
+    }
+}
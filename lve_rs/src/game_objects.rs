@@ -3,6 +3,7 @@ use std::{cell::RefCell, collections::HashMap, rc::Rc};
 pub type ObjectId = u32;
 pub type Map = HashMap<ObjectId, GameObject>;
 
+#[derive(Clone, Copy)]
 pub struct TransformComponent {
     pub translation: glm::Vec3,
     pub scale: glm::Vec3,
@@ -19,10 +20,29 @@ pub struct GameObject {
     pub model: Option<Rc<RefCell<crate::Model>>>,
     pub transform: TransformComponent,
     pub point_light: Option<PointLightComponent>,
+    // Transform before the latest fixed update step, see FixedTimestep
+    previous_transform: Option<TransformComponent>,
     id: ObjectId,
 }
 
 impl TransformComponent {
+    // Rotations take the shorter way around, so wrapped angles don't spin
+    pub fn lerp(&self, other: &Self, alpha: f32) -> Self {
+        let rotation = self.rotation.zip_map(&other.rotation, |from, to| {
+            let difference = (to - from + std::f32::consts::PI)
+                .rem_euclid(2.0 * std::f32::consts::PI)
+                - std::f32::consts::PI;
+
+            from + difference * alpha
+        });
+
+        Self {
+            translation: glm::lerp(&self.translation, &other.translation, alpha),
+            scale: glm::lerp(&self.scale, &other.scale, alpha),
+            rotation,
+        }
+    }
+
     pub fn mat4(&self) -> glm::Mat4 {
         let cosine = self
             .rotation
@@ -107,6 +127,7 @@ impl GameObject {
                 ..Default::default()
             },
             point_light: None,
+            previous_transform: None,
         }
     }
 
@@ -137,6 +158,20 @@ impl GameObject {
         game_object
     }
 
+    // Call at the start of every fixed update step, before the transform changes
+    #[inline]
+    pub fn store_previous_transform(&mut self) {
+        self.previous_transform = Some(self.transform);
+    }
+
+    // Between the state before and after the latest step; `alpha` is FixedTimestep::alpha
+    pub fn interpolated_transform(&self, alpha: f32) -> TransformComponent {
+        match &self.previous_transform {
+            Some(previous_transform) => previous_transform.lerp(&self.transform, alpha),
+            None => self.transform,
+        }
+    }
+
     pub const fn id(&self) -> ObjectId {
         self.id
     }
@@ -159,3 +194,52 @@ impl Default for PointLightComponent {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::PI;
+
+    fn rotated(y: f32) -> TransformComponent {
+        TransformComponent {
+            rotation: glm::vec3(0.0, y, 0.0),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn lerps_translation_and_scale() {
+        let from = TransformComponent::default();
+        let to = TransformComponent {
+            translation: glm::vec3(2.0, -4.0, 6.0),
+            scale: glm::vec3(3.0, 3.0, 3.0),
+            ..Default::default()
+        };
+        let halfway = from.lerp(&to, 0.5);
+
+        assert_eq!(halfway.translation, glm::vec3(1.0, -2.0, 3.0));
+        assert_eq!(halfway.scale, glm::vec3(2.0, 2.0, 2.0));
+    }
+
+    #[test]
+    fn lerps_rotation_the_short_way_across_the_wrap() {
+        let halfway = rotated(3.1).lerp(&rotated(-3.1), 0.5);
+        assert!((halfway.rotation.y - PI).abs() < 1e-4);
+
+        let halfway = rotated(-3.1).lerp(&rotated(3.1), 0.5);
+        assert!((halfway.rotation.y + PI).abs() < 1e-4);
+    }
+
+    #[test]
+    fn lerps_rotation_to_the_same_angle_at_the_ends() {
+        let from = rotated(0.25 * PI);
+        let to = rotated(-0.75 * PI);
+
+        assert_eq!(from.lerp(&to, 0.0).rotation.y, from.rotation.y);
+
+        // May land a full turn away from `to`
+        let end = from.lerp(&to, 1.0).rotation.y;
+        assert!((end.sin() - to.rotation.y.sin()).abs() < 1e-5);
+        assert!((end.cos() - to.rotation.y.cos()).abs() < 1e-5);
+    }
+}
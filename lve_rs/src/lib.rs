@@ -1,38 +1,78 @@
+mod application;
+mod assets;
 mod buffer;
 mod camera;
+mod capture;
+mod command_recorder;
+mod compute_pipeline;
 pub mod controller;
 mod debug;
 mod descriptors;
 mod device;
+mod dynamic_rendering;
 pub mod extras;
+mod fixed_timestep;
 mod frame_info;
+mod frame_pacer;
+mod frame_stats;
 mod game_objects;
 mod model;
 mod pipeline;
+mod profiler;
+mod render_graph;
+mod render_target;
 mod renderer;
+mod shader;
+mod specialization;
 mod surface;
 mod swap_chain;
 mod systems;
+mod timeline;
 mod window;
 
 pub use __utils::create_cube_model;
+pub use application::{run, Application, RunConfig};
+pub use assets::{AssetLoader, AssetRoot, EmbeddedArchive, BUILTIN_ASSETS};
 pub use buffer::Buffer;
 pub use camera::Camera;
+pub use capture::CapturedFrame;
+pub use command_recorder::{CommandRecorder, SecondaryInheritance, SecondaryRecorder};
+pub use compute_pipeline::ComputePipeline;
 pub use debug::DebugUtilsMessenger;
 pub use descriptors::{
     DescriptorPool, DescriptorPoolBuilder, DescriptorSetLayout, DescriptorSetLayoutBuilder,
     DescriptorWriter,
 };
 pub use device::{Device, QueryFamilyIndices};
-pub use frame_info::{FrameInfo, GlobalUbo};
+pub use fixed_timestep::FixedTimestep;
+pub use frame_info::{FrameInfo, GlobalUbo, MAX_LIGHT};
+pub use frame_pacer::{FramePacer, FrameTimingStats};
+pub use frame_stats::{
+    DrawCounters, DrawStats, FrameStats, FrameStatsCollector, PipelineStatistics,
+};
 pub use game_objects::{GameObject, Map, ObjectId, TransformComponent};
-pub use model::{Model, Vertex};
-pub use pipeline::Pipeline;
+pub use model::{Model, ModelDraw, Vertex};
+pub use pipeline::{Pipeline, PipelineConfigInfo, ShaderStage};
+pub use profiler::{FrameTimings, GpuProfiler, ScopeId, ScopeTiming};
+pub use render_graph::{
+    ImageDesc, PassBuilder, PassContext, PassKind, RenderGraph, ResourceAccess, ResourceId,
+    TransientImageCache,
+};
+pub use render_target::{RenderTarget, RenderTargetConfig};
 pub use renderer::Renderer;
+pub use shader::{ShaderCompileOptions, ShaderCompiler, ShaderLanguage, ShaderSource};
+pub use specialization::{SpecializationConstants, SpecializationValue};
 pub use surface::{Surface, SwapChainSupportDetails};
-pub use swap_chain::SwapChain;
-pub use systems::{PointLightSystem, SimplePushConstantData, SimpleRenderSystem};
-pub use window::Window;
+pub use swap_chain::{
+    AttachmentOps, ClearValues, HdrMetadata, LoadOp, PresentMode, RenderPassInfo, RenderPassOps,
+    StoreOp, SwapChain, SwapChainConfig,
+};
+pub use systems::{
+    PointLightSystem, RenderSystem, RenderSystemRegistry, SimplePushConstantData,
+    SimpleRenderSystem,
+};
+pub use timeline::TimelineSemaphore;
+pub use window::{FullscreenMode, Window};
 
 extern crate nalgebra_glm as glm;
 
@@ -1,11 +1,13 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
 use ash::vk;
 use offset::offset_of;
 use ordered_float::OrderedFloat;
 use std::{
     collections::HashMap,
     hash::{Hash, Hasher},
+    io::Cursor,
     mem::{size_of, size_of_val},
+    path::Path,
 };
 
 #[derive(Clone, Copy)]
@@ -29,6 +31,16 @@ pub struct Model {
     has_index_buffer: bool,
 }
 
+// Buffer handles and counts of a Model, to record its draw without borrowing it
+//  (e.g. on a worker thread, see SecondaryRecorder::record_parallel)
+#[derive(Debug, Clone, Copy)]
+pub struct ModelDraw {
+    vertex_buffer: vk::Buffer,
+    // None draws `count` vertices instead of indices
+    index_buffer: Option<vk::Buffer>,
+    count: u32,
+}
+
 impl Vertex {
     pub fn new(position: &[f32; 3], color: &[f32; 3]) -> Self {
         Self {
@@ -97,6 +109,34 @@ impl ModelBuilder {
             Err(err) => panic!("{}\n\tFailed to load file: {}", err, filepath),
         };
 
+        Self::from_shapes(&shapes)
+    }
+
+    pub fn load_model_from_assets(&self, assets: &crate::AssetLoader, path: &str) -> Result<Self> {
+        let bytes = assets.read(path)?;
+        let base_dir = Path::new(path).parent().unwrap_or(Path::new(""));
+        let (shapes, materials) = tobj::load_obj_buf(
+            &mut Cursor::new(bytes),
+            &tobj::LoadOptions::default(),
+            |mtl_path| {
+                // Material libraries are looked up next to the .obj in the same asset roots
+                let mtl_bytes = assets
+                    .read(base_dir.join(mtl_path))
+                    .map_err(|_| tobj::LoadError::OpenFileFailed)?;
+
+                tobj::load_mtl_buf(&mut Cursor::new(mtl_bytes))
+            },
+        )
+        .with_context(|| format!("Failed to load model {}", path))?;
+
+        if let Err(err) = materials {
+            eprintln!("Materials not available: {}", err);
+        }
+
+        Ok(Self::from_shapes(&shapes))
+    }
+
+    fn from_shapes(shapes: &[tobj::Model]) -> Self {
         let mut unique_vertices = HashMap::new();
         let mut indices = vec![];
         let mut vertices = vec![];
@@ -199,6 +239,18 @@ impl Model {
         Ok(Box::new(builder.build(device)?))
     }
 
+    pub fn create_model_from_asset(
+        device: &crate::Device,
+        assets: &crate::AssetLoader,
+        path: &str,
+    ) -> Result<Box<Self>> {
+        let builder = Self::builder().load_model_from_assets(assets, path)?;
+
+        println!("Vertex count: {}", builder.vertices.len());
+
+        Ok(Box::new(builder.build(device)?))
+    }
+
     pub fn builder() -> ModelBuilder {
         ModelBuilder {
             vertices: vec![],
@@ -216,33 +268,33 @@ impl Model {
 
     #[inline]
     pub unsafe fn bind(&self, device: &crate::Device, command_buffer: &vk::CommandBuffer) {
-        let device_ref = device.device();
-        let offsets = [0];
+        self.draw_command().bind(device.device(), command_buffer);
+    }
 
-        device_ref.cmd_bind_vertex_buffers(
-            *command_buffer,
-            0,
-            std::slice::from_ref(self.vertex_buffer.buffer()),
-            &offsets,
-        );
+    #[inline]
+    pub fn triangle_count(&self) -> u32 {
         if self.has_index_buffer {
-            device_ref.cmd_bind_index_buffer(
-                *command_buffer,
-                *self.index_buffer.buffer(),
-                0,
-                vk::IndexType::UINT32,
-            )
+            self.index_count / 3
+        } else {
+            self.vertex_count / 3
         }
     }
 
     #[inline]
     pub unsafe fn draw(&self, device: &crate::Device, command_buffer: &vk::CommandBuffer) {
-        let device_ref = device.device();
+        self.draw_command().draw(device.device(), command_buffer);
+    }
 
-        if self.has_index_buffer {
-            device_ref.cmd_draw_indexed(*command_buffer, self.index_count, 1, 0, 0, 0);
-        } else {
-            device_ref.cmd_draw(*command_buffer, self.vertex_count, 1, 0, 0)
+    #[inline]
+    pub fn draw_command(&self) -> ModelDraw {
+        ModelDraw {
+            vertex_buffer: *self.vertex_buffer.buffer(),
+            index_buffer: self.has_index_buffer.then(|| *self.index_buffer.buffer()),
+            count: if self.has_index_buffer {
+                self.index_count
+            } else {
+                self.vertex_count
+            },
         }
     }
 
@@ -331,6 +383,37 @@ impl Model {
     }
 }
 
+impl ModelDraw {
+    #[inline]
+    pub unsafe fn bind(&self, device: &ash::Device, command_buffer: &vk::CommandBuffer) {
+        let offsets = [0];
+
+        device.cmd_bind_vertex_buffers(
+            *command_buffer,
+            0,
+            std::slice::from_ref(&self.vertex_buffer),
+            &offsets,
+        );
+        if let Some(index_buffer) = self.index_buffer {
+            device.cmd_bind_index_buffer(*command_buffer, index_buffer, 0, vk::IndexType::UINT32)
+        }
+    }
+
+    #[inline]
+    pub unsafe fn draw(&self, device: &ash::Device, command_buffer: &vk::CommandBuffer) {
+        if self.index_buffer.is_some() {
+            device.cmd_draw_indexed(*command_buffer, self.count, 1, 0, 0, 0);
+        } else {
+            device.cmd_draw(*command_buffer, self.count, 1, 0, 0)
+        }
+    }
+
+    #[inline]
+    pub fn triangle_count(&self) -> u32 {
+        self.count / 3
+    }
+}
+
 impl Default for Vertex {
     fn default() -> Self {
         Self {
@@ -1,7 +1,7 @@
 use crate as lve_rs;
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use ash::vk;
-use std::{ffi::CStr, fs::File};
+use std::ffi::CString;
 
 /* MEMO
  *  In the Vulkan Tutorial video, a reference to lve_rs::Device is passed but
@@ -23,28 +23,115 @@ pub struct PipelineConfigInfo {
     pub pipeline_layout: vk::PipelineLayout,
     pub render_pass: vk::RenderPass,
     pub subpass: u32,
+    // Only used with dynamic rendering, i.e. when render_pass is null
+    pub color_attachment_format: vk::Format,
+    pub depth_attachment_format: vk::Format,
+    pub tessellation_info: vk::PipelineTessellationStateCreateInfo,
+}
+
+pub struct ShaderStage {
+    pub stage: vk::ShaderStageFlags,
+    pub source: lve_rs::ShaderSource,
+    pub entry_point: CString,
+    pub specialization_constants: lve_rs::SpecializationConstants,
 }
 
 pub struct Pipeline {
     graphics_pipeline: vk::Pipeline,
-    vert_shader_module: vk::ShaderModule,
-    frag_shader_module: vk::ShaderModule,
+    shader_modules: Vec<vk::ShaderModule>,
+}
+
+impl PipelineConfigInfo {
+    // Targets the given render pass (or dynamic rendering formats) and matches its sample count
+    pub fn set_render_pass_info(&mut self, render_pass_info: &lve_rs::RenderPassInfo) {
+        self.render_pass = render_pass_info.render_pass;
+        self.color_attachment_format = render_pass_info.color_format;
+        self.depth_attachment_format = render_pass_info.depth_format;
+        self.multisample_info.rasterization_samples = render_pass_info.samples;
+    }
+}
+
+impl ShaderStage {
+    pub fn new(stage: vk::ShaderStageFlags, source: lve_rs::ShaderSource) -> Self {
+        Self {
+            stage,
+            source,
+            entry_point: CString::new("main").unwrap(),
+            specialization_constants: lve_rs::SpecializationConstants::new(),
+        }
+    }
+
+    #[inline]
+    pub fn vertex(source: lve_rs::ShaderSource) -> Self {
+        Self::new(vk::ShaderStageFlags::VERTEX, source)
+    }
+
+    #[inline]
+    pub fn fragment(source: lve_rs::ShaderSource) -> Self {
+        Self::new(vk::ShaderStageFlags::FRAGMENT, source)
+    }
+
+    #[inline]
+    pub fn geometry(source: lve_rs::ShaderSource) -> Self {
+        Self::new(vk::ShaderStageFlags::GEOMETRY, source)
+    }
+
+    #[inline]
+    pub fn tessellation_control(source: lve_rs::ShaderSource) -> Self {
+        Self::new(vk::ShaderStageFlags::TESSELLATION_CONTROL, source)
+    }
+
+    #[inline]
+    pub fn tessellation_evaluation(source: lve_rs::ShaderSource) -> Self {
+        Self::new(vk::ShaderStageFlags::TESSELLATION_EVALUATION, source)
+    }
+
+    #[inline]
+    pub fn mesh(source: lve_rs::ShaderSource) -> Self {
+        Self::new(vk::ShaderStageFlags::MESH_EXT, source)
+    }
+
+    #[inline]
+    pub fn task(source: lve_rs::ShaderSource) -> Self {
+        Self::new(vk::ShaderStageFlags::TASK_EXT, source)
+    }
+
+    pub fn entry_point_name(self, entry_point: &str) -> Result<Self> {
+        Ok(Self {
+            entry_point: CString::new(entry_point)?,
+            ..self
+        })
+    }
+
+    pub fn specialization_constants(
+        self,
+        specialization_constants: lve_rs::SpecializationConstants,
+    ) -> Self {
+        Self {
+            specialization_constants,
+            ..self
+        }
+    }
+
+    #[inline]
+    pub fn entry_point(&self) -> &str {
+        // Constructed from a &str, so this can never fail
+        self.entry_point.to_str().unwrap()
+    }
 }
 
 impl Pipeline {
     pub fn new(
         device: &lve_rs::Device,
-        vert_file_path: &str,
-        frag_file_path: &str,
+        stages: &[ShaderStage],
         config_info: &PipelineConfigInfo,
     ) -> Result<Self> {
-        let (graphics_pipeline, vert_shader_module, frag_shader_module) =
-            Self::create_graphics_pipeline(device, vert_file_path, frag_file_path, config_info)?;
+        let (graphics_pipeline, shader_modules) =
+            Self::create_graphics_pipeline(device, stages, config_info)?;
 
         Ok(Self {
             graphics_pipeline,
-            vert_shader_module,
-            frag_shader_module,
+            shader_modules,
         })
     }
 
@@ -57,6 +144,11 @@ impl Pipeline {
         )
     }
 
+    #[inline]
+    pub fn graphics_pipeline(&self) -> vk::Pipeline {
+        self.graphics_pipeline
+    }
+
     pub fn default_pipeline_config_info() -> PipelineConfigInfo {
         PipelineConfigInfo {
             binding_descriptions: crate::Vertex::binding_descriptions(),
@@ -115,6 +207,11 @@ impl Pipeline {
             pipeline_layout: vk::PipelineLayout::null(),
             render_pass: vk::RenderPass::null(),
             subpass: 0,
+            color_attachment_format: vk::Format::UNDEFINED,
+            depth_attachment_format: vk::Format::UNDEFINED,
+            tessellation_info: vk::PipelineTessellationStateCreateInfo::builder()
+                .patch_control_points(3)
+                .build(),
         }
     }
 
@@ -176,52 +273,85 @@ impl Pipeline {
             pipeline_layout: vk::PipelineLayout::null(),
             render_pass: vk::RenderPass::null(),
             subpass: 0,
+            color_attachment_format: vk::Format::UNDEFINED,
+            depth_attachment_format: vk::Format::UNDEFINED,
+            tessellation_info: vk::PipelineTessellationStateCreateInfo::builder()
+                .patch_control_points(3)
+                .build(),
         }
     }
 
-    fn read_file(file_path: &str) -> Result<File> {
-        Ok(File::open(file_path)?)
-    }
-
     /* --- Helper functions --- */
     fn create_graphics_pipeline(
         device: &lve_rs::Device,
-        vert_file_path: &str,
-        frag_file_path: &str,
+        stages: &[ShaderStage],
         config_info: &PipelineConfigInfo,
-    ) -> Result<(vk::Pipeline, vk::ShaderModule, vk::ShaderModule)> {
+    ) -> Result<(vk::Pipeline, Vec<vk::ShaderModule>)> {
         assert!(
             config_info.pipeline_layout != vk::PipelineLayout::null(),
             "Cannot create graphics pipeline: No pipeline_layout provided in config_info"
         );
+        let dynamic_rendering = config_info.render_pass == vk::RenderPass::null();
+
         assert!(
-            config_info.render_pass != vk::RenderPass::null(),
-            "Cannot create graphics pipeline: No render_pass provided in config_info"
+            !dynamic_rendering
+                || config_info.color_attachment_format != vk::Format::UNDEFINED
+                || config_info.depth_attachment_format != vk::Format::UNDEFINED,
+            "Cannot create graphics pipeline: No render_pass or attachment formats provided in config_info"
         );
+        if dynamic_rendering && !device.supports_dynamic_rendering() {
+            bail!("Dynamic rendering is not supported by this device");
+        }
+        Self::validate_stages(device, stages)?;
 
-        let vert_shader_module = {
-            let mut vert_code = Self::read_file(vert_file_path)?;
+        let mut shader_modules = Vec::with_capacity(stages.len());
 
-            Self::create_shader_module(device, &mut vert_code)?
-        };
-        let frag_shader_module = {
-            let mut frag_code = Self::read_file(frag_file_path)?;
+        for stage in stages.iter() {
+            match stage
+                .source
+                .load(stage.stage, stage.entry_point())
+                .and_then(|code| Self::create_shader_module(device, &code))
+            {
+                Ok(shader_module) => shader_modules.push(shader_module),
+                Err(e) => {
+                    unsafe { Self::destroy_shader_modules(device, &mut shader_modules) };
+
+                    return Err(e.context(format!(
+                        "Failed to create {:?} shader module from {}",
+                        stage.stage,
+                        stage.source.name()
+                    )));
+                }
+            }
+        }
 
-            Self::create_shader_module(device, &mut frag_code)?
-        };
         let graphics_pipeline = {
-            let shader_stages = [
-                vk::PipelineShaderStageCreateInfo::builder()
-                    .stage(vk::ShaderStageFlags::VERTEX)
-                    .module(vert_shader_module)
-                    .name(unsafe { CStr::from_bytes_with_nul_unchecked(b"main\0") })
-                    .build(),
-                vk::PipelineShaderStageCreateInfo::builder()
-                    .stage(vk::ShaderStageFlags::FRAGMENT)
-                    .module(frag_shader_module)
-                    .name(unsafe { CStr::from_bytes_with_nul_unchecked(b"main\0") })
-                    .build(),
-            ];
+            let specialization_data = stages
+                .iter()
+                .map(|stage| stage.specialization_constants.map_entries_and_data())
+                .collect::<Vec<_>>();
+            let specialization_infos = specialization_data
+                .iter()
+                .map(|(map_entries, data)| {
+                    vk::SpecializationInfo::builder()
+                        .map_entries(map_entries)
+                        .data(data)
+                        .build()
+                })
+                .collect::<Vec<_>>();
+            let shader_stages = stages
+                .iter()
+                .zip(shader_modules.iter())
+                .zip(specialization_infos.iter())
+                .map(|((stage, shader_module), specialization_info)| {
+                    vk::PipelineShaderStageCreateInfo::builder()
+                        .stage(stage.stage)
+                        .module(*shader_module)
+                        .name(&stage.entry_point)
+                        .specialization_info(specialization_info)
+                        .build()
+                })
+                .collect::<Vec<_>>();
             let binding_descriptions = &config_info.binding_descriptions;
             let attribute_descriptions = &config_info.attribute_descriptions;
             let vertex_input_info = vk::PipelineVertexInputStateCreateInfo::builder()
@@ -236,10 +366,20 @@ impl Pipeline {
                 .dynamic_states(&config_info.dynamic_state_enables)
                 .flags(vk::PipelineDynamicStateCreateFlags::empty())
                 .build();
-            let create_info = vk::GraphicsPipelineCreateInfo::builder()
+            let color_attachment_formats =
+                if config_info.color_attachment_format == vk::Format::UNDEFINED {
+                    vec![]
+                } else {
+                    vec![config_info.color_attachment_format]
+                };
+            let mut rendering_info = vk::PipelineRenderingCreateInfo::builder()
+                .color_attachment_formats(&color_attachment_formats)
+                .depth_attachment_format(config_info.depth_attachment_format);
+            let has_stage = |flags: vk::ShaderStageFlags| {
+                stages.iter().any(|stage| flags.contains(stage.stage))
+            };
+            let mut create_info = vk::GraphicsPipelineCreateInfo::builder()
                 .stages(&shader_stages)
-                .vertex_input_state(&vertex_input_info)
-                .input_assembly_state(&config_info.input_assembly_info)
                 .viewport_state(&config_info.viewport_info)
                 .rasterization_state(&config_info.rasterization_info)
                 .multisample_state(&config_info.multisample_info)
@@ -252,6 +392,22 @@ impl Pipeline {
                 .base_pipeline_index(-1)
                 .base_pipeline_handle(vk::Pipeline::null());
 
+            if dynamic_rendering {
+                create_info = create_info.push_next(&mut rendering_info);
+            }
+            // Mesh shading pipelines have no vertex input or input assembly stage
+            if !has_stage(vk::ShaderStageFlags::MESH_EXT) {
+                create_info = create_info
+                    .vertex_input_state(&vertex_input_info)
+                    .input_assembly_state(&config_info.input_assembly_info);
+            }
+            if has_stage(
+                vk::ShaderStageFlags::TESSELLATION_CONTROL
+                    | vk::ShaderStageFlags::TESSELLATION_EVALUATION,
+            ) {
+                create_info = create_info.tessellation_state(&config_info.tessellation_info);
+            }
+
             match unsafe {
                 device.device().create_graphics_pipelines(
                     vk::PipelineCache::null(),
@@ -260,31 +416,79 @@ impl Pipeline {
                 )
             } {
                 Ok(pipelines) => Ok(pipelines),
-                Err((_, e)) => Err(e),
+                Err((_, e)) => {
+                    unsafe { Self::destroy_shader_modules(device, &mut shader_modules) };
+
+                    Err(e)
+                }
             }?
         }
         .into_iter()
         .next()
         .context("Failed to create graphics pipeline")?;
 
-        Ok((graphics_pipeline, vert_shader_module, frag_shader_module))
+        Ok((graphics_pipeline, shader_modules))
+    }
+
+    fn validate_stages(device: &lve_rs::Device, stages: &[ShaderStage]) -> Result<()> {
+        let count = |flags: vk::ShaderStageFlags| {
+            stages.iter().filter(|stage| stage.stage == flags).count()
+        };
+
+        for stage in stages.iter() {
+            if count(stage.stage) > 1 {
+                bail!("Shader stage {:?} was given more than once", stage.stage);
+            }
+        }
+        if count(vk::ShaderStageFlags::VERTEX) + count(vk::ShaderStageFlags::MESH_EXT) != 1 {
+            bail!("Graphics pipeline requires exactly one vertex or mesh shader stage");
+        }
+        if count(vk::ShaderStageFlags::TESSELLATION_CONTROL)
+            != count(vk::ShaderStageFlags::TESSELLATION_EVALUATION)
+        {
+            bail!("Tessellation control and evaluation stages must be given together");
+        }
+        if count(vk::ShaderStageFlags::COMPUTE) > 0 {
+            bail!("Compute stages belong in a ComputePipeline");
+        }
+        if count(vk::ShaderStageFlags::GEOMETRY) > 0
+            && device.enabled_features().geometry_shader == 0
+        {
+            bail!("Geometry shaders are not supported by this device");
+        }
+        if count(vk::ShaderStageFlags::TESSELLATION_CONTROL) > 0
+            && device.enabled_features().tessellation_shader == 0
+        {
+            bail!("Tessellation shaders are not supported by this device");
+        }
+        if count(vk::ShaderStageFlags::MESH_EXT) + count(vk::ShaderStageFlags::TASK_EXT) > 0
+            && device.mesh_shader().is_none()
+        {
+            bail!("Mesh shaders are not supported by this device");
+        }
+
+        Ok(())
     }
 
     pub unsafe fn destroy(&mut self, device: &crate::Device) {
-        device
-            .device()
-            .destroy_shader_module(self.frag_shader_module, None);
-        device
-            .device()
-            .destroy_shader_module(self.vert_shader_module, None);
+        Self::destroy_shader_modules(device, &mut self.shader_modules);
         device
             .device()
             .destroy_pipeline(self.graphics_pipeline, None);
     }
 
-    fn create_shader_module(device: &lve_rs::Device, code: &mut File) -> Result<vk::ShaderModule> {
-        let spv_code = ash::util::read_spv(code)?;
-        let create_info = vk::ShaderModuleCreateInfo::builder().code(&spv_code);
+    unsafe fn destroy_shader_modules(
+        device: &lve_rs::Device,
+        shader_modules: &mut Vec<vk::ShaderModule>,
+    ) {
+        shader_modules.iter().for_each(|shader_module| {
+            device.device().destroy_shader_module(*shader_module, None);
+        });
+        shader_modules.clear();
+    }
+
+    fn create_shader_module(device: &lve_rs::Device, code: &[u32]) -> Result<vk::ShaderModule> {
+        let create_info = vk::ShaderModuleCreateInfo::builder().code(code);
         let shader_module = unsafe { device.device().create_shader_module(&create_info, None) }?;
 
         Ok(shader_module)
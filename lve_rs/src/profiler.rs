@@ -0,0 +1,402 @@
+use anyhow::{bail, Context, Result};
+use ash::vk;
+use std::{
+    collections::VecDeque,
+    fmt::Write as _,
+    fs,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+// Returned by begin_scope and handed back to end_scope
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScopeId(Option<usize>);
+
+#[derive(Debug, Clone)]
+pub struct ScopeTiming {
+    pub name: String,
+    // Number of enclosing scopes
+    pub depth: u32,
+    // Relative to the start of the frame's first scope
+    pub start_ms: f64,
+    pub duration_ms: f64,
+}
+
+#[derive(Debug, Clone)]
+pub struct FrameTimings {
+    pub frame: u64,
+    // Relative to the first frame the profiler resolved
+    pub start_ms: f64,
+    pub scopes: Vec<ScopeTiming>,
+}
+
+/* MEMO
+ *  Each frame in flight owns a query pool. begin_frame reads back what that pool
+ *  recorded the last time the frame index was used, which the renderer's timeline
+ *  wait in begin_frame has already made available, so reading never stalls.
+ *  Results therefore lag `frames_in_flight` frames behind.
+ *
+ *  profiler.begin_frame(device, &command_buffer, frame_index);
+ *  let scope = profiler.begin_scope(device, &command_buffer, "simple render system");
+ *  ...
+ *  profiler.end_scope(device, &command_buffer, scope);
+ */
+pub struct GpuProfiler {
+    query_pools: Vec<vk::QueryPool>,
+    frames: Vec<PendingFrame>,
+    max_scopes: usize,
+    timestamp_period: f64,
+    timestamp_mask: u64,
+    current_frame_index: Option<usize>,
+    frame_count: u64,
+    open_scopes: u32,
+    origin: Option<u64>,
+    history: VecDeque<FrameTimings>,
+    history_len: usize,
+}
+
+#[derive(Default)]
+struct PendingFrame {
+    frame: u64,
+    scopes: Vec<PendingScope>,
+}
+
+struct PendingScope {
+    name: String,
+    depth: u32,
+    ended: bool,
+}
+
+impl GpuProfiler {
+    pub const DEFAULT_MAX_SCOPES: usize = 64;
+    pub const DEFAULT_HISTORY_LEN: usize = 600;
+
+    pub fn new(device: &crate::Device, frames_in_flight: usize) -> Result<Self> {
+        Self::with_max_scopes(device, frames_in_flight, Self::DEFAULT_MAX_SCOPES)
+    }
+
+    pub fn with_max_scopes(
+        device: &crate::Device,
+        frames_in_flight: usize,
+        max_scopes: usize,
+    ) -> Result<Self> {
+        let timestamp_valid_bits = device.timestamp_valid_bits()?;
+        let timestamp_period = device.properties.limits.timestamp_period as f64;
+
+        if timestamp_valid_bits == 0 || timestamp_period <= 0.0 {
+            bail!("The graphics queue does not support timestamps");
+        }
+
+        let create_info = vk::QueryPoolCreateInfo::builder()
+            .query_type(vk::QueryType::TIMESTAMP)
+            .query_count((max_scopes * 2) as u32);
+        let mut query_pools = Vec::with_capacity(frames_in_flight);
+
+        for _ in 0..frames_in_flight {
+            match unsafe { device.device().create_query_pool(&create_info, None) } {
+                Ok(query_pool) => query_pools.push(query_pool),
+                Err(err) => {
+                    for query_pool in query_pools {
+                        unsafe { device.device().destroy_query_pool(query_pool, None) };
+                    }
+                    bail!("Failed to create timestamp query pool: {}", err);
+                }
+            }
+        }
+
+        Ok(Self {
+            query_pools,
+            frames: (0..frames_in_flight)
+                .map(|_| PendingFrame::default())
+                .collect(),
+            max_scopes,
+            timestamp_period,
+            timestamp_mask: if timestamp_valid_bits >= 64 {
+                u64::MAX
+            } else {
+                (1u64 << timestamp_valid_bits) - 1
+            },
+            current_frame_index: None,
+            frame_count: 0,
+            open_scopes: 0,
+            origin: None,
+            history: VecDeque::new(),
+            history_len: Self::DEFAULT_HISTORY_LEN,
+        })
+    }
+
+    pub unsafe fn destroy(&mut self, device: &crate::Device) {
+        for query_pool in self.query_pools.drain(..) {
+            device.device().destroy_query_pool(query_pool, None);
+        }
+    }
+
+    // Number of resolved frames kept for write_csv/write_chrome_trace
+    pub fn set_history_len(&mut self, history_len: usize) {
+        self.history_len = history_len;
+        while self.history.len() > history_len {
+            self.history.pop_front();
+        }
+    }
+
+    // Call right after Renderer::begin_frame, outside of any render pass
+    pub unsafe fn begin_frame(
+        &mut self,
+        device: &crate::Device,
+        command_buffer: &vk::CommandBuffer,
+        frame_index: usize,
+    ) {
+        self.resolve(device, frame_index);
+
+        device.device().cmd_reset_query_pool(
+            *command_buffer,
+            self.query_pools[frame_index],
+            0,
+            (self.max_scopes * 2) as u32,
+        );
+        self.frames[frame_index] = PendingFrame {
+            frame: self.frame_count,
+            scopes: vec![],
+        };
+        self.current_frame_index = Some(frame_index);
+        self.frame_count += 1;
+        self.open_scopes = 0;
+    }
+
+    // Scopes beyond the pool's capacity are silently left out
+    pub unsafe fn begin_scope(
+        &mut self,
+        device: &crate::Device,
+        command_buffer: &vk::CommandBuffer,
+        name: &str,
+    ) -> ScopeId {
+        let frame_index = self
+            .current_frame_index
+            .expect("begin_scope called before begin_frame");
+        let frame = &mut self.frames[frame_index];
+
+        if frame.scopes.len() >= self.max_scopes {
+            return ScopeId(None);
+        }
+
+        let index = frame.scopes.len();
+
+        device.device().cmd_write_timestamp(
+            *command_buffer,
+            vk::PipelineStageFlags::TOP_OF_PIPE,
+            self.query_pools[frame_index],
+            (index * 2) as u32,
+        );
+        frame.scopes.push(PendingScope {
+            name: name.to_string(),
+            depth: self.open_scopes,
+            ended: false,
+        });
+        self.open_scopes += 1;
+
+        ScopeId(Some(index))
+    }
+
+    pub unsafe fn end_scope(
+        &mut self,
+        device: &crate::Device,
+        command_buffer: &vk::CommandBuffer,
+        scope: ScopeId,
+    ) {
+        let frame_index = self
+            .current_frame_index
+            .expect("end_scope called before begin_frame");
+
+        self.open_scopes = self.open_scopes.saturating_sub(1);
+        if let Some(index) = scope.0 {
+            device.device().cmd_write_timestamp(
+                *command_buffer,
+                vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+                self.query_pools[frame_index],
+                (index * 2 + 1) as u32,
+            );
+            self.frames[frame_index].scopes[index].ended = true;
+        }
+    }
+
+    // Wraps `f` in a scope named `name`
+    pub unsafe fn scope<R, F: FnOnce() -> R>(
+        &mut self,
+        device: &crate::Device,
+        command_buffer: &vk::CommandBuffer,
+        name: &str,
+        f: F,
+    ) -> R {
+        let scope = self.begin_scope(device, command_buffer, name);
+        let result = f();
+
+        self.end_scope(device, command_buffer, scope);
+
+        result
+    }
+
+    // Picks up the frames still in flight; the device must be idle
+    pub unsafe fn resolve_pending(&mut self, device: &crate::Device) {
+        let mut frame_indices = (0..self.frames.len()).collect::<Vec<_>>();
+
+        frame_indices.sort_by_key(|&frame_index| self.frames[frame_index].frame);
+        for frame_index in frame_indices {
+            self.resolve(device, frame_index);
+        }
+        self.current_frame_index = None;
+    }
+
+    // Most recently resolved frame
+    #[inline]
+    pub fn last_frame(&self) -> Option<&FrameTimings> {
+        self.history.back()
+    }
+
+    #[inline]
+    pub fn history(&self) -> impl Iterator<Item = &FrameTimings> {
+        self.history.iter()
+    }
+
+    // Milliseconds of the first scope called `name` in the most recently resolved frame
+    pub fn scope_ms(&self, name: &str) -> Option<f64> {
+        self.last_frame()?
+            .scopes
+            .iter()
+            .find(|scope| scope.name == name)
+            .map(|scope| scope.duration_ms)
+    }
+
+    // One row per scope: frame,scope,depth,start_ms,duration_ms
+    pub fn write_csv<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let file = fs::File::create(path)
+            .with_context(|| format!("Failed to create profile {:?}", path))?;
+        let mut writer = BufWriter::new(file);
+
+        writeln!(writer, "frame,scope,depth,start_ms,duration_ms")?;
+        for frame in self.history.iter() {
+            for scope in frame.scopes.iter() {
+                writeln!(
+                    writer,
+                    "{},\"{}\",{},{:.6},{:.6}",
+                    frame.frame,
+                    scope.name.replace('"', "\"\""),
+                    scope.depth,
+                    frame.start_ms + scope.start_ms,
+                    scope.duration_ms
+                )?;
+            }
+        }
+        writer.flush()?;
+
+        Ok(())
+    }
+
+    // Trace Event Format, viewable in chrome://tracing or Perfetto
+    pub fn write_chrome_trace<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let mut events = vec![];
+
+        for frame in self.history.iter() {
+            for scope in frame.scopes.iter() {
+                let mut name = String::new();
+
+                for c in scope.name.chars() {
+                    match c {
+                        '"' => name.push_str("\\\""),
+                        '\\' => name.push_str("\\\\"),
+                        c if c.is_control() => write!(name, "\\u{:04x}", c as u32)?,
+                        c => name.push(c),
+                    }
+                }
+                events.push(format!(
+                    "{{\"name\":\"{}\",\"cat\":\"gpu\",\"ph\":\"X\",\"pid\":0,\"tid\":0,\"ts\":{:.3},\"dur\":{:.3},\"args\":{{\"frame\":{}}}}}",
+                    name,
+                    (frame.start_ms + scope.start_ms) * 1000.0,
+                    scope.duration_ms * 1000.0,
+                    frame.frame
+                ));
+            }
+        }
+
+        fs::write(
+            path,
+            format!("{{\"traceEvents\":[\n{}\n]}}\n", events.join(",\n")),
+        )
+        .with_context(|| format!("Failed to write profile {:?}", path))
+    }
+
+    // Format is picked from the extension: .csv, or a Chrome trace otherwise
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("csv") => self.write_csv(path),
+            _ => self.write_chrome_trace(path),
+        }
+    }
+
+    /* --- Helper functions --- */
+    unsafe fn resolve(&mut self, device: &crate::Device, frame_index: usize) {
+        let frame = std::mem::take(&mut self.frames[frame_index]);
+
+        if frame.scopes.is_empty() {
+            return;
+        }
+
+        // Timestamp and availability of each query
+        let mut timestamps = vec![[0u64; 2]; frame.scopes.len() * 2];
+
+        // NOT_READY only means some queries weren't written, e.g. a scope that was
+        // never ended; the available ones are still filled in
+        match device.device().get_query_pool_results(
+            self.query_pools[frame_index],
+            0,
+            timestamps.len() as u32,
+            &mut timestamps,
+            vk::QueryResultFlags::TYPE_64 | vk::QueryResultFlags::WITH_AVAILABILITY,
+        ) {
+            Ok(()) | Err(vk::Result::NOT_READY) => (),
+            Err(_) => return,
+        }
+
+        let timestamp = |query: usize| {
+            let [ticks, available] = timestamps[query];
+
+            (available != 0).then_some(ticks & self.timestamp_mask)
+        };
+        // The first scope's begin is missing only if the frame was never submitted
+        let frame_start = match timestamp(0) {
+            Some(frame_start) => frame_start,
+            None => return,
+        };
+        let origin = *self.origin.get_or_insert(frame_start);
+        let to_ms = |ticks: u64| ticks as f64 * self.timestamp_period / 1_000_000.0;
+        let scopes = frame
+            .scopes
+            .into_iter()
+            .enumerate()
+            .filter(|(_, scope)| scope.ended)
+            .filter_map(|(index, scope)| {
+                let begin = timestamp(index * 2)?;
+                let end = timestamp(index * 2 + 1)?;
+
+                Some(ScopeTiming {
+                    name: scope.name,
+                    depth: scope.depth,
+                    start_ms: to_ms(begin.wrapping_sub(frame_start) & self.timestamp_mask),
+                    duration_ms: to_ms(end.wrapping_sub(begin) & self.timestamp_mask),
+                })
+            })
+            .collect();
+
+        self.history.push_back(FrameTimings {
+            frame: frame.frame,
+            start_ms: to_ms(frame_start.wrapping_sub(origin) & self.timestamp_mask),
+            scopes,
+        });
+        while self.history.len() > self.history_len {
+            self.history.pop_front();
+        }
+    }
+}
@@ -0,0 +1,1839 @@
+use anyhow::{bail, Result};
+use ash::vk;
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap},
+    fmt::Write,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ResourceId(usize);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ImageDesc {
+    pub format: vk::Format,
+    pub extent: vk::Extent2D,
+    pub samples: vk::SampleCountFlags,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PassKind {
+    Graphics,
+    Compute,
+    Transfer,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResourceAccess {
+    ColorAttachment,
+    DepthAttachment,
+    // Depth testing without depth writes
+    DepthAttachmentReadOnly,
+    Sampled,
+    StorageImageRead,
+    StorageImageWrite,
+    TransferSrc,
+    TransferDst,
+    UniformBuffer,
+    VertexBuffer,
+    IndexBuffer,
+    IndirectBuffer,
+    StorageBufferRead,
+    StorageBufferWrite,
+}
+
+// Handed to each pass while it records its commands
+pub struct PassContext<'c> {
+    device: &'c crate::Device,
+    command_buffer: vk::CommandBuffer,
+    extent: vk::Extent2D,
+    physical: &'c [PhysicalResource],
+}
+
+pub struct PassBuilder<'g, 'a> {
+    graph: &'g mut RenderGraph<'a>,
+    pass: Pass<'a>,
+}
+
+/* MEMO
+ *  A RenderGraph is built every frame. Passes declare what they read and write
+ *  and the graph takes care of the rest when it is executed:
+ *   - Passes run after every pass writing a resource they read. Passes writing
+ *     the same resource keep their declaration order. Each write should therefore
+ *     go to its own resource rather than ping-ponging a single one.
+ *   - Passes that contribute neither to an imported resource nor have side
+ *     effects are culled.
+ *   - Transient images with the same ImageDesc whose lifetimes don't overlap
+ *     reuse one physical image. Physical images whose lifetimes don't overlap
+ *     alias each other's memory: they are bound to offsets of shared
+ *     allocations, placed by their memory requirements. The images and the
+ *     memory live in a TransientImageCache across frames.
+ *   - Barriers and layout transitions are inserted between passes. Imported
+ *     images end in the layout they were imported with.
+ *  Graphics passes with attachments are recorded with dynamic rendering, so
+ *  their pipelines must be created from RenderPassInfo with a null render pass.
+ */
+pub struct RenderGraph<'a> {
+    resources: Vec<Resource>,
+    passes: Vec<Pass<'a>>,
+}
+
+// Physical images for transient resources, kept per frame in flight
+pub struct TransientImageCache {
+    frames: Vec<Option<TransientFrame>>,
+}
+
+#[derive(Clone, Copy)]
+enum PhysicalResource {
+    Image(vk::Image, vk::ImageView),
+    Buffer(vk::Buffer),
+}
+
+enum ResourceKind {
+    Transient(ImageDesc),
+    Image {
+        image: vk::Image,
+        view: vk::ImageView,
+        desc: ImageDesc,
+        initial_layout: vk::ImageLayout,
+        final_layout: vk::ImageLayout,
+    },
+    Buffer(vk::Buffer),
+}
+
+struct Resource {
+    name: String,
+    kind: ResourceKind,
+}
+
+struct ColorAttachment {
+    resource: ResourceId,
+    clear: Option<[f32; 4]>,
+    resolve: Option<ResourceId>,
+}
+
+struct DepthAttachment {
+    resource: ResourceId,
+    clear: Option<f32>,
+    read_only: bool,
+}
+
+struct Pass<'a> {
+    name: String,
+    kind: PassKind,
+    accesses: Vec<(ResourceId, ResourceAccess)>,
+    // Attachments that are loaded rather than cleared, i.e. read before written
+    loads: Vec<ResourceId>,
+    color_attachments: Vec<ColorAttachment>,
+    depth_attachment: Option<DepthAttachment>,
+    side_effects: bool,
+    execute: Option<Box<dyn FnOnce(&PassContext) -> Result<()> + 'a>>,
+}
+
+#[derive(Clone, Copy)]
+struct ResourceState {
+    stage: vk::PipelineStageFlags,
+    access: vk::AccessFlags,
+    layout: vk::ImageLayout,
+}
+
+// Result of ordering, culling and image reuse, before anything is recorded
+struct GraphLayout {
+    order: Vec<usize>,
+    culled: Vec<usize>,
+    // First and last position in `order` that uses each resource
+    lifetimes: Vec<Option<(usize, usize)>>,
+    // Physical image slot of each transient resource
+    slots: Vec<Option<usize>>,
+    slot_descs: Vec<(ImageDesc, vk::ImageUsageFlags)>,
+    // First and last position in `order` of each slot's users
+    slot_lifetimes: Vec<(usize, usize)>,
+}
+
+// Images of the slots of one frame index, and the memory they alias
+struct TransientFrame {
+    slot_descs: Vec<(ImageDesc, vk::ImageUsageFlags)>,
+    slot_lifetimes: Vec<(usize, usize)>,
+    images: Vec<(vk::Image, vk::ImageView)>,
+    memory: Vec<vk::DeviceMemory>,
+    // Earlier slots whose memory each slot takes over
+    aliases: Vec<Vec<usize>>,
+}
+
+// Where each image is bound; images alive at the same time never share bytes
+struct MemoryPlan {
+    // Memory type and size of each allocation
+    blocks: Vec<(u32, vk::DeviceSize)>,
+    // Allocation and offset of each image
+    placements: Vec<(usize, vk::DeviceSize)>,
+    // Earlier images (by lifetime) whose memory each image overlaps
+    aliases: Vec<Vec<usize>>,
+}
+
+impl ImageDesc {
+    pub fn new(format: vk::Format, width: u32, height: u32) -> Self {
+        Self {
+            format,
+            extent: vk::Extent2D { width, height },
+            samples: vk::SampleCountFlags::TYPE_1,
+        }
+    }
+
+    pub fn samples(&self, samples: vk::SampleCountFlags) -> Self {
+        Self { samples, ..*self }
+    }
+
+    #[inline]
+    fn is_depth(&self) -> bool {
+        matches!(
+            self.format,
+            vk::Format::D16_UNORM
+                | vk::Format::X8_D24_UNORM_PACK32
+                | vk::Format::D32_SFLOAT
+                | vk::Format::D16_UNORM_S8_UINT
+                | vk::Format::D24_UNORM_S8_UINT
+                | vk::Format::D32_SFLOAT_S8_UINT
+        )
+    }
+
+    fn aspect_mask(&self) -> vk::ImageAspectFlags {
+        if self.is_depth() {
+            crate::dynamic_rendering::depth_aspect_mask(self.format)
+        } else {
+            vk::ImageAspectFlags::COLOR
+        }
+    }
+}
+
+impl ResourceAccess {
+    pub fn is_write(&self) -> bool {
+        matches!(
+            self,
+            Self::ColorAttachment
+                | Self::DepthAttachment
+                | Self::StorageImageWrite
+                | Self::TransferDst
+                | Self::StorageBufferWrite
+        )
+    }
+
+    pub fn is_attachment(&self) -> bool {
+        matches!(
+            self,
+            Self::ColorAttachment | Self::DepthAttachment | Self::DepthAttachmentReadOnly
+        )
+    }
+
+    fn image_usage(&self) -> vk::ImageUsageFlags {
+        match self {
+            Self::ColorAttachment => vk::ImageUsageFlags::COLOR_ATTACHMENT,
+            Self::DepthAttachment | Self::DepthAttachmentReadOnly => {
+                vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT
+            }
+            Self::Sampled => vk::ImageUsageFlags::SAMPLED,
+            Self::StorageImageRead | Self::StorageImageWrite => vk::ImageUsageFlags::STORAGE,
+            Self::TransferSrc => vk::ImageUsageFlags::TRANSFER_SRC,
+            Self::TransferDst => vk::ImageUsageFlags::TRANSFER_DST,
+            _ => vk::ImageUsageFlags::empty(),
+        }
+    }
+
+    fn state(&self, kind: PassKind, desc: Option<&ImageDesc>) -> ResourceState {
+        let shader_stages = match kind {
+            PassKind::Graphics => {
+                vk::PipelineStageFlags::VERTEX_SHADER | vk::PipelineStageFlags::FRAGMENT_SHADER
+            }
+            PassKind::Compute => vk::PipelineStageFlags::COMPUTE_SHADER,
+            PassKind::Transfer => vk::PipelineStageFlags::TRANSFER,
+        };
+        let fragment_tests = vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS
+            | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS;
+        let (stage, access, layout) = match self {
+            Self::ColorAttachment => (
+                vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+                vk::AccessFlags::COLOR_ATTACHMENT_READ | vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+                vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            ),
+            Self::DepthAttachment => (
+                fragment_tests,
+                vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ
+                    | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+                vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+            ),
+            Self::DepthAttachmentReadOnly => (
+                fragment_tests,
+                vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ,
+                vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL,
+            ),
+            Self::Sampled => (
+                shader_stages,
+                vk::AccessFlags::SHADER_READ,
+                if desc.map_or(false, ImageDesc::is_depth) {
+                    vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL
+                } else {
+                    vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL
+                },
+            ),
+            Self::StorageImageRead => (
+                shader_stages,
+                vk::AccessFlags::SHADER_READ,
+                vk::ImageLayout::GENERAL,
+            ),
+            Self::StorageImageWrite => (
+                shader_stages,
+                vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE,
+                vk::ImageLayout::GENERAL,
+            ),
+            Self::TransferSrc => (
+                vk::PipelineStageFlags::TRANSFER,
+                vk::AccessFlags::TRANSFER_READ,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            ),
+            Self::TransferDst => (
+                vk::PipelineStageFlags::TRANSFER,
+                vk::AccessFlags::TRANSFER_WRITE,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            ),
+            Self::UniformBuffer => (
+                shader_stages,
+                vk::AccessFlags::UNIFORM_READ,
+                vk::ImageLayout::UNDEFINED,
+            ),
+            Self::VertexBuffer => (
+                vk::PipelineStageFlags::VERTEX_INPUT,
+                vk::AccessFlags::VERTEX_ATTRIBUTE_READ,
+                vk::ImageLayout::UNDEFINED,
+            ),
+            Self::IndexBuffer => (
+                vk::PipelineStageFlags::VERTEX_INPUT,
+                vk::AccessFlags::INDEX_READ,
+                vk::ImageLayout::UNDEFINED,
+            ),
+            Self::IndirectBuffer => (
+                vk::PipelineStageFlags::DRAW_INDIRECT,
+                vk::AccessFlags::INDIRECT_COMMAND_READ,
+                vk::ImageLayout::UNDEFINED,
+            ),
+            Self::StorageBufferRead => (
+                shader_stages,
+                vk::AccessFlags::SHADER_READ,
+                vk::ImageLayout::UNDEFINED,
+            ),
+            Self::StorageBufferWrite => (
+                shader_stages,
+                vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE,
+                vk::ImageLayout::UNDEFINED,
+            ),
+        };
+
+        ResourceState {
+            stage,
+            access,
+            layout,
+        }
+    }
+}
+
+impl PassContext<'_> {
+    #[inline]
+    pub fn device(&self) -> &crate::Device {
+        self.device
+    }
+
+    #[inline]
+    pub fn command_buffer(&self) -> vk::CommandBuffer {
+        self.command_buffer
+    }
+
+    // Extent of the attachments; zero for passes without any
+    #[inline]
+    pub fn extent(&self) -> vk::Extent2D {
+        self.extent
+    }
+
+    pub fn image(&self, resource: ResourceId) -> vk::Image {
+        match self.physical[resource.0] {
+            PhysicalResource::Image(image, _) => image,
+            PhysicalResource::Buffer(_) => panic!("Resource {:?} is not an image", resource),
+        }
+    }
+
+    pub fn image_view(&self, resource: ResourceId) -> vk::ImageView {
+        match self.physical[resource.0] {
+            PhysicalResource::Image(_, view) => view,
+            PhysicalResource::Buffer(_) => panic!("Resource {:?} is not an image", resource),
+        }
+    }
+
+    pub fn buffer(&self, resource: ResourceId) -> vk::Buffer {
+        match self.physical[resource.0] {
+            PhysicalResource::Buffer(buffer) => buffer,
+            PhysicalResource::Image(..) => panic!("Resource {:?} is not a buffer", resource),
+        }
+    }
+}
+
+impl<'g, 'a> PassBuilder<'g, 'a> {
+    pub fn color_attachment(mut self, resource: ResourceId, clear: Option<[f32; 4]>) -> Self {
+        self.assert_graphics("color attachments");
+        self.pass
+            .accesses
+            .push((resource, ResourceAccess::ColorAttachment));
+        if clear.is_none() {
+            self.pass.loads.push(resource);
+        }
+        self.pass.color_attachments.push(ColorAttachment {
+            resource,
+            clear,
+            resolve: None,
+        });
+
+        self
+    }
+
+    // Resolves the most recently added (multisampled) color attachment into `resource`
+    pub fn resolve_into(mut self, resource: ResourceId) -> Self {
+        let color_attachment = self
+            .pass
+            .color_attachments
+            .last_mut()
+            .expect("resolve_into needs a color attachment to resolve");
+
+        color_attachment.resolve = Some(resource);
+        self.pass
+            .accesses
+            .push((resource, ResourceAccess::ColorAttachment));
+
+        self
+    }
+
+    pub fn depth_attachment(mut self, resource: ResourceId, clear: Option<f32>) -> Self {
+        self.assert_graphics("depth attachments");
+        assert!(
+            self.pass.depth_attachment.is_none(),
+            "Pass {:?} already has a depth attachment",
+            self.pass.name
+        );
+        self.pass
+            .accesses
+            .push((resource, ResourceAccess::DepthAttachment));
+        if clear.is_none() {
+            self.pass.loads.push(resource);
+        }
+        self.pass.depth_attachment = Some(DepthAttachment {
+            resource,
+            clear,
+            read_only: false,
+        });
+
+        self
+    }
+
+    // Depth testing against a depth buffer written by an earlier pass
+    pub fn depth_attachment_read_only(mut self, resource: ResourceId) -> Self {
+        self.assert_graphics("depth attachments");
+        assert!(
+            self.pass.depth_attachment.is_none(),
+            "Pass {:?} already has a depth attachment",
+            self.pass.name
+        );
+        self.pass
+            .accesses
+            .push((resource, ResourceAccess::DepthAttachmentReadOnly));
+        self.pass.depth_attachment = Some(DepthAttachment {
+            resource,
+            clear: None,
+            read_only: true,
+        });
+
+        self
+    }
+
+    pub fn read(mut self, resource: ResourceId, access: ResourceAccess) -> Self {
+        assert!(
+            !access.is_write() && !access.is_attachment(),
+            "{:?} is not a read access",
+            access
+        );
+        self.pass.accesses.push((resource, access));
+
+        self
+    }
+
+    pub fn write(mut self, resource: ResourceId, access: ResourceAccess) -> Self {
+        assert!(
+            access.is_write() && !access.is_attachment(),
+            "{:?} is not a write access",
+            access
+        );
+        self.pass.accesses.push((resource, access));
+
+        self
+    }
+
+    // Never culled, e.g. for passes that only write to host-visible buffers
+    pub fn side_effects(mut self) -> Self {
+        self.pass.side_effects = true;
+
+        self
+    }
+
+    // Adds the pass to the graph; `execute` records its commands when the graph runs
+    pub fn execute<F>(mut self, execute: F)
+    where
+        F: FnOnce(&PassContext) -> Result<()> + 'a,
+    {
+        self.pass.execute = Some(Box::new(execute));
+        self.graph.passes.push(self.pass);
+    }
+
+    fn assert_graphics(&self, what: &str) {
+        assert!(
+            self.pass.kind == PassKind::Graphics,
+            "Only graphics passes can have {}",
+            what
+        );
+    }
+}
+
+impl<'a> RenderGraph<'a> {
+    pub fn new() -> Self {
+        Self {
+            resources: vec![],
+            passes: vec![],
+        }
+    }
+
+    // Owned by the graph, which may give its image to later transients; contents don't survive the frame
+    pub fn create_image(&mut self, name: &str, desc: ImageDesc) -> ResourceId {
+        self.add_resource(name, ResourceKind::Transient(desc))
+    }
+
+    pub fn import_image(
+        &mut self,
+        name: &str,
+        image: vk::Image,
+        view: vk::ImageView,
+        desc: ImageDesc,
+        initial_layout: vk::ImageLayout,
+        final_layout: vk::ImageLayout,
+    ) -> ResourceId {
+        self.add_resource(
+            name,
+            ResourceKind::Image {
+                image,
+                view,
+                desc,
+                initial_layout,
+                final_layout,
+            },
+        )
+    }
+
+    // The image acquired for the current frame, handed back ready to present
+    pub fn import_swap_chain_image(&mut self, renderer: &crate::Renderer) -> ResourceId {
+        let (image, view) = renderer.current_swap_chain_image();
+        let extent = renderer.swap_chain().swap_chain_extent();
+
+        self.import_image(
+            "swap chain",
+            image,
+            view,
+            ImageDesc::new(
+                renderer.surface_format().format,
+                extent.width,
+                extent.height,
+            ),
+            vk::ImageLayout::UNDEFINED,
+            vk::ImageLayout::PRESENT_SRC_KHR,
+        )
+    }
+
+    pub fn import_buffer(&mut self, name: &str, buffer: vk::Buffer) -> ResourceId {
+        self.add_resource(name, ResourceKind::Buffer(buffer))
+    }
+
+    pub fn graphics_pass(&mut self, name: &str) -> PassBuilder<'_, 'a> {
+        self.add_pass(name, PassKind::Graphics)
+    }
+
+    pub fn compute_pass(&mut self, name: &str) -> PassBuilder<'_, 'a> {
+        self.add_pass(name, PassKind::Compute)
+    }
+
+    pub fn transfer_pass(&mut self, name: &str) -> PassBuilder<'_, 'a> {
+        self.add_pass(name, PassKind::Transfer)
+    }
+
+    /* MEMO
+     *  `frame_index` selects the set of transient images in `cache`; the renderer
+     *  has waited for the previous submission of that frame index by the time
+     *  begin_frame returns, so those images are free to be reused.
+     */
+    pub fn execute(
+        self,
+        device: &crate::Device,
+        command_buffer: &vk::CommandBuffer,
+        frame_index: usize,
+        cache: &mut TransientImageCache,
+    ) -> Result<()> {
+        let layout = self.compile()?;
+
+        if !device.supports_dynamic_rendering()
+            && layout.order.iter().any(|&index| {
+                let pass = &self.passes[index];
+
+                !pass.color_attachments.is_empty() || pass.depth_attachment.is_some()
+            })
+        {
+            bail!("Render graph passes with attachments need dynamic rendering");
+        }
+
+        let transient_frame = cache.acquire(
+            device,
+            frame_index,
+            &layout.slot_descs,
+            &layout.slot_lifetimes,
+        )?;
+        let physical = self.physical_resources(&layout.slots, &transient_frame.images);
+        // Tracked per physical image, so resources sharing one synchronize with each other
+        let state_index = |resource: ResourceId| match layout.slots[resource.0] {
+            Some(slot) => self.resources.len() + slot,
+            None => resource.0,
+        };
+        let mut states = self
+            .resources
+            .iter()
+            .map(|resource| ResourceState {
+                stage: vk::PipelineStageFlags::ALL_COMMANDS,
+                access: vk::AccessFlags::empty(),
+                layout: match resource.kind {
+                    ResourceKind::Image { initial_layout, .. } => initial_layout,
+                    _ => vk::ImageLayout::UNDEFINED,
+                },
+            })
+            .chain(layout.slot_descs.iter().map(|_| ResourceState {
+                stage: vk::PipelineStageFlags::TOP_OF_PIPE,
+                access: vk::AccessFlags::empty(),
+                layout: vk::ImageLayout::UNDEFINED,
+            }))
+            .collect::<Vec<_>>();
+        let mut passes = self.passes.into_iter().map(Some).collect::<Vec<_>>();
+
+        for (position, &pass_index) in layout.order.iter().enumerate() {
+            let pass = passes[pass_index]
+                .take()
+                .expect("Render graph pass scheduled twice");
+            let is_first_use = |resource: ResourceId| {
+                matches!(self.resources[resource.0].kind, ResourceKind::Transient(_))
+                    && layout.lifetimes[resource.0].map(|(first, _)| first) == Some(position)
+            };
+            let is_last_use = |resource: ResourceId| {
+                matches!(self.resources[resource.0].kind, ResourceKind::Transient(_))
+                    && layout.lifetimes[resource.0].map(|(_, last)| last) == Some(position)
+            };
+            let mut next_states: Vec<(ResourceId, ResourceState)> = vec![];
+
+            for (resource, access) in pass.accesses.iter() {
+                let next = access.state(pass.kind, self.resources[resource.0].image_desc());
+
+                match next_states.iter_mut().find(|(other, _)| other == resource) {
+                    Some((_, state)) if state.layout != next.layout => bail!(
+                        "Pass {:?} uses {:?} in two different layouts",
+                        pass.name,
+                        self.resources[resource.0].name
+                    ),
+                    Some((_, state)) => {
+                        state.stage |= next.stage;
+                        state.access |= next.access;
+                    }
+                    None => next_states.push((*resource, next)),
+                }
+            }
+
+            let mut src_stage = vk::PipelineStageFlags::empty();
+            let mut dst_stage = vk::PipelineStageFlags::empty();
+            let mut image_barriers = vec![];
+            let mut buffer_barriers = vec![];
+
+            for (resource, next) in next_states {
+                let key = state_index(resource);
+                let mut previous = states[key];
+
+                // Memory taken over from other images has to be done with first
+                if let Some(slot) = layout.slots[resource.0].filter(|_| is_first_use(resource)) {
+                    for &alias in transient_frame.aliases[slot].iter() {
+                        let alias_state = states[self.resources.len() + alias];
+
+                        previous.stage |= alias_state.stage;
+                        previous.access |= alias_state.access;
+                    }
+                }
+                // Shared or not, a transient image holds nothing useful before its first use
+                let old_layout = if is_first_use(resource) {
+                    vk::ImageLayout::UNDEFINED
+                } else {
+                    previous.layout
+                };
+                let written = Self::has_writes(previous.access) || Self::has_writes(next.access);
+
+                if old_layout == next.layout && !written && !is_first_use(resource) {
+                    // Read after read: nothing to wait for, but a later write has to
+                    states[key].stage |= next.stage;
+                    states[key].access |= next.access;
+                    continue;
+                }
+
+                src_stage |= previous.stage;
+                dst_stage |= next.stage;
+                match (&self.resources[resource.0].kind, physical[resource.0]) {
+                    (ResourceKind::Buffer(_), PhysicalResource::Buffer(buffer)) => buffer_barriers
+                        .push(
+                            vk::BufferMemoryBarrier::builder()
+                                .src_access_mask(previous.access & Self::write_access_mask())
+                                .dst_access_mask(next.access)
+                                .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                                .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                                .buffer(buffer)
+                                .offset(0)
+                                .size(vk::WHOLE_SIZE)
+                                .build(),
+                        ),
+                    (kind, PhysicalResource::Image(image, _)) => {
+                        let aspect_mask = match kind {
+                            ResourceKind::Transient(desc) | ResourceKind::Image { desc, .. } => {
+                                desc.aspect_mask()
+                            }
+                            ResourceKind::Buffer(_) => unreachable!(),
+                        };
+
+                        image_barriers.push(Self::image_barrier(
+                            image,
+                            aspect_mask,
+                            old_layout,
+                            next.layout,
+                            previous.access & Self::write_access_mask(),
+                            next.access,
+                        ));
+                    }
+                    _ => unreachable!(),
+                }
+                states[key] = next;
+            }
+
+            if !image_barriers.is_empty() || !buffer_barriers.is_empty() {
+                unsafe {
+                    device.device().cmd_pipeline_barrier(
+                        *command_buffer,
+                        src_stage,
+                        dst_stage,
+                        vk::DependencyFlags::empty(),
+                        &[],
+                        &buffer_barriers,
+                        &image_barriers,
+                    )
+                };
+            }
+
+            let extent = Self::attachment_extent(&self.resources, &pass)?;
+            let rendering = pass.depth_attachment.is_some() || !pass.color_attachments.is_empty();
+
+            if rendering {
+                let load_op = |resource: ResourceId, clear: bool| {
+                    if clear {
+                        vk::AttachmentLoadOp::CLEAR
+                    } else if is_first_use(resource) {
+                        vk::AttachmentLoadOp::DONT_CARE
+                    } else {
+                        vk::AttachmentLoadOp::LOAD
+                    }
+                };
+                let store_op = |resource: ResourceId| {
+                    if is_last_use(resource) {
+                        vk::AttachmentStoreOp::DONT_CARE
+                    } else {
+                        vk::AttachmentStoreOp::STORE
+                    }
+                };
+                let view = |resource: ResourceId| match physical[resource.0] {
+                    PhysicalResource::Image(_, view) => view,
+                    PhysicalResource::Buffer(_) => vk::ImageView::null(),
+                };
+                let color_attachments = pass
+                    .color_attachments
+                    .iter()
+                    .map(|attachment| {
+                        let mut info = vk::RenderingAttachmentInfo::builder()
+                            .image_view(view(attachment.resource))
+                            .image_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+                            .load_op(load_op(attachment.resource, attachment.clear.is_some()))
+                            .store_op(store_op(attachment.resource))
+                            .clear_value(vk::ClearValue {
+                                color: vk::ClearColorValue {
+                                    float32: attachment.clear.unwrap_or_default(),
+                                },
+                            });
+
+                        if let Some(resolve) = attachment.resolve {
+                            info = info
+                                .resolve_mode(vk::ResolveModeFlags::AVERAGE)
+                                .resolve_image_view(view(resolve))
+                                .resolve_image_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL);
+                        }
+
+                        info.build()
+                    })
+                    .collect::<Vec<_>>();
+                let depth_attachment = pass.depth_attachment.as_ref().map(|attachment| {
+                    vk::RenderingAttachmentInfo::builder()
+                        .image_view(view(attachment.resource))
+                        .image_layout(if attachment.read_only {
+                            vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL
+                        } else {
+                            vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL
+                        })
+                        .load_op(load_op(attachment.resource, attachment.clear.is_some()))
+                        .store_op(store_op(attachment.resource))
+                        .clear_value(vk::ClearValue {
+                            depth_stencil: vk::ClearDepthStencilValue::builder()
+                                .depth(attachment.clear.unwrap_or(1.0))
+                                .stencil(0)
+                                .build(),
+                        })
+                        .build()
+                });
+                let mut rendering_info = vk::RenderingInfo::builder()
+                    .render_area(vk::Rect2D {
+                        offset: vk::Offset2D { x: 0, y: 0 },
+                        extent,
+                    })
+                    .layer_count(1)
+                    .color_attachments(&color_attachments);
+
+                if let Some(depth_attachment) = depth_attachment.as_ref() {
+                    rendering_info = rendering_info.depth_attachment(depth_attachment);
+                }
+
+                let viewport = vk::Viewport {
+                    x: 0.0,
+                    y: 0.0,
+                    width: extent.width as f32,
+                    height: extent.height as f32,
+                    min_depth: 0.0,
+                    max_depth: 1.0,
+                };
+                let scissor = vk::Rect2D {
+                    extent,
+                    offset: vk::Offset2D { x: 0, y: 0 },
+                };
+
+                unsafe {
+                    device
+                        .device()
+                        .cmd_begin_rendering(*command_buffer, &rendering_info);
+                    device.device().cmd_set_viewport(
+                        *command_buffer,
+                        0,
+                        std::slice::from_ref(&viewport),
+                    );
+                    device.device().cmd_set_scissor(
+                        *command_buffer,
+                        0,
+                        std::slice::from_ref(&scissor),
+                    );
+                }
+            }
+
+            if let Some(execute) = pass.execute {
+                execute(&PassContext {
+                    device,
+                    command_buffer: *command_buffer,
+                    extent,
+                    physical: &physical,
+                })
+                .map_err(|e| e.context(format!("Render graph pass {:?} failed", pass.name)))?;
+            }
+
+            if rendering {
+                unsafe { device.device().cmd_end_rendering(*command_buffer) };
+            }
+        }
+
+        // Hand imported images back in the layout they were promised in
+        let mut src_stage = vk::PipelineStageFlags::empty();
+        let mut image_barriers = vec![];
+
+        for (index, resource) in self.resources.iter().enumerate() {
+            if let ResourceKind::Image {
+                image,
+                desc,
+                final_layout,
+                ..
+            } = resource.kind
+            {
+                let state = states[index];
+
+                if state.layout == final_layout && !Self::has_writes(state.access) {
+                    continue;
+                }
+
+                src_stage |= state.stage;
+                image_barriers.push(Self::image_barrier(
+                    image,
+                    desc.aspect_mask(),
+                    state.layout,
+                    final_layout,
+                    state.access & Self::write_access_mask(),
+                    vk::AccessFlags::MEMORY_READ | vk::AccessFlags::MEMORY_WRITE,
+                ));
+            }
+        }
+        if !image_barriers.is_empty() {
+            unsafe {
+                device.device().cmd_pipeline_barrier(
+                    *command_buffer,
+                    src_stage,
+                    vk::PipelineStageFlags::ALL_COMMANDS,
+                    vk::DependencyFlags::empty(),
+                    &[],
+                    &[],
+                    &image_barriers,
+                )
+            };
+        }
+
+        Ok(())
+    }
+
+    // Human-readable execution order, culled passes and transient image reuse
+    pub fn dump(&self) -> Result<String> {
+        let layout = self.compile()?;
+        let mut out = String::new();
+
+        writeln!(
+            out,
+            "RenderGraph: {} passes ({} culled), {} resources, {} transient images",
+            self.passes.len(),
+            layout.culled.len(),
+            self.resources.len(),
+            layout.slot_descs.len()
+        )?;
+        for (position, &index) in layout.order.iter().enumerate() {
+            let pass = &self.passes[index];
+
+            writeln!(out, "  [{}] {} ({:?})", position, pass.name, pass.kind)?;
+            for (resource, access) in pass.accesses.iter() {
+                writeln!(
+                    out,
+                    "        {} {} as {:?}",
+                    if access.is_write() {
+                        "writes"
+                    } else {
+                        "reads "
+                    },
+                    self.resources[resource.0].name,
+                    access
+                )?;
+            }
+        }
+        for &index in layout.culled.iter() {
+            writeln!(out, "  [culled] {}", self.passes[index].name)?;
+        }
+        for (index, resource) in self.resources.iter().enumerate() {
+            let lifetime = match layout.lifetimes[index] {
+                Some((first, last)) => format!("passes {}..={}", first, last),
+                None => "unused".to_string(),
+            };
+
+            match &resource.kind {
+                ResourceKind::Transient(desc) => writeln!(
+                    out,
+                    "  {}: transient {}x{} {:?} x{}, image #{}, {}",
+                    resource.name,
+                    desc.extent.width,
+                    desc.extent.height,
+                    desc.format,
+                    desc.samples.as_raw(),
+                    layout.slots[index].map_or(-1, |slot| slot as i64),
+                    lifetime
+                )?,
+                ResourceKind::Image {
+                    desc,
+                    initial_layout,
+                    final_layout,
+                    ..
+                } => writeln!(
+                    out,
+                    "  {}: imported {}x{} {:?}, {:?} -> {:?}, {}",
+                    resource.name,
+                    desc.extent.width,
+                    desc.extent.height,
+                    desc.format,
+                    initial_layout,
+                    final_layout,
+                    lifetime
+                )?,
+                ResourceKind::Buffer(_) => {
+                    writeln!(out, "  {}: imported buffer, {}", resource.name, lifetime)?
+                }
+            }
+        }
+
+        Ok(out)
+    }
+
+    // Graphviz source; culled passes are drawn dashed
+    pub fn to_dot(&self) -> Result<String> {
+        let layout = self.compile()?;
+        let mut out = String::from("digraph RenderGraph {\n    rankdir=LR;\n");
+
+        for (index, pass) in self.passes.iter().enumerate() {
+            writeln!(
+                out,
+                "    pass{} [shape=box, label=\"{}\"{}];",
+                index,
+                pass.name,
+                if layout.culled.contains(&index) {
+                    ", style=dashed"
+                } else {
+                    ""
+                }
+            )?;
+        }
+        for (index, resource) in self.resources.iter().enumerate() {
+            writeln!(
+                out,
+                "    resource{} [shape=ellipse, label=\"{}\"];",
+                index, resource.name
+            )?;
+        }
+        for (index, pass) in self.passes.iter().enumerate() {
+            for (resource, access) in pass.accesses.iter() {
+                if access.is_write() {
+                    writeln!(out, "    pass{} -> resource{};", index, resource.0)?;
+                }
+            }
+            for resource in Self::reads(pass) {
+                writeln!(out, "    resource{} -> pass{};", resource.0, index)?;
+            }
+        }
+        out.push_str("}\n");
+
+        Ok(out)
+    }
+
+    /* --- Helper functions --- */
+    fn add_resource(&mut self, name: &str, kind: ResourceKind) -> ResourceId {
+        self.resources.push(Resource {
+            name: name.to_string(),
+            kind,
+        });
+
+        ResourceId(self.resources.len() - 1)
+    }
+
+    fn add_pass(&mut self, name: &str, kind: PassKind) -> PassBuilder<'_, 'a> {
+        PassBuilder {
+            graph: self,
+            pass: Pass {
+                name: name.to_string(),
+                kind,
+                accesses: vec![],
+                loads: vec![],
+                color_attachments: vec![],
+                depth_attachment: None,
+                side_effects: false,
+                execute: None,
+            },
+        }
+    }
+
+    fn compile(&self) -> Result<GraphLayout> {
+        let pass_count = self.passes.len();
+        let mut writers: HashMap<ResourceId, Vec<usize>> = HashMap::new();
+
+        for (index, pass) in self.passes.iter().enumerate() {
+            for resource in Self::writes(pass) {
+                let resource_writers = writers.entry(resource).or_default();
+
+                if resource_writers.last() != Some(&index) {
+                    resource_writers.push(index);
+                }
+            }
+        }
+
+        // Passes a pass has to wait for: the writers of whatever it reads, and the
+        //  previous writer of whatever it writes
+        let dependencies = self
+            .passes
+            .iter()
+            .enumerate()
+            .map(|(index, pass)| {
+                let mut dependencies = vec![];
+
+                for resource in Self::reads(pass) {
+                    let writes_too = Self::writes(pass).any(|written| written == resource);
+
+                    dependencies.extend(
+                        writers
+                            .get(&resource)
+                            .into_iter()
+                            .flatten()
+                            .copied()
+                            .filter(|&writer| writer != index && (!writes_too || writer < index)),
+                    );
+                }
+                for resource in Self::writes(pass) {
+                    dependencies.extend(
+                        writers
+                            .get(&resource)
+                            .into_iter()
+                            .flatten()
+                            .copied()
+                            .filter(|&writer| writer < index)
+                            .last(),
+                    );
+                }
+                dependencies.sort_unstable();
+                dependencies.dedup();
+
+                dependencies
+            })
+            .collect::<Vec<_>>();
+
+        // Culling: keep what leads to an imported resource or has side effects
+        let mut needed = self
+            .passes
+            .iter()
+            .map(|pass| {
+                pass.side_effects
+                    || Self::writes(pass).any(|resource| {
+                        !matches!(self.resources[resource.0].kind, ResourceKind::Transient(_))
+                    })
+            })
+            .collect::<Vec<_>>();
+        let mut stack = (0..pass_count)
+            .filter(|&index| needed[index])
+            .collect::<Vec<_>>();
+
+        while let Some(index) = stack.pop() {
+            for &dependency in dependencies[index].iter() {
+                if !needed[dependency] {
+                    needed[dependency] = true;
+                    stack.push(dependency);
+                }
+            }
+        }
+
+        // Topological order, declaration order among passes that are ready together
+        let mut dependents = vec![vec![]; pass_count];
+        let mut pending = vec![0; pass_count];
+
+        for index in (0..pass_count).filter(|&index| needed[index]) {
+            for &dependency in dependencies[index].iter() {
+                dependents[dependency].push(index);
+                pending[index] += 1;
+            }
+        }
+
+        let mut ready = (0..pass_count)
+            .filter(|&index| needed[index] && pending[index] == 0)
+            .map(Reverse)
+            .collect::<BinaryHeap<_>>();
+        let mut order = Vec::with_capacity(pass_count);
+
+        while let Some(Reverse(index)) = ready.pop() {
+            order.push(index);
+            for &dependent in dependents[index].iter() {
+                pending[dependent] -= 1;
+                if pending[dependent] == 0 {
+                    ready.push(Reverse(dependent));
+                }
+            }
+        }
+
+        let culled = (0..pass_count)
+            .filter(|&index| !needed[index])
+            .collect::<Vec<_>>();
+
+        if order.len() + culled.len() != pass_count {
+            let stuck = (0..pass_count)
+                .find(|&index| needed[index] && pending[index] > 0)
+                .map(|index| self.passes[index].name.as_str())
+                .unwrap_or_default();
+
+            bail!(
+                "Render graph has a dependency cycle through pass {:?}",
+                stuck
+            );
+        }
+
+        let mut lifetimes: Vec<Option<(usize, usize)>> = vec![None; self.resources.len()];
+
+        for (position, &index) in order.iter().enumerate() {
+            for (resource, _) in self.passes[index].accesses.iter() {
+                let lifetime = &mut lifetimes[resource.0];
+
+                *lifetime = Some(match *lifetime {
+                    Some((first, _)) => (first, position),
+                    None => (position, position),
+                });
+            }
+        }
+
+        // Image reuse: a transient takes over the slot of one with the same ImageDesc
+        //  once the slot's last user is done
+        let mut slots = vec![None; self.resources.len()];
+        let mut slot_descs: Vec<(ImageDesc, vk::ImageUsageFlags)> = vec![];
+        let mut slot_lifetimes: Vec<(usize, usize)> = vec![];
+        let mut transients = self
+            .resources
+            .iter()
+            .enumerate()
+            .filter_map(
+                |(index, resource)| match (&resource.kind, lifetimes[index]) {
+                    (ResourceKind::Transient(desc), Some(lifetime)) => {
+                        Some((index, *desc, lifetime))
+                    }
+                    _ => None,
+                },
+            )
+            .collect::<Vec<_>>();
+
+        transients.sort_by_key(|(_, _, (first, _))| *first);
+        for (index, desc, (first, last)) in transients {
+            let usage = self
+                .passes
+                .iter()
+                .flat_map(|pass| pass.accesses.iter())
+                .filter(|(resource, _)| resource.0 == index)
+                .fold(vk::ImageUsageFlags::empty(), |usage, (_, access)| {
+                    usage | access.image_usage()
+                });
+            let slot = match (0..slot_descs.len())
+                .find(|&slot| slot_descs[slot].0 == desc && slot_lifetimes[slot].1 < first)
+            {
+                Some(slot) => {
+                    slot_descs[slot].1 |= usage;
+                    slot
+                }
+                None => {
+                    slot_descs.push((desc, usage));
+                    slot_lifetimes.push((first, last));
+                    slot_descs.len() - 1
+                }
+            };
+
+            slot_lifetimes[slot].1 = last;
+            slots[index] = Some(slot);
+        }
+
+        Ok(GraphLayout {
+            order,
+            culled,
+            lifetimes,
+            slots,
+            slot_descs,
+            slot_lifetimes,
+        })
+    }
+
+    // Transients without a slot are never used (or only by culled passes), so they get no image
+    fn physical_resources(
+        &self,
+        slots: &[Option<usize>],
+        slot_images: &[(vk::Image, vk::ImageView)],
+    ) -> Vec<PhysicalResource> {
+        self.resources
+            .iter()
+            .zip(slots)
+            .map(|(resource, slot)| match (&resource.kind, slot) {
+                (ResourceKind::Transient(_), Some(slot)) => {
+                    let (image, view) = slot_images[*slot];
+
+                    PhysicalResource::Image(image, view)
+                }
+                (ResourceKind::Transient(_), None) => {
+                    PhysicalResource::Image(vk::Image::null(), vk::ImageView::null())
+                }
+                (ResourceKind::Image { image, view, .. }, _) => {
+                    PhysicalResource::Image(*image, *view)
+                }
+                (ResourceKind::Buffer(buffer), _) => PhysicalResource::Buffer(*buffer),
+            })
+            .collect()
+    }
+
+    fn reads<'p>(pass: &'p Pass) -> impl Iterator<Item = ResourceId> + 'p {
+        pass.accesses
+            .iter()
+            .filter(|(_, access)| !access.is_write())
+            .map(|(resource, _)| *resource)
+            .chain(pass.loads.iter().copied())
+    }
+
+    fn writes<'p>(pass: &'p Pass) -> impl Iterator<Item = ResourceId> + 'p {
+        pass.accesses
+            .iter()
+            .filter(|(_, access)| access.is_write())
+            .map(|(resource, _)| *resource)
+    }
+
+    fn attachment_extent(resources: &[Resource], pass: &Pass) -> Result<vk::Extent2D> {
+        let mut extent = None;
+
+        for resource in pass
+            .color_attachments
+            .iter()
+            .flat_map(|attachment| [Some(attachment.resource), attachment.resolve])
+            .flatten()
+            .chain(
+                pass.depth_attachment
+                    .as_ref()
+                    .map(|attachment| attachment.resource),
+            )
+        {
+            let desc = match resources[resource.0].image_desc() {
+                Some(desc) => desc,
+                None => bail!(
+                    "Buffer {:?} can't be an attachment",
+                    resources[resource.0].name
+                ),
+            };
+
+            match extent {
+                Some(extent) if extent != desc.extent => bail!(
+                    "Attachments of pass {:?} differ in size ({:?} vs {:?})",
+                    pass.name,
+                    extent,
+                    desc.extent
+                ),
+                _ => extent = Some(desc.extent),
+            }
+        }
+
+        Ok(extent.unwrap_or_default())
+    }
+
+    #[inline]
+    fn write_access_mask() -> vk::AccessFlags {
+        vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+            | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE
+            | vk::AccessFlags::SHADER_WRITE
+            | vk::AccessFlags::TRANSFER_WRITE
+            | vk::AccessFlags::MEMORY_WRITE
+    }
+
+    #[inline]
+    fn has_writes(access: vk::AccessFlags) -> bool {
+        access.intersects(Self::write_access_mask())
+    }
+
+    fn image_barrier(
+        image: vk::Image,
+        aspect_mask: vk::ImageAspectFlags,
+        old_layout: vk::ImageLayout,
+        new_layout: vk::ImageLayout,
+        src_access: vk::AccessFlags,
+        dst_access: vk::AccessFlags,
+    ) -> vk::ImageMemoryBarrier {
+        vk::ImageMemoryBarrier::builder()
+            .src_access_mask(src_access)
+            .dst_access_mask(dst_access)
+            .old_layout(old_layout)
+            .new_layout(new_layout)
+            .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .image(image)
+            .subresource_range(vk::ImageSubresourceRange {
+                aspect_mask,
+                base_mip_level: 0,
+                level_count: 1,
+                base_array_layer: 0,
+                layer_count: 1,
+            })
+            .build()
+    }
+}
+
+impl Default for RenderGraph<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Resource {
+    fn image_desc(&self) -> Option<&ImageDesc> {
+        match &self.kind {
+            ResourceKind::Transient(desc) | ResourceKind::Image { desc, .. } => Some(desc),
+            ResourceKind::Buffer(_) => None,
+        }
+    }
+}
+
+impl TransientImageCache {
+    pub fn new() -> Self {
+        Self { frames: vec![] }
+    }
+
+    pub unsafe fn destroy(&mut self, device: &crate::Device) {
+        for transient_frame in self.frames.drain(..).flatten() {
+            transient_frame.destroy(device);
+        }
+    }
+
+    // The frame index's images are rebuilt whenever the graph asks for other slots
+    fn acquire(
+        &mut self,
+        device: &crate::Device,
+        frame_index: usize,
+        slot_descs: &[(ImageDesc, vk::ImageUsageFlags)],
+        slot_lifetimes: &[(usize, usize)],
+    ) -> Result<&TransientFrame> {
+        if self.frames.len() <= frame_index {
+            self.frames.resize_with(frame_index + 1, || None);
+        }
+
+        let frame = &mut self.frames[frame_index];
+
+        if !frame.as_ref().map_or(false, |transient_frame| {
+            transient_frame.slot_descs == slot_descs
+                && transient_frame.slot_lifetimes == slot_lifetimes
+        }) {
+            if let Some(transient_frame) = frame.take() {
+                unsafe { transient_frame.destroy(device) };
+            }
+            *frame = Some(TransientFrame::new(device, slot_descs, slot_lifetimes)?);
+        }
+
+        Ok(frame.as_ref().unwrap())
+    }
+}
+
+impl Default for TransientImageCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TransientFrame {
+    fn new(
+        device: &crate::Device,
+        slot_descs: &[(ImageDesc, vk::ImageUsageFlags)],
+        slot_lifetimes: &[(usize, usize)],
+    ) -> Result<Self> {
+        let mut transient_frame = Self {
+            slot_descs: slot_descs.to_vec(),
+            slot_lifetimes: slot_lifetimes.to_vec(),
+            images: Vec::with_capacity(slot_descs.len()),
+            memory: vec![],
+            aliases: vec![],
+        };
+
+        match transient_frame.init(device) {
+            Ok(()) => Ok(transient_frame),
+            Err(err) => {
+                unsafe { transient_frame.destroy(device) };
+                Err(err)
+            }
+        }
+    }
+
+    unsafe fn destroy(self, device: &crate::Device) {
+        for (image, view) in self.images {
+            device.device().destroy_image_view(view, None);
+            device.device().destroy_image(image, None);
+        }
+        for memory in self.memory {
+            device.device().free_memory(memory, None);
+        }
+    }
+
+    /* --- Helper functions --- */
+    fn init(&mut self, device: &crate::Device) -> Result<()> {
+        for (desc, usage) in self.slot_descs.iter() {
+            let image_info = vk::ImageCreateInfo::builder()
+                .image_type(vk::ImageType::TYPE_2D)
+                .extent(vk::Extent3D {
+                    width: desc.extent.width,
+                    height: desc.extent.height,
+                    depth: 1,
+                })
+                .mip_levels(1)
+                .array_layers(1)
+                .format(desc.format)
+                .tiling(vk::ImageTiling::OPTIMAL)
+                .initial_layout(vk::ImageLayout::UNDEFINED)
+                .usage(*usage)
+                .samples(desc.samples)
+                .sharing_mode(vk::SharingMode::EXCLUSIVE);
+            let image = unsafe { device.device().create_image(&image_info, None) }?;
+
+            self.images.push((image, vk::ImageView::null()));
+        }
+
+        let requirements = self
+            .images
+            .iter()
+            .zip(self.slot_lifetimes.iter())
+            .map(|((image, _), lifetime)| {
+                let requirements = unsafe { device.device().get_image_memory_requirements(*image) };
+
+                (requirements, *lifetime)
+            })
+            .collect::<Vec<_>>();
+        let plan = MemoryPlan::new(&requirements, |memory_type_bits| {
+            device.find_memory_type(memory_type_bits, vk::MemoryPropertyFlags::DEVICE_LOCAL)
+        })?;
+
+        for (memory_type, size) in plan.blocks.iter() {
+            let allocate_info = vk::MemoryAllocateInfo::builder()
+                .allocation_size(*size)
+                .memory_type_index(*memory_type);
+
+            self.memory
+                .push(unsafe { device.device().allocate_memory(&allocate_info, None) }?);
+        }
+        for (index, (desc, _)) in self.slot_descs.iter().enumerate() {
+            let (block, offset) = plan.placements[index];
+            let image = self.images[index].0;
+
+            unsafe {
+                device
+                    .device()
+                    .bind_image_memory(image, self.memory[block], offset)
+            }?;
+
+            // Views of depth/stencil images can only see one aspect
+            let aspect_mask = if desc.is_depth() {
+                vk::ImageAspectFlags::DEPTH
+            } else {
+                vk::ImageAspectFlags::COLOR
+            };
+            let create_info = vk::ImageViewCreateInfo::builder()
+                .image(image)
+                .view_type(vk::ImageViewType::TYPE_2D)
+                .format(desc.format)
+                .subresource_range(vk::ImageSubresourceRange {
+                    aspect_mask,
+                    base_mip_level: 0,
+                    level_count: 1,
+                    base_array_layer: 0,
+                    layer_count: 1,
+                });
+
+            self.images[index].1 =
+                unsafe { device.device().create_image_view(&create_info, None) }?;
+        }
+        self.aliases = plan.aliases;
+
+        Ok(())
+    }
+}
+
+impl MemoryPlan {
+    /* MEMO
+     *  Largest images first, each at the lowest offset (respecting its alignment)
+     *  that no image alive at the same time occupies. There is one allocation per
+     *  memory type, grown to fit; an image goes into the first allocation whose
+     *  memory type it supports, `memory_type` picks the type of a new one.
+     */
+    fn new<F>(images: &[(vk::MemoryRequirements, (usize, usize))], memory_type: F) -> Result<Self>
+    where
+        F: Fn(u32) -> Result<u32>,
+    {
+        let mut blocks: Vec<(u32, vk::DeviceSize)> = vec![];
+        let mut placements = vec![(0, 0); images.len()];
+        let mut placed: Vec<usize> = vec![];
+        let mut order = (0..images.len()).collect::<Vec<_>>();
+
+        order.sort_by_key(|&index| Reverse(images[index].0.size));
+        for index in order {
+            let (requirements, lifetime) = images[index];
+            let block = match blocks.iter().position(|(memory_type, _)| {
+                requirements.memory_type_bits & (1 << memory_type) != 0
+            }) {
+                Some(block) => block,
+                None => {
+                    blocks.push((memory_type(requirements.memory_type_bits)?, 0));
+                    blocks.len() - 1
+                }
+            };
+            let mut busy = placed
+                .iter()
+                .filter(|&&other| {
+                    placements[other].0 == block && Self::overlaps(images[other].1, lifetime)
+                })
+                .map(|&other| Self::range(images[other].0.size, placements[other].1))
+                .collect::<Vec<_>>();
+            let mut offset = 0;
+
+            busy.sort_unstable();
+            for (start, end) in busy {
+                if offset + requirements.size <= start {
+                    break;
+                }
+                offset = offset.max(end.div_ceil(requirements.alignment) * requirements.alignment);
+            }
+
+            blocks[block].1 = blocks[block].1.max(offset + requirements.size);
+            placements[index] = (block, offset);
+            placed.push(index);
+        }
+
+        let aliases = images
+            .iter()
+            .enumerate()
+            .map(|(index, (requirements, (first, _)))| {
+                let (start, end) = Self::range(requirements.size, placements[index].1);
+
+                (0..images.len())
+                    .filter(|&other| {
+                        let (other_start, other_end) =
+                            Self::range(images[other].0.size, placements[other].1);
+
+                        placements[other].0 == placements[index].0
+                            && images[other].1 .1 < *first
+                            && other_start < end
+                            && start < other_end
+                    })
+                    .collect()
+            })
+            .collect();
+
+        Ok(Self {
+            blocks,
+            placements,
+            aliases,
+        })
+    }
+
+    /* --- Helper functions --- */
+    #[inline]
+    fn overlaps(a: (usize, usize), b: (usize, usize)) -> bool {
+        a.0 <= b.1 && b.0 <= a.1
+    }
+
+    #[inline]
+    fn range(size: vk::DeviceSize, offset: vk::DeviceSize) -> (vk::DeviceSize, vk::DeviceSize) {
+        (offset, offset + size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn desc() -> ImageDesc {
+        ImageDesc::new(vk::Format::R8G8B8A8_UNORM, 64, 64)
+    }
+
+    fn import_output(graph: &mut RenderGraph) -> ResourceId {
+        graph.import_image(
+            "output",
+            vk::Image::null(),
+            vk::ImageView::null(),
+            desc(),
+            vk::ImageLayout::UNDEFINED,
+            vk::ImageLayout::GENERAL,
+        )
+    }
+
+    fn add_pass(graph: &mut RenderGraph, name: &str, reads: &[ResourceId], writes: &[ResourceId]) {
+        let mut pass = graph.compute_pass(name);
+
+        for &resource in reads {
+            pass = pass.read(resource, ResourceAccess::StorageImageRead);
+        }
+        for &resource in writes {
+            pass = pass.write(resource, ResourceAccess::StorageImageWrite);
+        }
+        pass.execute(|_| Ok(()));
+    }
+
+    #[test]
+    fn orders_passes_after_the_writers_they_read() {
+        let mut graph = RenderGraph::new();
+        let output = import_output(&mut graph);
+        let a = graph.create_image("a", desc());
+        let b = graph.create_image("b", desc());
+
+        add_pass(&mut graph, "composite", &[b], &[output]);
+        add_pass(&mut graph, "blur", &[a], &[b]);
+        add_pass(&mut graph, "draw", &[], &[a]);
+
+        let layout = graph.compile().unwrap();
+
+        assert_eq!(layout.order, vec![2, 1, 0]);
+        assert!(layout.culled.is_empty());
+    }
+
+    #[test]
+    fn keeps_declaration_order_between_independent_passes() {
+        let mut graph = RenderGraph::new();
+        let output = import_output(&mut graph);
+        let other = graph.import_buffer("other", vk::Buffer::null());
+
+        add_pass(&mut graph, "first", &[], &[output]);
+        graph
+            .compute_pass("second")
+            .write(other, ResourceAccess::StorageBufferWrite)
+            .execute(|_| Ok(()));
+
+        assert_eq!(graph.compile().unwrap().order, vec![0, 1]);
+    }
+
+    #[test]
+    fn culls_passes_without_consumers() {
+        let mut graph = RenderGraph::new();
+        let output = import_output(&mut graph);
+        let used = graph.create_image("used", desc());
+        let unused = graph.create_image("unused", desc());
+        let debug = graph.create_image("debug", desc());
+
+        add_pass(&mut graph, "draw", &[], &[used]);
+        add_pass(&mut graph, "dead end", &[used], &[unused]);
+        add_pass(&mut graph, "present", &[used], &[output]);
+        graph
+            .compute_pass("readback")
+            .write(debug, ResourceAccess::StorageImageWrite)
+            .side_effects()
+            .execute(|_| Ok(()));
+
+        let layout = graph.compile().unwrap();
+
+        assert_eq!(layout.order, vec![0, 2, 3]);
+        assert_eq!(layout.culled, vec![1]);
+        assert_eq!(layout.slots[unused.0], None);
+    }
+
+    #[test]
+    fn rejects_dependency_cycles() {
+        let mut graph = RenderGraph::new();
+        let output = import_output(&mut graph);
+        let x = graph.create_image("x", desc());
+        let y = graph.create_image("y", desc());
+
+        add_pass(&mut graph, "ping", &[x], &[y]);
+        add_pass(&mut graph, "pong", &[y], &[x, output]);
+
+        let err = graph.compile().err().expect("cycle was not detected");
+
+        assert!(err.to_string().contains("cycle"), "{}", err);
+    }
+
+    #[test]
+    fn reuses_slots_of_finished_transients_with_the_same_desc() {
+        let mut graph = RenderGraph::new();
+        let output = import_output(&mut graph);
+        let a = graph.create_image("a", desc());
+        let b = graph.create_image("b", desc());
+        let c = graph.create_image("c", desc());
+        let d = graph.create_image("d", ImageDesc::new(vk::Format::R16G16B16A16_SFLOAT, 64, 64));
+
+        add_pass(&mut graph, "p0", &[], &[a]);
+        add_pass(&mut graph, "p1", &[a], &[b]);
+        add_pass(&mut graph, "p2", &[b], &[c, d]);
+        add_pass(&mut graph, "p3", &[c, d], &[output]);
+
+        let layout = graph.compile().unwrap();
+
+        // a is done before c starts; b overlaps both; d has a different format
+        assert_eq!(layout.slots[a.0], Some(0));
+        assert_eq!(layout.slots[b.0], Some(1));
+        assert_eq!(layout.slots[c.0], Some(0));
+        assert_eq!(layout.slots[d.0], Some(2));
+        assert_eq!(layout.slot_descs.len(), 3);
+        assert_eq!(layout.slot_lifetimes, vec![(0, 3), (1, 2), (2, 3)]);
+        assert_eq!(layout.slots[output.0], None);
+    }
+
+    #[test]
+    fn gives_unused_transients_no_image() {
+        let mut graph = RenderGraph::new();
+        let output = import_output(&mut graph);
+        let unused = graph.create_image("unused", desc());
+        let culled = graph.create_image("culled", desc());
+
+        add_pass(&mut graph, "dead end", &[], &[culled]);
+        add_pass(&mut graph, "present", &[], &[output]);
+
+        let layout = graph.compile().unwrap();
+
+        assert!(layout.slot_descs.is_empty());
+
+        let physical = graph.physical_resources(&layout.slots, &[]);
+
+        for resource in [unused, culled] {
+            assert!(matches!(
+                physical[resource.0],
+                PhysicalResource::Image(image, view)
+                    if image == vk::Image::null() && view == vk::ImageView::null()
+            ));
+        }
+    }
+
+    fn requirements(size: u64, alignment: u64, memory_type_bits: u32) -> vk::MemoryRequirements {
+        vk::MemoryRequirements {
+            size,
+            alignment,
+            memory_type_bits,
+        }
+    }
+
+    fn lowest_memory_type(memory_type_bits: u32) -> Result<u32> {
+        Ok(memory_type_bits.trailing_zeros())
+    }
+
+    #[test]
+    fn aliases_memory_of_images_with_disjoint_lifetimes() {
+        let plan = MemoryPlan::new(
+            &[
+                (requirements(4096, 256, 1), (0, 1)),
+                (requirements(1024, 256, 1), (2, 3)),
+                (requirements(2048, 256, 1), (1, 2)),
+            ],
+            lowest_memory_type,
+        )
+        .unwrap();
+
+        // The last image only overlaps the second in time, so it takes the first one's bytes
+        assert_eq!(plan.blocks, vec![(0, 6144)]);
+        assert_eq!(plan.placements, vec![(0, 0), (0, 0), (0, 4096)]);
+        assert_eq!(plan.aliases, vec![vec![], vec![0], vec![]]);
+    }
+
+    #[test]
+    fn keeps_images_alive_together_apart_and_aligned() {
+        let plan = MemoryPlan::new(
+            &[
+                (requirements(1000, 1024, 1), (0, 2)),
+                (requirements(1000, 1024, 1), (1, 1)),
+                (requirements(100, 64, 1), (2, 3)),
+            ],
+            lowest_memory_type,
+        )
+        .unwrap();
+
+        assert_eq!(plan.placements, vec![(0, 0), (0, 1024), (0, 1024)]);
+        assert_eq!(plan.blocks, vec![(0, 2024)]);
+        assert_eq!(plan.aliases, vec![vec![], vec![], vec![1]]);
+    }
+
+    #[test]
+    fn only_aliases_images_sharing_a_memory_type() {
+        let plan = MemoryPlan::new(
+            &[
+                (requirements(256, 256, 0b01), (0, 0)),
+                (requirements(256, 256, 0b10), (1, 1)),
+                (requirements(256, 256, 0b11), (2, 2)),
+            ],
+            lowest_memory_type,
+        )
+        .unwrap();
+
+        assert_eq!(plan.blocks, vec![(0, 256), (1, 256)]);
+        assert_eq!(plan.placements, vec![(0, 0), (1, 0), (0, 0)]);
+        assert_eq!(plan.aliases, vec![vec![], vec![], vec![0]]);
+    }
+}
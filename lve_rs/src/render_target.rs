@@ -0,0 +1,698 @@
+use anyhow::{bail, Result};
+use ash::vk;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RenderTargetConfig {
+    pub extent: vk::Extent2D,
+    // None for depth-only targets such as shadow maps
+    pub color_format: Option<vk::Format>,
+    // None when the target does not need depth testing
+    pub depth_format: Option<vk::Format>,
+    pub samples: vk::SampleCountFlags,
+    pub clear_color: [f32; 4],
+    pub clear_depth: f32,
+    // Render with vkCmdBeginRendering instead of a VkRenderPass when the device supports it
+    pub dynamic_rendering: bool,
+}
+
+struct Attachment {
+    image: vk::Image,
+    memory: vk::DeviceMemory,
+    view: vk::ImageView,
+}
+
+/* MEMO
+ *  The single-sampled color (or depth, for depth-only targets) attachment ends
+ *  the pass in SHADER_READ_ONLY_OPTIMAL, so it can be bound as a texture right
+ *  after end_render_pass. With MSAA the multisampled images are transient and
+ *  get resolved into the sampled color image.
+ */
+pub struct RenderTarget {
+    config: RenderTargetConfig,
+    render_pass: vk::RenderPass,
+    framebuffer: vk::Framebuffer,
+    color: Option<Attachment>,
+    msaa_color: Option<Attachment>,
+    depth: Option<Attachment>,
+    sampler: vk::Sampler,
+}
+
+impl RenderTargetConfig {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            extent: vk::Extent2D { width, height },
+            color_format: Some(vk::Format::R8G8B8A8_SRGB),
+            depth_format: None,
+            samples: vk::SampleCountFlags::TYPE_1,
+            clear_color: [0.01, 0.01, 0.01, 1.0],
+            clear_depth: 1.0,
+            dynamic_rendering: false,
+        }
+    }
+
+    pub fn extent(&self, width: u32, height: u32) -> Self {
+        Self {
+            extent: vk::Extent2D { width, height },
+            ..self.clone()
+        }
+    }
+
+    pub fn color_format(&self, color_format: Option<vk::Format>) -> Self {
+        Self {
+            color_format,
+            ..self.clone()
+        }
+    }
+
+    pub fn depth_format(&self, depth_format: Option<vk::Format>) -> Self {
+        Self {
+            depth_format,
+            ..self.clone()
+        }
+    }
+
+    pub fn samples(&self, samples: vk::SampleCountFlags) -> Self {
+        Self {
+            samples,
+            ..self.clone()
+        }
+    }
+
+    pub fn clear_color(&self, clear_color: [f32; 4]) -> Self {
+        Self {
+            clear_color,
+            ..self.clone()
+        }
+    }
+
+    pub fn clear_depth(&self, clear_depth: f32) -> Self {
+        Self {
+            clear_depth,
+            ..self.clone()
+        }
+    }
+
+    pub fn dynamic_rendering(&self, dynamic_rendering: bool) -> Self {
+        Self {
+            dynamic_rendering,
+            ..self.clone()
+        }
+    }
+
+    #[inline]
+    fn multisampled(&self) -> bool {
+        self.samples != vk::SampleCountFlags::TYPE_1
+    }
+}
+
+impl RenderTarget {
+    pub fn new(device: &crate::Device, config: &RenderTargetConfig) -> Result<Self> {
+        if config.color_format.is_none() && config.depth_format.is_none() {
+            bail!("Render target needs a color or a depth attachment");
+        }
+        if config.color_format.is_none() && config.multisampled() {
+            bail!("Depth-only render targets cannot be multisampled");
+        }
+        if config.samples.as_raw() > device.max_usable_sample_count().as_raw() {
+            bail!("Sample count {:?} is not supported", config.samples);
+        }
+
+        let render_pass = if config.dynamic_rendering && device.supports_dynamic_rendering() {
+            vk::RenderPass::null()
+        } else {
+            Self::create_render_pass(device, config)?
+        };
+        let (color, msaa_color, depth, framebuffer) =
+            Self::create_attachments(device, config, &render_pass)?;
+        let sampler = Self::create_sampler(device)?;
+
+        Ok(Self {
+            config: config.clone(),
+            render_pass,
+            framebuffer,
+            color,
+            msaa_color,
+            depth,
+            sampler,
+        })
+    }
+
+    pub unsafe fn destroy(&mut self, device: &crate::Device) {
+        self.destroy_attachments(device);
+        device.device().destroy_sampler(self.sampler, None);
+        device.device().destroy_render_pass(self.render_pass, None);
+    }
+
+    // Keeps the render pass, so pipelines built against this target stay valid
+    pub fn resize(&mut self, device: &crate::Device, width: u32, height: u32) -> Result<()> {
+        if self.config.extent.width == width && self.config.extent.height == height {
+            return Ok(());
+        }
+
+        unsafe {
+            device.device().device_wait_idle()?;
+            self.destroy_attachments(device);
+        }
+        self.config = self.config.extent(width, height);
+        (self.color, self.msaa_color, self.depth, self.framebuffer) =
+            Self::create_attachments(device, &self.config, &self.render_pass)?;
+
+        Ok(())
+    }
+
+    #[inline]
+    pub fn config(&self) -> &RenderTargetConfig {
+        &self.config
+    }
+
+    #[inline]
+    pub fn extent(&self) -> vk::Extent2D {
+        self.config.extent
+    }
+
+    #[inline]
+    pub fn aspect_ratio(&self) -> f32 {
+        self.config.extent.width as f32 / self.config.extent.height as f32
+    }
+
+    #[inline]
+    pub fn render_pass(&self) -> &vk::RenderPass {
+        &self.render_pass
+    }
+
+    // False when dynamic rendering was requested but the device lacks it
+    #[inline]
+    pub fn uses_dynamic_rendering(&self) -> bool {
+        self.render_pass == vk::RenderPass::null()
+    }
+
+    #[inline]
+    pub fn render_pass_info(&self) -> crate::RenderPassInfo {
+        crate::RenderPassInfo {
+            render_pass: self.render_pass,
+            color_format: self.config.color_format.unwrap_or(vk::Format::UNDEFINED),
+            depth_format: self.config.depth_format.unwrap_or(vk::Format::UNDEFINED),
+            color_space: vk::ColorSpaceKHR::SRGB_NONLINEAR,
+            samples: self.config.samples,
+        }
+    }
+
+    #[inline]
+    pub fn color_image(&self) -> Option<vk::Image> {
+        self.color.as_ref().map(|color| color.image)
+    }
+
+    #[inline]
+    pub fn color_image_view(&self) -> Option<vk::ImageView> {
+        self.color.as_ref().map(|color| color.view)
+    }
+
+    #[inline]
+    pub fn depth_image_view(&self) -> Option<vk::ImageView> {
+        self.depth.as_ref().map(|depth| depth.view)
+    }
+
+    #[inline]
+    pub fn sampler(&self) -> vk::Sampler {
+        self.sampler
+    }
+
+    // Valid once the pass has ended; for DescriptorWriter::write_image
+    pub fn color_descriptor_info(&self) -> Option<vk::DescriptorImageInfo> {
+        self.color.as_ref().map(|color| {
+            vk::DescriptorImageInfo::builder()
+                .sampler(self.sampler)
+                .image_view(color.view)
+                .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+                .build()
+        })
+    }
+
+    // Only depth-only targets keep a sampleable depth image
+    pub fn depth_descriptor_info(&self) -> Option<vk::DescriptorImageInfo> {
+        if self.color.is_some() {
+            return None;
+        }
+
+        self.depth.as_ref().map(|depth| {
+            vk::DescriptorImageInfo::builder()
+                .sampler(self.sampler)
+                .image_view(depth.view)
+                .image_layout(vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL)
+                .build()
+        })
+    }
+
+    pub unsafe fn begin_render_pass(
+        &self,
+        device: &crate::Device,
+        command_buffer: &vk::CommandBuffer,
+    ) {
+        let viewport = vk::Viewport {
+            x: 0.0,
+            y: 0.0,
+            width: self.config.extent.width as f32,
+            height: self.config.extent.height as f32,
+            min_depth: 0.0,
+            max_depth: 1.0,
+        };
+        let scissor = vk::Rect2D {
+            extent: self.config.extent,
+            offset: vk::Offset2D { x: 0, y: 0 },
+        };
+
+        if self.uses_dynamic_rendering() {
+            let (color, depth) = self.rendering_attachments();
+
+            crate::dynamic_rendering::begin_rendering(
+                device,
+                command_buffer,
+                self.config.extent,
+                color.as_ref(),
+                depth.as_ref(),
+                vk::RenderingFlags::empty(),
+            );
+        } else {
+            self.begin_render_pass_with_clear_values(device, command_buffer);
+        }
+        device
+            .device()
+            .cmd_set_viewport(*command_buffer, 0, std::slice::from_ref(&viewport));
+        device
+            .device()
+            .cmd_set_scissor(*command_buffer, 0, std::slice::from_ref(&scissor));
+    }
+
+    #[inline]
+    pub unsafe fn end_render_pass(
+        &self,
+        device: &crate::Device,
+        command_buffer: &vk::CommandBuffer,
+    ) {
+        if self.uses_dynamic_rendering() {
+            let (color, depth) = self.rendering_attachments();
+
+            crate::dynamic_rendering::end_rendering(
+                device,
+                command_buffer,
+                color.as_ref(),
+                depth.as_ref(),
+            );
+        } else {
+            device.device().cmd_end_render_pass(*command_buffer);
+        }
+    }
+
+    unsafe fn begin_render_pass_with_clear_values(
+        &self,
+        device: &crate::Device,
+        command_buffer: &vk::CommandBuffer,
+    ) {
+        let mut clear_values = vec![];
+
+        if self.color.is_some() {
+            clear_values.push(vk::ClearValue {
+                color: vk::ClearColorValue {
+                    float32: self.config.clear_color,
+                },
+            });
+        }
+        if self.depth.is_some() {
+            clear_values.push(vk::ClearValue {
+                depth_stencil: vk::ClearDepthStencilValue::builder()
+                    .depth(self.config.clear_depth)
+                    .stencil(0)
+                    .build(),
+            });
+        }
+
+        let render_pass_info = vk::RenderPassBeginInfo::builder()
+            .render_pass(self.render_pass)
+            .framebuffer(self.framebuffer)
+            .render_area(vk::Rect2D {
+                offset: vk::Offset2D { x: 0, y: 0 },
+                extent: self.config.extent,
+            })
+            .clear_values(&clear_values);
+
+        device.device().cmd_begin_render_pass(
+            *command_buffer,
+            &render_pass_info,
+            vk::SubpassContents::INLINE,
+        );
+    }
+
+    // Same layouts as the render pass: the sampled image ends up shader-readable
+    fn rendering_attachments(
+        &self,
+    ) -> (
+        Option<crate::dynamic_rendering::ColorAttachment>,
+        Option<crate::dynamic_rendering::DepthAttachment>,
+    ) {
+        let color = self.color.as_ref().map(|color| {
+            let ((image, view), resolve) = match self.msaa_color.as_ref() {
+                Some(msaa_color) => (
+                    (msaa_color.image, msaa_color.view),
+                    Some((color.image, color.view)),
+                ),
+                None => ((color.image, color.view), None),
+            };
+
+            crate::dynamic_rendering::ColorAttachment {
+                image,
+                view,
+                resolve,
+                clear_color: self.config.clear_color,
+                load_op: vk::AttachmentLoadOp::CLEAR,
+                store_op: if resolve.is_some() {
+                    vk::AttachmentStoreOp::DONT_CARE
+                } else {
+                    vk::AttachmentStoreOp::STORE
+                },
+                initial_layout: vk::ImageLayout::UNDEFINED,
+                final_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            }
+        });
+        let depth = self
+            .depth
+            .as_ref()
+            .zip(self.config.depth_format)
+            .map(|(depth, format)| {
+                // Depth-only targets are sampled afterwards, so their depth is kept
+                let final_layout = self
+                    .color
+                    .is_none()
+                    .then_some(vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL);
+
+                crate::dynamic_rendering::DepthAttachment {
+                    image: depth.image,
+                    view: depth.view,
+                    format,
+                    clear_depth: self.config.clear_depth,
+                    load_op: vk::AttachmentLoadOp::CLEAR,
+                    store_op: if final_layout.is_some() {
+                        vk::AttachmentStoreOp::STORE
+                    } else {
+                        vk::AttachmentStoreOp::DONT_CARE
+                    },
+                    initial_layout: vk::ImageLayout::UNDEFINED,
+                    final_layout,
+                }
+            });
+
+        (color, depth)
+    }
+
+    unsafe fn destroy_attachments(&mut self, device: &crate::Device) {
+        device.device().destroy_framebuffer(self.framebuffer, None);
+
+        for attachment in [self.color.take(), self.msaa_color.take(), self.depth.take()]
+            .into_iter()
+            .flatten()
+        {
+            device.device().destroy_image_view(attachment.view, None);
+            device.device().destroy_image(attachment.image, None);
+            device.device().free_memory(attachment.memory, None);
+        }
+    }
+
+    fn create_render_pass(
+        device: &crate::Device,
+        config: &RenderTargetConfig,
+    ) -> Result<vk::RenderPass> {
+        let multisampled = config.multisampled();
+        // Attachment order: color, depth, resolve; must match create_attachments
+        let mut attachments = vec![];
+        let mut color_attachment = None;
+        let mut depth_stencil_attachment = None;
+        let mut resolve_attachment = None;
+
+        if let Some(color_format) = config.color_format {
+            color_attachment = Some(
+                vk::AttachmentReference::builder()
+                    .attachment(attachments.len() as u32)
+                    .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+                    .build(),
+            );
+            attachments.push(
+                vk::AttachmentDescription::builder()
+                    .format(color_format)
+                    .samples(config.samples)
+                    .load_op(vk::AttachmentLoadOp::CLEAR)
+                    .store_op(if multisampled {
+                        vk::AttachmentStoreOp::DONT_CARE
+                    } else {
+                        vk::AttachmentStoreOp::STORE
+                    })
+                    .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
+                    .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+                    .initial_layout(vk::ImageLayout::UNDEFINED)
+                    .final_layout(if multisampled {
+                        vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL
+                    } else {
+                        vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL
+                    })
+                    .build(),
+            );
+        }
+        if let Some(depth_format) = config.depth_format {
+            let depth_only = config.color_format.is_none();
+
+            depth_stencil_attachment = Some(
+                vk::AttachmentReference::builder()
+                    .attachment(attachments.len() as u32)
+                    .layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL)
+                    .build(),
+            );
+            attachments.push(
+                vk::AttachmentDescription::builder()
+                    .format(depth_format)
+                    .samples(config.samples)
+                    .load_op(vk::AttachmentLoadOp::CLEAR)
+                    .store_op(if depth_only {
+                        vk::AttachmentStoreOp::STORE
+                    } else {
+                        vk::AttachmentStoreOp::DONT_CARE
+                    })
+                    .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
+                    .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+                    .initial_layout(vk::ImageLayout::UNDEFINED)
+                    .final_layout(if depth_only {
+                        vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL
+                    } else {
+                        vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL
+                    })
+                    .build(),
+            );
+        }
+        if let (Some(color_format), true) = (config.color_format, multisampled) {
+            resolve_attachment = Some(
+                vk::AttachmentReference::builder()
+                    .attachment(attachments.len() as u32)
+                    .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+                    .build(),
+            );
+            attachments.push(
+                vk::AttachmentDescription::builder()
+                    .format(color_format)
+                    .samples(vk::SampleCountFlags::TYPE_1)
+                    .load_op(vk::AttachmentLoadOp::DONT_CARE)
+                    .store_op(vk::AttachmentStoreOp::STORE)
+                    .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
+                    .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+                    .initial_layout(vk::ImageLayout::UNDEFINED)
+                    .final_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+                    .build(),
+            );
+        }
+
+        let subpass = {
+            let mut subpass = vk::SubpassDescription::builder()
+                .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS);
+
+            if let Some(color_attachment) = color_attachment.as_ref() {
+                subpass = subpass.color_attachments(std::slice::from_ref(color_attachment));
+            }
+            if let Some(depth_stencil_attachment) = depth_stencil_attachment.as_ref() {
+                subpass = subpass.depth_stencil_attachment(depth_stencil_attachment);
+            }
+            if let Some(resolve_attachment) = resolve_attachment.as_ref() {
+                subpass = subpass.resolve_attachments(std::slice::from_ref(resolve_attachment));
+            }
+
+            subpass.build()
+        };
+        let attachment_stages = vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+            | vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS
+            | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS;
+        let attachment_writes = vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+            | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE;
+        // Previous frame's sampling must finish before we overwrite the target, and our
+        //  writes must be visible before anyone samples it
+        let dependencies = [
+            vk::SubpassDependency::builder()
+                .src_subpass(vk::SUBPASS_EXTERNAL)
+                .src_stage_mask(vk::PipelineStageFlags::FRAGMENT_SHADER)
+                .src_access_mask(vk::AccessFlags::SHADER_READ)
+                .dst_subpass(0)
+                .dst_stage_mask(attachment_stages)
+                .dst_access_mask(attachment_writes)
+                .dependency_flags(vk::DependencyFlags::BY_REGION)
+                .build(),
+            vk::SubpassDependency::builder()
+                .src_subpass(0)
+                .src_stage_mask(attachment_stages)
+                .src_access_mask(attachment_writes)
+                .dst_subpass(vk::SUBPASS_EXTERNAL)
+                .dst_stage_mask(vk::PipelineStageFlags::FRAGMENT_SHADER)
+                .dst_access_mask(vk::AccessFlags::SHADER_READ)
+                .dependency_flags(vk::DependencyFlags::BY_REGION)
+                .build(),
+        ];
+        let create_info = vk::RenderPassCreateInfo::builder()
+            .attachments(&attachments)
+            .subpasses(std::slice::from_ref(&subpass))
+            .dependencies(&dependencies);
+
+        Ok(unsafe { device.device().create_render_pass(&create_info, None) }?)
+    }
+
+    fn create_attachments(
+        device: &crate::Device,
+        config: &RenderTargetConfig,
+        render_pass: &vk::RenderPass,
+    ) -> Result<(
+        Option<Attachment>,
+        Option<Attachment>,
+        Option<Attachment>,
+        vk::Framebuffer,
+    )> {
+        let multisampled = config.multisampled();
+        let color = match config.color_format {
+            Some(color_format) => Some(Self::create_attachment(
+                device,
+                config.extent,
+                color_format,
+                vk::SampleCountFlags::TYPE_1,
+                // TRANSFER_SRC allows reading the result back, e.g. for screenshots
+                vk::ImageUsageFlags::COLOR_ATTACHMENT
+                    | vk::ImageUsageFlags::SAMPLED
+                    | vk::ImageUsageFlags::TRANSFER_SRC,
+                vk::ImageAspectFlags::COLOR,
+            )?),
+            None => None,
+        };
+        let msaa_color = match config.color_format {
+            Some(color_format) if multisampled => Some(Self::create_attachment(
+                device,
+                config.extent,
+                color_format,
+                config.samples,
+                vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSIENT_ATTACHMENT,
+                vk::ImageAspectFlags::COLOR,
+            )?),
+            _ => None,
+        };
+        let depth = match config.depth_format {
+            Some(depth_format) => Some(Self::create_attachment(
+                device,
+                config.extent,
+                depth_format,
+                config.samples,
+                if config.color_format.is_none() {
+                    vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT | vk::ImageUsageFlags::SAMPLED
+                } else {
+                    vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT
+                },
+                vk::ImageAspectFlags::DEPTH,
+            )?),
+            None => None,
+        };
+        let framebuffer = if *render_pass == vk::RenderPass::null() {
+            vk::Framebuffer::null()
+        } else {
+            // Must match the attachment order of create_render_pass
+            let attachments = if multisampled {
+                [msaa_color.as_ref(), depth.as_ref(), color.as_ref()]
+            } else {
+                [color.as_ref(), depth.as_ref(), None]
+            }
+            .into_iter()
+            .flatten()
+            .map(|attachment| attachment.view)
+            .collect::<Vec<_>>();
+            let create_info = vk::FramebufferCreateInfo::builder()
+                .render_pass(*render_pass)
+                .attachments(&attachments)
+                .width(config.extent.width)
+                .height(config.extent.height)
+                .layers(1);
+
+            unsafe { device.device().create_framebuffer(&create_info, None) }?
+        };
+
+        Ok((color, msaa_color, depth, framebuffer))
+    }
+
+    fn create_attachment(
+        device: &crate::Device,
+        extent: vk::Extent2D,
+        format: vk::Format,
+        samples: vk::SampleCountFlags,
+        usage: vk::ImageUsageFlags,
+        aspect_mask: vk::ImageAspectFlags,
+    ) -> Result<Attachment> {
+        let image_info = vk::ImageCreateInfo::builder()
+            .image_type(vk::ImageType::TYPE_2D)
+            .extent(vk::Extent3D {
+                width: extent.width,
+                height: extent.height,
+                depth: 1,
+            })
+            .mip_levels(1)
+            .array_layers(1)
+            .format(format)
+            .tiling(vk::ImageTiling::OPTIMAL)
+            .initial_layout(vk::ImageLayout::UNDEFINED)
+            .usage(usage)
+            .samples(samples)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE);
+        let (image, memory) =
+            device.create_image_with_info(&image_info, vk::MemoryPropertyFlags::DEVICE_LOCAL)?;
+        let view = {
+            let create_info = vk::ImageViewCreateInfo::builder()
+                .image(image)
+                .view_type(vk::ImageViewType::TYPE_2D)
+                .format(format)
+                .subresource_range(vk::ImageSubresourceRange {
+                    aspect_mask,
+                    base_mip_level: 0,
+                    level_count: 1,
+                    base_array_layer: 0,
+                    layer_count: 1,
+                });
+
+            unsafe { device.device().create_image_view(&create_info, None) }?
+        };
+
+        Ok(Attachment {
+            image,
+            memory,
+            view,
+        })
+    }
+
+    fn create_sampler(device: &crate::Device) -> Result<vk::Sampler> {
+        let create_info = vk::SamplerCreateInfo::builder()
+            .mag_filter(vk::Filter::LINEAR)
+            .min_filter(vk::Filter::LINEAR)
+            .mipmap_mode(vk::SamplerMipmapMode::LINEAR)
+            .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .max_lod(1.0)
+            .border_color(vk::BorderColor::FLOAT_OPAQUE_WHITE);
+
+        Ok(unsafe { device.device().create_sampler(&create_info, None) }?)
+    }
+}
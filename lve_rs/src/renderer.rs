@@ -1,38 +1,144 @@
-use anyhow::{bail, Result};
+use anyhow::{bail, Context, Result};
 use ash::vk;
+use std::{path::PathBuf, thread::JoinHandle};
 use winit::event_loop::ControlFlow;
 
 pub struct Renderer {
+    // None when presenting to the device's own surface
+    surface: Option<crate::Surface>,
     swap_chain: Box<crate::SwapChain>,
     command_buffers: Vec<vk::CommandBuffer>,
     current_image_index: usize,
     current_frame_index: usize,
     frame_started: bool,
+    clear_values: crate::ClearValues,
+    render_pass_ops: crate::RenderPassOps,
+    // Recreation deferred while the window is minimized
+    pending_swap_chain_config: Option<crate::SwapChainConfig>,
+    pending_captures: Vec<crate::capture::PendingCapture>,
+    capture_threads: Vec<JoinHandle<Result<PathBuf>>>,
+    render_systems: crate::RenderSystemRegistry,
+    // None records the swap chain render pass inline, on the main thread
+    command_recorder: Option<crate::CommandRecorder>,
 }
 
 impl Renderer {
     pub fn new(window: &crate::Window, device: &crate::Device) -> Result<Self> {
-        let swap_chain = Self::recreate_swap_chain(&window, &device, None, None)?;
-        let command_buffers = Self::create_command_buffers(&device)?;
+        Self::with_config(window, device, &crate::SwapChainConfig::default())
+    }
+
+    pub fn with_config(
+        window: &crate::Window,
+        device: &crate::Device,
+        config: &crate::SwapChainConfig,
+    ) -> Result<Self> {
+        Self::init(window, device, None, config)
+    }
+
+    /* MEMO
+     *  Renders into an additional window that shares `device` with the main one.
+     *  Takes ownership of `surface` (see Device::create_surface) and destroys it along
+     *  with the swap chain.
+     *
+     *  let surface = device.create_surface(&preview_window)?;
+     *  let preview_renderer = Renderer::with_surface(&preview_window, &device, surface, &config)?;
+     */
+    pub fn with_surface(
+        window: &crate::Window,
+        device: &crate::Device,
+        surface: crate::Surface,
+        config: &crate::SwapChainConfig,
+    ) -> Result<Self> {
+        Self::init(window, device, Some(surface), config)
+    }
+
+    pub unsafe fn destroy(&mut self, device: &crate::Device) {
+        self.discard_captures(device);
+        for capture_thread in self.capture_threads.drain(..) {
+            Self::report_capture(capture_thread);
+        }
+        self.render_systems.destroy(device);
+        if let Some(mut command_recorder) = self.command_recorder.take() {
+            command_recorder.destroy(device);
+        }
+        Self::free_command_buffers(device, &mut self.command_buffers);
+        self.swap_chain.destroy(device);
+        if let Some(surface) = self.surface.take() {
+            surface.destroy_surface();
+        }
+    }
+
+    // The surface this renderer presents to
+    #[inline]
+    pub fn surface<'a>(&'a self, device: &'a crate::Device) -> &'a crate::Surface {
+        self.surface.as_ref().unwrap_or_else(|| device.surface())
+    }
+
+    fn init(
+        window: &crate::Window,
+        device: &crate::Device,
+        surface: Option<crate::Surface>,
+        config: &crate::SwapChainConfig,
+    ) -> Result<Self> {
+        let swap_chain = match Self::recreate_swap_chain(
+            window,
+            device,
+            surface.as_ref().unwrap_or_else(|| device.surface()),
+            config,
+            None,
+        ) {
+            Ok(swap_chain) => swap_chain,
+            Err(err) => {
+                if let Some(surface) = surface {
+                    unsafe { surface.destroy_surface() };
+                }
+                return Err(err);
+            }
+        };
+        let command_buffers = Self::create_command_buffers(device, config.frames_in_flight)?;
 
         Ok(Self {
+            surface,
             swap_chain,
             command_buffers,
             current_image_index: 0,
             current_frame_index: 0,
             frame_started: false,
+            clear_values: crate::ClearValues::default(),
+            render_pass_ops: crate::RenderPassOps::default(),
+            pending_swap_chain_config: None,
+            pending_captures: vec![],
+            capture_threads: vec![],
+            render_systems: crate::RenderSystemRegistry::new(),
+            command_recorder: None,
         })
     }
 
-    pub unsafe fn destroy(&mut self, device: &crate::Device) {
-        Self::free_command_buffers(device, &mut self.command_buffers);
-        self.swap_chain.destroy(device);
-    }
-
     pub const fn swap_chain_render_pass(&self) -> &vk::RenderPass {
         self.swap_chain.render_pass()
     }
 
+    #[inline]
+    pub fn swap_chain_render_pass_info(&self) -> crate::RenderPassInfo {
+        self.swap_chain.render_pass_info()
+    }
+
+    #[inline]
+    pub fn msaa_samples(&self) -> vk::SampleCountFlags {
+        self.swap_chain.msaa_samples()
+    }
+
+    // Per-frame resources (UBOs, descriptor sets, ...) should be sized from this
+    #[inline]
+    pub fn frames_in_flight(&self) -> usize {
+        self.command_buffers.len()
+    }
+
+    #[inline]
+    pub fn image_count(&self) -> usize {
+        self.swap_chain.image_count()
+    }
+
     pub fn aspect_ratio(&self) -> f32 {
         self.swap_chain.extent_aspect_ratio() as f32
     }
@@ -41,6 +147,179 @@ impl Renderer {
         &self.swap_chain
     }
 
+    #[inline]
+    pub fn present_mode(&self) -> vk::PresentModeKHR {
+        self.swap_chain.present_mode()
+    }
+
+    // Recreates the swap chain right away; returns the mode the surface actually accepted
+    pub fn set_present_mode(
+        &mut self,
+        window: &crate::Window,
+        device: &crate::Device,
+        present_mode: crate::PresentMode,
+    ) -> Result<vk::PresentModeKHR> {
+        assert!(
+            !self.frame_started,
+            "Can't change present mode while frame is in progress"
+        );
+
+        if self.swap_chain.config().present_mode != present_mode {
+            let config = self.swap_chain.config().present_mode(present_mode);
+
+            self.replace_swap_chain(window, device, &config, None)?;
+        }
+
+        Ok(self.present_mode())
+    }
+
+    #[inline]
+    pub fn swap_chain_config(&self) -> &crate::SwapChainConfig {
+        self.swap_chain.config()
+    }
+
+    // Recreates the swap chain right away, e.g. with another MSAA sample count; render
+    //  systems rebuild their pipelines when the render pass is no longer compatible
+    pub fn set_swap_chain_config(
+        &mut self,
+        window: &crate::Window,
+        device: &crate::Device,
+        config: &crate::SwapChainConfig,
+    ) -> Result<()> {
+        assert!(
+            !self.frame_started,
+            "Can't change swap chain config while frame is in progress"
+        );
+
+        if config.frames_in_flight != self.frames_in_flight() {
+            bail!(
+                "Frames in flight can't change from {} to {} after the renderer is created",
+                self.frames_in_flight(),
+                config.frames_in_flight
+            );
+        }
+
+        self.replace_swap_chain(window, device, config, None)
+    }
+
+    // `system` has to be built for swap_chain_render_pass_info; it is destroyed with the renderer
+    pub fn add_render_system<S: crate::RenderSystem + 'static>(&mut self, system: S) {
+        self.render_systems.add(
+            Box::new(system),
+            self.swap_chain.render_pass_info(),
+            self.swap_chain.swap_chain_extent(),
+        );
+    }
+
+    #[inline]
+    pub fn render_systems(&self) -> &crate::RenderSystemRegistry {
+        &self.render_systems
+    }
+
+    #[inline]
+    pub fn render_systems_mut(&mut self) -> &mut crate::RenderSystemRegistry {
+        &mut self.render_systems
+    }
+
+    /* MEMO
+     *  With worker threads the swap chain render pass only takes secondary command
+     *  buffers: record_render_systems records every system into them (see
+     *  RenderSystem::record_secondary) and executes them in the pass. Commands can
+     *  no longer be recorded into the pass directly. 0 goes back to recording
+     *  inline on the main thread.
+     *  frame_stats is told about the switch, since its query has to be inherited
+     *  by the secondary command buffers.
+     */
+    pub fn set_worker_threads(
+        &mut self,
+        device: &crate::Device,
+        worker_threads: usize,
+        frame_stats: Option<&mut crate::FrameStatsCollector>,
+    ) -> Result<()> {
+        assert!(
+            !self.frame_started,
+            "Can't change worker threads while frame is in progress"
+        );
+
+        if self.worker_threads() != worker_threads {
+            unsafe { device.device().device_wait_idle() }?;
+            if let Some(mut command_recorder) = self.command_recorder.take() {
+                unsafe { command_recorder.destroy(device) };
+            }
+            if worker_threads > 0 {
+                self.command_recorder = Some(crate::CommandRecorder::new(
+                    device,
+                    worker_threads,
+                    self.frames_in_flight(),
+                )?);
+            }
+        }
+        if let Some(frame_stats) = frame_stats {
+            frame_stats.set_secondary_command_buffers(device, worker_threads > 0);
+        }
+
+        Ok(())
+    }
+
+    #[inline]
+    pub fn worker_threads(&self) -> usize {
+        self.command_recorder
+            .as_ref()
+            .map_or(0, |command_recorder| command_recorder.worker_threads())
+    }
+
+    #[inline]
+    pub fn surface_format(&self) -> vk::SurfaceFormatKHR {
+        vk::SurfaceFormatKHR {
+            format: self.swap_chain.swap_chain_image_format(),
+            color_space: self.swap_chain.swap_chain_color_space(),
+        }
+    }
+
+    // Set on the current swap chain and stored in the config, so it is re-applied
+    //  whenever the swap chain is recreated; returns whether it took effect
+    pub fn set_hdr_metadata(
+        &mut self,
+        device: &crate::Device,
+        hdr_metadata: Option<crate::HdrMetadata>,
+    ) -> Result<bool> {
+        if let Some(config) = self.pending_swap_chain_config.as_mut() {
+            config.hdr_metadata = hdr_metadata;
+        }
+
+        self.swap_chain.update_hdr_metadata(device, hdr_metadata)
+    }
+
+    #[inline]
+    pub fn clear_values(&self) -> crate::ClearValues {
+        self.clear_values
+    }
+
+    // Used by every following begin_swap_chain_render_pass, including in this frame
+    #[inline]
+    pub fn set_clear_values(&mut self, clear_values: crate::ClearValues) {
+        self.clear_values = clear_values;
+    }
+
+    #[inline]
+    pub fn render_pass_ops(&self) -> crate::RenderPassOps {
+        self.render_pass_ops
+    }
+
+    // Can change between swap chain passes of a frame, e.g. Clear for the first and
+    //  Load for one drawing on top of it
+    pub fn set_render_pass_ops(
+        &mut self,
+        device: &crate::Device,
+        render_pass_ops: crate::RenderPassOps,
+    ) -> Result<()> {
+        self.swap_chain
+            .prepare_render_pass(device, &render_pass_ops)?;
+        self.render_pass_ops = render_pass_ops;
+
+        Ok(())
+    }
+
     pub const fn frame_started(&self) -> bool {
         self.frame_started
     }
@@ -54,6 +333,12 @@ impl Renderer {
         &self.command_buffers[self.current_frame_index]
     }
 
+    // Wait on Device::graphics_timeline for this value to know the frame has finished
+    #[inline]
+    pub fn last_submitted_value(&self) -> u64 {
+        self.swap_chain.last_submitted_value()
+    }
+
     pub const fn frame_index(&self) -> usize {
         assert!(
             self.frame_started,
@@ -63,6 +348,19 @@ impl Renderer {
         self.current_frame_index
     }
 
+    // Image and view acquired for this frame, e.g. to import into a RenderGraph
+    pub fn current_swap_chain_image(&self) -> (vk::Image, vk::ImageView) {
+        assert!(
+            self.frame_started,
+            "Cannot get swap chain image when frame not in progress"
+        );
+
+        (
+            self.swap_chain.image(self.current_image_index),
+            *self.swap_chain.image_view(self.current_image_index),
+        )
+    }
+
     pub fn begin_frame(
         &mut self,
         window: &crate::Window,
@@ -74,21 +372,26 @@ impl Renderer {
             "Can't call begin_frame while already in progress"
         );
 
-        let device_ref = device.device();
+        // Rendering is suspended while minimized; the null command buffer skips the frame
+        if window.is_minimized() {
+            if let Some(control_flow) = control_flow {
+                *control_flow = ControlFlow::Wait;
+            }
+
+            return Ok(vk::CommandBuffer::null());
+        }
+        if let Some(config) = self.pending_swap_chain_config.take() {
+            self.replace_swap_chain(window, device, &config, control_flow)?;
+
+            return Ok(vk::CommandBuffer::null());
+        }
+
         (self.current_image_index, _) = match self.swap_chain.acquire_next_image(device) {
             Ok((image_index, result)) => {
                 if result {
-                    let swap_chain = Self::recreate_swap_chain(
-                        window,
-                        device,
-                        Some(&self.swap_chain),
-                        control_flow,
-                    )?;
-                    unsafe { device_ref.device_wait_idle() }?;
-                    unsafe {
-                        self.swap_chain.destroy(device);
-                    }
-                    self.swap_chain = swap_chain;
+                    let config = self.swap_chain.config().clone();
+
+                    self.replace_swap_chain(window, device, &config, control_flow)?;
 
                     return Ok(vk::CommandBuffer::null());
                 }
@@ -128,30 +431,27 @@ impl Renderer {
             "Can't call end_frame while frame is not in progress"
         );
 
-        let device_ref = device.device();
         let command_buffer = *self.current_command_buffer();
 
         unsafe { device.device().end_command_buffer(command_buffer) }?;
-        match self.swap_chain.submit_command_buffers(
+        let submit_result = self.swap_chain.submit_command_buffers(
             device,
             &command_buffer,
             self.current_image_index,
-        ) {
+        );
+        if submit_result.is_ok() {
+            self.finish_captures(device)?;
+        } else {
+            unsafe { self.discard_captures(device) };
+        }
+        match submit_result {
             Ok(window_resized) => {
                 self.frame_started = false;
                 if window_resized || window.was_window_resized() {
                     window.reset_window_resized_flag();
-                    let swap_chain = Self::recreate_swap_chain(
-                        window,
-                        device,
-                        Some(&self.swap_chain),
-                        control_flow,
-                    )?;
-                    unsafe { device_ref.device_wait_idle() }?;
-                    unsafe {
-                        self.swap_chain.destroy(device);
-                    }
-                    self.swap_chain = swap_chain;
+                    let config = self.swap_chain.config().clone();
+
+                    self.replace_swap_chain(window, device, &config, control_flow)?;
 
                     return Ok(());
                 }
@@ -160,17 +460,9 @@ impl Renderer {
             Err(_) => {
                 if window.was_window_resized() {
                     window.reset_window_resized_flag();
-                    let swap_chain = Self::recreate_swap_chain(
-                        window,
-                        device,
-                        Some(&self.swap_chain),
-                        control_flow,
-                    )?;
-                    unsafe { device_ref.device_wait_idle() }?;
-                    unsafe {
-                        self.swap_chain.destroy(device);
-                    }
-                    self.swap_chain = swap_chain;
+                    let config = self.swap_chain.config().clone();
+
+                    self.replace_swap_chain(window, device, &config, control_flow)?;
                     self.frame_started = false;
 
                     return Ok(());
@@ -180,8 +472,74 @@ impl Renderer {
             }
         };
 
-        self.current_frame_index =
-            (self.current_frame_index + 1) % crate::SwapChain::MAX_FRAMES_IN_FLIGHT as usize;
+        self.current_frame_index = (self.current_frame_index + 1) % self.frames_in_flight();
+
+        Ok(())
+    }
+
+    // Copies the swap chain image of the current frame to a PNG (or raw RGBA8
+    //  if `path` ends in .raw); call after end_swap_chain_render_pass and before end_frame
+    pub fn capture_frame<P: Into<PathBuf>>(
+        &mut self,
+        device: &crate::Device,
+        path: P,
+    ) -> Result<()> {
+        assert!(
+            self.frame_started,
+            "Can't call capture_frame while frame is not in progress"
+        );
+
+        if !self.swap_chain.supports_capture() {
+            bail!("Swap chain images don't support TRANSFER_SRC, so frames can't be captured");
+        }
+
+        let pending_capture = unsafe {
+            crate::capture::PendingCapture::record(
+                device,
+                self.current_command_buffer(),
+                self.swap_chain.image(self.current_image_index),
+                vk::ImageLayout::PRESENT_SRC_KHR,
+                self.swap_chain.swap_chain_extent(),
+                self.swap_chain.swap_chain_image_format(),
+                path.into(),
+            )
+        }?;
+
+        self.pending_captures.push(pending_capture);
+
+        Ok(())
+    }
+
+    // Same as capture_frame, for the color attachment of an offscreen target
+    pub fn capture_render_target<P: Into<PathBuf>>(
+        &mut self,
+        device: &crate::Device,
+        render_target: &crate::RenderTarget,
+        path: P,
+    ) -> Result<()> {
+        assert!(
+            self.frame_started,
+            "Can't call capture_render_target while frame is not in progress"
+        );
+
+        let pending_capture = unsafe {
+            crate::capture::PendingCapture::record(
+                device,
+                self.current_command_buffer(),
+                render_target
+                    .color_image()
+                    .context("Render target has no color attachment to capture")?,
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                render_target.extent(),
+                render_target
+                    .config()
+                    .color_format
+                    .context("Render target has no color attachment to capture")?,
+                path.into(),
+            )
+        }?;
+
+        self.pending_captures.push(pending_capture);
 
         Ok(())
     }
@@ -200,27 +558,6 @@ impl Renderer {
             "Can't begin render pass on command buffer from a different frame"
         );
 
-        let clear_values = [
-            vk::ClearValue {
-                color: vk::ClearColorValue {
-                    float32: [0.01f32, 0.01f32, 0.01f32, 1.0f32],
-                },
-            },
-            vk::ClearValue {
-                depth_stencil: vk::ClearDepthStencilValue::builder()
-                    .depth(1.0f32)
-                    .stencil(0)
-                    .build(),
-            },
-        ];
-        let render_pass_info = vk::RenderPassBeginInfo::builder()
-            .render_pass(*self.swap_chain.render_pass())
-            .framebuffer(*self.swap_chain.framebuffer(self.current_image_index))
-            .render_area(vk::Rect2D {
-                offset: vk::Offset2D { x: 0, y: 0 },
-                extent: self.swap_chain.swap_chain_extent(),
-            })
-            .clear_values(&clear_values);
         let viewport = vk::Viewport {
             x: 0.0,
             y: 0.0,
@@ -233,18 +570,130 @@ impl Renderer {
             extent: self.swap_chain.swap_chain_extent(),
             offset: vk::Offset2D { x: 0, y: 0 },
         };
+        let contents = if self.command_recorder.is_some() {
+            vk::SubpassContents::SECONDARY_COMMAND_BUFFERS
+        } else {
+            vk::SubpassContents::INLINE
+        };
+
+        if self.swap_chain.uses_dynamic_rendering() {
+            self.swap_chain.begin_rendering(
+                device,
+                command_buffer,
+                self.current_image_index,
+                &self.clear_values,
+                &self.render_pass_ops,
+                contents,
+            );
+        } else {
+            let clear_values = self.clear_values.vk_clear_values();
+            let render_pass = self
+                .swap_chain
+                .render_pass_for(&self.render_pass_ops)
+                .expect("Render pass ops were not prepared");
+            let render_pass_info = vk::RenderPassBeginInfo::builder()
+                .render_pass(render_pass)
+                .framebuffer(*self.swap_chain.framebuffer(self.current_image_index))
+                .render_area(vk::Rect2D {
+                    offset: vk::Offset2D { x: 0, y: 0 },
+                    extent: self.swap_chain.swap_chain_extent(),
+                })
+                .clear_values(&clear_values);
 
-        device.device().cmd_begin_render_pass(
-            *command_buffer,
-            &render_pass_info,
-            vk::SubpassContents::INLINE,
+            device
+                .device()
+                .cmd_begin_render_pass(*command_buffer, &render_pass_info, contents);
+        }
+        // Secondary command buffers set their own, see SecondaryInheritance
+        if contents == vk::SubpassContents::INLINE {
+            device
+                .device()
+                .cmd_set_viewport(*command_buffer, 0, std::slice::from_ref(&viewport));
+            device
+                .device()
+                .cmd_set_scissor(*command_buffer, 0, std::slice::from_ref(&scissor));
+        }
+    }
+
+    // Records the render systems into the swap chain render pass, on the worker threads if there are any
+    pub unsafe fn record_render_systems(
+        &mut self,
+        device: &crate::Device,
+        frame_info: &mut crate::FrameInfo,
+        profiler: Option<&mut crate::GpuProfiler>,
+    ) -> Result<()> {
+        assert!(
+            self.frame_started,
+            "Can't call record_render_systems while frame is not in progress"
         );
-        device
-            .device()
-            .cmd_set_viewport(*command_buffer, 0, std::slice::from_ref(&viewport));
-        device
-            .device()
-            .cmd_set_scissor(*command_buffer, 0, std::slice::from_ref(&scissor));
+
+        let command_buffer = *self.current_command_buffer();
+        let inheritance = self.secondary_inheritance(device);
+        let command_recorder = match self.command_recorder.as_mut() {
+            Some(command_recorder) => command_recorder,
+            None => {
+                self.render_systems.render(device, frame_info, profiler);
+
+                return Ok(());
+            }
+        };
+        let mut recorder =
+            command_recorder.begin_frame(device, self.current_frame_index, inheritance)?;
+
+        self.render_systems
+            .record_secondary(device, frame_info, &mut recorder, profiler)?;
+        recorder.execute(device, &command_buffer);
+
+        Ok(())
+    }
+
+    // For secondary command buffers executed in the swap chain render pass of the current frame
+    pub fn secondary_inheritance(&self, device: &crate::Device) -> crate::SecondaryInheritance {
+        assert!(
+            self.frame_started,
+            "Cannot get secondary inheritance when frame not in progress"
+        );
+
+        let render_pass_info = self.swap_chain.render_pass_info();
+        let extent = self.swap_chain.swap_chain_extent();
+        let features = device.enabled_features();
+        let (render_pass, framebuffer) =
+            match self.swap_chain.render_pass_for(&self.render_pass_ops) {
+                Some(render_pass) => (
+                    render_pass,
+                    *self.swap_chain.framebuffer(self.current_image_index),
+                ),
+                None => (vk::RenderPass::null(), vk::Framebuffer::null()),
+            };
+
+        crate::SecondaryInheritance {
+            render_pass,
+            framebuffer,
+            color_format: render_pass_info.color_format,
+            depth_format: render_pass_info.depth_format,
+            samples: render_pass_info.samples,
+            viewport: vk::Viewport {
+                x: 0.0,
+                y: 0.0,
+                width: extent.width as f32,
+                height: extent.height as f32,
+                min_depth: 0.0,
+                max_depth: 1.0,
+            },
+            scissor: vk::Rect2D {
+                extent,
+                offset: vk::Offset2D { x: 0, y: 0 },
+            },
+            // FrameStatsCollector keeps its query active across the render pass,
+            // see FrameStatsCollector::set_secondary_command_buffers
+            pipeline_statistics: if features.pipeline_statistics_query != 0
+                && features.inherited_queries != 0
+            {
+                crate::FrameStatsCollector::STATISTICS
+            } else {
+                vk::QueryPipelineStatisticFlags::empty()
+            },
+        }
     }
 
     pub unsafe fn end_swap_chain_render_pass(
@@ -261,51 +710,147 @@ impl Renderer {
             "Can't end render pass on command buffer from a different frame"
         );
 
-        device.device().cmd_end_render_pass(*command_buffer);
+        if self.swap_chain.uses_dynamic_rendering() {
+            self.swap_chain
+                .end_rendering(device, command_buffer, self.current_image_index);
+        } else {
+            device.device().cmd_end_render_pass(*command_buffer);
+        }
+    }
+
+    fn finish_captures(&mut self, device: &crate::Device) -> Result<()> {
+        let (finished, running) = self
+            .capture_threads
+            .drain(..)
+            .partition(|capture_thread| capture_thread.is_finished());
+
+        self.capture_threads = running;
+        finished.into_iter().for_each(Self::report_capture);
+
+        if self.pending_captures.is_empty() {
+            return Ok(());
+        }
+
+        // Stalls, but only on frames that take a capture
+        device.graphics_timeline().wait(
+            device,
+            self.swap_chain.last_submitted_value(),
+            u64::MAX,
+        )?;
+        for pending_capture in std::mem::take(&mut self.pending_captures) {
+            let (captured_frame, path) = unsafe { pending_capture.read_back(device) }?;
+
+            self.capture_threads.push(captured_frame.save_async(path));
+        }
+
+        Ok(())
+    }
+
+    unsafe fn discard_captures(&mut self, device: &crate::Device) {
+        if self.pending_captures.is_empty() {
+            return;
+        }
+
+        let _ = device.graphics_timeline().wait(
+            device,
+            device.graphics_timeline().last_value(),
+            u64::MAX,
+        );
+        for pending_capture in std::mem::take(&mut self.pending_captures) {
+            pending_capture.discard(device);
+        }
+    }
+
+    fn report_capture(capture_thread: JoinHandle<Result<PathBuf>>) {
+        match capture_thread.join() {
+            Ok(Ok(path)) => println!("Saved screenshot to {:?}", path),
+            Ok(Err(e)) => eprintln!("Failed to save screenshot: {:?}", e),
+            Err(_) => eprintln!("Screenshot thread panicked"),
+        }
+    }
+
+    fn replace_swap_chain(
+        &mut self,
+        window: &crate::Window,
+        device: &crate::Device,
+        config: &crate::SwapChainConfig,
+        control_flow: Option<&mut ControlFlow>,
+    ) -> Result<()> {
+        // A zero-sized swap chain can't exist, so keep the old one until the window is restored
+        if window.is_minimized() {
+            self.pending_swap_chain_config = Some(config.clone());
+            if let Some(control_flow) = control_flow {
+                *control_flow = ControlFlow::Wait;
+            }
+
+            return Ok(());
+        }
+
+        let swap_chain = Self::recreate_swap_chain(
+            window,
+            device,
+            self.surface(device),
+            config,
+            Some(&self.swap_chain),
+        )?;
+
+        self.pending_swap_chain_config = None;
+
+        unsafe { device.device().device_wait_idle() }?;
+        unsafe {
+            self.swap_chain.destroy(device);
+        }
+        self.swap_chain = swap_chain;
+        self.swap_chain
+            .prepare_render_pass(device, &self.render_pass_ops)?;
+        self.render_systems.swap_chain_changed(
+            device,
+            self.swap_chain.render_pass_info(),
+            self.swap_chain.swap_chain_extent(),
+        )?;
+
+        Ok(())
     }
 
     fn recreate_swap_chain(
         window: &crate::Window,
         device: &crate::Device,
+        surface: &crate::Surface,
+        config: &crate::SwapChainConfig,
         old_swap_chain: Option<&crate::SwapChain>,
-        mut control_flow: Option<&mut ControlFlow>,
     ) -> Result<Box<crate::SwapChain>> {
         let device_ref = device.device();
-        let mut extent = window.extent()?;
+        let extent = window.extent()?;
 
-        while extent.width == 0 || extent.height == 0 {
-            extent = window.extent()?;
-            if let Some(ref mut control_flow_mut_ref) = control_flow {
-                **control_flow_mut_ref = ControlFlow::Wait;
-            }
+        if extent.width == 0 || extent.height == 0 {
+            bail!("Can't create a swap chain for a minimized window");
         }
         // Wait until current swap chain is out of use
         unsafe { device_ref.device_wait_idle() }?;
 
-        let swap_chain = if let Some(old_swap_chain) = old_swap_chain {
-            let swap_chain = crate::SwapChain::with_previous_swap_chain(
-                device,
-                extent,
-                old_swap_chain.swap_chain(),
-            )?;
-
-            if !old_swap_chain.compare_swap_formats(&swap_chain) {
-                bail!("Swap chain image or depth format has changed!");
-            }
-
-            swap_chain
-        } else {
-            crate::SwapChain::new(device, extent)?
-        };
+        // The formats may differ from the old swap chain's (e.g. the surface switched
+        //  to HDR); replace_swap_chain rebuilds the render systems' pipelines then
+        let swap_chain = crate::SwapChain::with_surface(
+            device,
+            surface,
+            extent,
+            config,
+            old_swap_chain.map_or(&vk::SwapchainKHR::null(), |old_swap_chain| {
+                old_swap_chain.swap_chain()
+            }),
+        )?;
 
         Ok(Box::new(swap_chain))
     }
 
-    fn create_command_buffers(device: &crate::Device) -> Result<Vec<vk::CommandBuffer>> {
+    fn create_command_buffers(
+        device: &crate::Device,
+        frames_in_flight: usize,
+    ) -> Result<Vec<vk::CommandBuffer>> {
         let allocate_info = vk::CommandBufferAllocateInfo::builder()
             .level(vk::CommandBufferLevel::PRIMARY)
             .command_pool(*device.command_pool())
-            .command_buffer_count(crate::SwapChain::MAX_FRAMES_IN_FLIGHT as u32);
+            .command_buffer_count(frames_in_flight as u32);
         let command_buffers = unsafe { device.device().allocate_command_buffers(&allocate_info) }?;
 
         Ok(command_buffers)
@@ -0,0 +1,516 @@
+use anyhow::{bail, Context, Result};
+use ash::vk;
+use std::{
+    cell::RefCell,
+    collections::{hash_map::DefaultHasher, HashMap},
+    fs,
+    hash::{Hash, Hasher},
+    io::Cursor,
+    path::{Path, PathBuf},
+    rc::Rc,
+    sync::{Mutex, OnceLock},
+};
+
+/* MEMO
+ *  Compiled SPIR-V is cached for the lifetime of the process, keyed by a hash
+ *  of everything that affects the output (source, stage, language, defines).
+ *  Every entry also remembers the files it #included with a hash of their
+ *  contents, and is compiled again once one of them changed.
+ */
+static SPIRV_CACHE: OnceLock<Mutex<HashMap<u64, CachedSpirv>>> = OnceLock::new();
+
+thread_local! {
+    // Creating a shaderc compiler is expensive; it isn't Send, so one per thread
+    static COMPILER: RefCell<Option<ShaderCompiler>> = const { RefCell::new(None) };
+}
+
+// Resolved path and content hash of a file pulled in through #include
+type IncludeDependency = (PathBuf, u64);
+
+struct CachedSpirv {
+    code: Vec<u32>,
+    includes: Vec<IncludeDependency>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ShaderLanguage {
+    Glsl,
+    Hlsl,
+}
+
+#[derive(Debug, Clone, Default, Hash)]
+pub struct ShaderCompileOptions {
+    pub defines: Vec<(String, Option<String>)>,
+    pub include_dirs: Vec<PathBuf>,
+    pub optimize: bool,
+}
+
+#[derive(Debug, Clone)]
+pub enum ShaderSource {
+    SpirvFile(PathBuf),
+    Spirv(Vec<u32>),
+    File {
+        path: PathBuf,
+        language: ShaderLanguage,
+        options: ShaderCompileOptions,
+    },
+    Source {
+        name: String,
+        source: String,
+        language: ShaderLanguage,
+        options: ShaderCompileOptions,
+    },
+}
+
+pub struct ShaderCompiler {
+    compiler: shaderc::Compiler,
+}
+
+impl ShaderCompileOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn define(&self, name: &str, value: Option<&str>) -> Self {
+        let mut defines = self.defines.clone();
+
+        defines.push((name.to_owned(), value.map(str::to_owned)));
+
+        Self {
+            defines,
+            include_dirs: self.include_dirs.clone(),
+            optimize: self.optimize,
+        }
+    }
+
+    pub fn include_dir<P: AsRef<Path>>(&self, dir: P) -> Self {
+        let mut include_dirs = self.include_dirs.clone();
+
+        include_dirs.push(dir.as_ref().to_path_buf());
+
+        Self {
+            defines: self.defines.clone(),
+            include_dirs,
+            optimize: self.optimize,
+        }
+    }
+
+    pub fn optimize(&self, optimize: bool) -> Self {
+        Self {
+            defines: self.defines.clone(),
+            include_dirs: self.include_dirs.clone(),
+            optimize,
+        }
+    }
+}
+
+impl ShaderSource {
+    pub fn spirv_file<P: AsRef<Path>>(path: P) -> Self {
+        Self::SpirvFile(path.as_ref().to_path_buf())
+    }
+
+    pub fn glsl_file<P: AsRef<Path>>(path: P) -> Self {
+        Self::File {
+            path: path.as_ref().to_path_buf(),
+            language: ShaderLanguage::Glsl,
+            options: ShaderCompileOptions::default(),
+        }
+    }
+
+    pub fn hlsl_file<P: AsRef<Path>>(path: P) -> Self {
+        Self::File {
+            path: path.as_ref().to_path_buf(),
+            language: ShaderLanguage::Hlsl,
+            options: ShaderCompileOptions::default(),
+        }
+    }
+
+    pub fn glsl(name: &str, source: &str) -> Self {
+        Self::Source {
+            name: name.to_owned(),
+            source: source.to_owned(),
+            language: ShaderLanguage::Glsl,
+            options: ShaderCompileOptions::default(),
+        }
+    }
+
+    pub fn hlsl(name: &str, source: &str) -> Self {
+        Self::Source {
+            name: name.to_owned(),
+            source: source.to_owned(),
+            language: ShaderLanguage::Hlsl,
+            options: ShaderCompileOptions::default(),
+        }
+    }
+
+    pub fn with_options(self, options: ShaderCompileOptions) -> Self {
+        match self {
+            Self::File { path, language, .. } => Self::File {
+                path,
+                language,
+                options,
+            },
+            Self::Source {
+                name,
+                source,
+                language,
+                ..
+            } => Self::Source {
+                name,
+                source,
+                language,
+                options,
+            },
+            spirv => spirv,
+        }
+    }
+
+    pub fn name(&self) -> String {
+        match self {
+            Self::SpirvFile(path) | Self::File { path, .. } => path.display().to_string(),
+            Self::Spirv(_) => "<spirv>".to_owned(),
+            Self::Source { name, .. } => name.clone(),
+        }
+    }
+
+    pub fn load(&self, stage: vk::ShaderStageFlags, entry_point: &str) -> Result<Vec<u32>> {
+        match self {
+            Self::SpirvFile(path) => {
+                let mut file = fs::File::open(path)
+                    .with_context(|| format!("Failed to open SPIR-V file {:?}", path))?;
+
+                Ok(ash::util::read_spv(&mut file)?)
+            }
+            Self::Spirv(code) => Ok(code.clone()),
+            Self::File {
+                path,
+                language,
+                options,
+            } => {
+                let source = fs::read_to_string(path)
+                    .with_context(|| format!("Failed to read shader source {:?}", path))?;
+                let mut options = options.clone();
+
+                // Relative includes resolve against the directory of the shader itself
+                if let Some(parent) = path.parent() {
+                    options.include_dirs.insert(0, parent.to_path_buf());
+                }
+
+                Self::load_cached(
+                    &path.display().to_string(),
+                    &source,
+                    *language,
+                    &options,
+                    stage,
+                    entry_point,
+                )
+            }
+            Self::Source {
+                name,
+                source,
+                language,
+                options,
+            } => Self::load_cached(name, source, *language, options, stage, entry_point),
+        }
+    }
+
+    fn load_cached(
+        name: &str,
+        source: &str,
+        language: ShaderLanguage,
+        options: &ShaderCompileOptions,
+        stage: vk::ShaderStageFlags,
+        entry_point: &str,
+    ) -> Result<Vec<u32>> {
+        let key = {
+            let mut hasher = DefaultHasher::new();
+
+            source.hash(&mut hasher);
+            language.hash(&mut hasher);
+            options.hash(&mut hasher);
+            stage.as_raw().hash(&mut hasher);
+            entry_point.hash(&mut hasher);
+
+            hasher.finish()
+        };
+
+        Self::cached(key, || {
+            COMPILER.with(|compiler| {
+                let mut compiler = compiler.borrow_mut();
+
+                if compiler.is_none() {
+                    *compiler = Some(ShaderCompiler::new()?);
+                }
+                compiler.as_mut().unwrap().compile_with_includes(
+                    name,
+                    source,
+                    language,
+                    options,
+                    stage,
+                    entry_point,
+                )
+            })
+        })
+    }
+
+    // Runs `compile` unless `key` is cached and none of its includes changed since
+    fn cached<F>(key: u64, compile: F) -> Result<Vec<u32>>
+    where
+        F: FnOnce() -> Result<(Vec<u32>, Vec<IncludeDependency>)>,
+    {
+        let cache = SPIRV_CACHE.get_or_init(|| Mutex::new(HashMap::new()));
+
+        if let Some(cached) = cache.lock().unwrap().get(&key) {
+            if cached.is_up_to_date() {
+                return Ok(cached.code.clone());
+            }
+        }
+
+        let (code, includes) = compile()?;
+
+        cache.lock().unwrap().insert(
+            key,
+            CachedSpirv {
+                code: code.clone(),
+                includes,
+            },
+        );
+
+        Ok(code)
+    }
+}
+
+impl CachedSpirv {
+    fn is_up_to_date(&self) -> bool {
+        self.includes.iter().all(|(path, hash)| {
+            fs::read_to_string(path).is_ok_and(|content| content_hash(&content) == *hash)
+        })
+    }
+}
+
+impl ShaderCompiler {
+    pub fn new() -> Result<Self> {
+        let compiler = shaderc::Compiler::new().context("Failed to initialize shaderc compiler")?;
+
+        Ok(Self { compiler })
+    }
+
+    pub fn compile(
+        &mut self,
+        name: &str,
+        source: &str,
+        language: ShaderLanguage,
+        options: &ShaderCompileOptions,
+        stage: vk::ShaderStageFlags,
+        entry_point: &str,
+    ) -> Result<Vec<u32>> {
+        Ok(self
+            .compile_with_includes(name, source, language, options, stage, entry_point)?
+            .0)
+    }
+
+    // Also returns every file the source included, for SPIRV_CACHE
+    fn compile_with_includes(
+        &mut self,
+        name: &str,
+        source: &str,
+        language: ShaderLanguage,
+        options: &ShaderCompileOptions,
+        stage: vk::ShaderStageFlags,
+        entry_point: &str,
+    ) -> Result<(Vec<u32>, Vec<IncludeDependency>)> {
+        let shader_kind = Self::shader_kind(stage)?;
+        let mut compile_options =
+            shaderc::CompileOptions::new().context("Failed to create shader compile options")?;
+
+        compile_options.set_source_language(match language {
+            ShaderLanguage::Glsl => shaderc::SourceLanguage::GLSL,
+            ShaderLanguage::Hlsl => shaderc::SourceLanguage::HLSL,
+        });
+        compile_options.set_target_env(
+            shaderc::TargetEnv::Vulkan,
+            shaderc::EnvVersion::Vulkan1_2 as u32,
+        );
+        compile_options.set_optimization_level(if options.optimize {
+            shaderc::OptimizationLevel::Performance
+        } else {
+            shaderc::OptimizationLevel::Zero
+        });
+        for (define, value) in options.defines.iter() {
+            compile_options.add_macro_definition(define, value.as_deref());
+        }
+
+        let include_dirs = options.include_dirs.clone();
+        let includes = Rc::new(RefCell::new(vec![]));
+        let resolved_includes = includes.clone();
+        compile_options.set_include_callback(move |requested, include_type, requesting, _| {
+            let resolved =
+                Self::resolve_include(&include_dirs, requested, include_type, requesting)?;
+
+            resolved_includes.borrow_mut().push((
+                PathBuf::from(&resolved.resolved_name),
+                content_hash(&resolved.content),
+            ));
+
+            Ok(resolved)
+        });
+
+        let artifact = self
+            .compiler
+            .compile_into_spirv(
+                source,
+                shader_kind,
+                name,
+                entry_point,
+                Some(&compile_options),
+            )
+            .with_context(|| format!("Failed to compile shader {}", name))?;
+
+        if artifact.get_num_warnings() > 0 {
+            eprintln!("{}", artifact.get_warning_messages());
+        }
+
+        let code = ash::util::read_spv(&mut Cursor::new(artifact.as_binary_u8()))?;
+        let includes = includes.take();
+
+        Ok((code, includes))
+    }
+
+    /* --- Helper functions --- */
+    fn shader_kind(stage: vk::ShaderStageFlags) -> Result<shaderc::ShaderKind> {
+        Ok(match stage {
+            vk::ShaderStageFlags::VERTEX => shaderc::ShaderKind::Vertex,
+            vk::ShaderStageFlags::FRAGMENT => shaderc::ShaderKind::Fragment,
+            vk::ShaderStageFlags::COMPUTE => shaderc::ShaderKind::Compute,
+            vk::ShaderStageFlags::GEOMETRY => shaderc::ShaderKind::Geometry,
+            vk::ShaderStageFlags::TESSELLATION_CONTROL => shaderc::ShaderKind::TessControl,
+            vk::ShaderStageFlags::TESSELLATION_EVALUATION => shaderc::ShaderKind::TessEvaluation,
+            vk::ShaderStageFlags::TASK_EXT => shaderc::ShaderKind::Task,
+            vk::ShaderStageFlags::MESH_EXT => shaderc::ShaderKind::Mesh,
+            _ => bail!("Unsupported shader stage: {:?}", stage),
+        })
+    }
+
+    fn resolve_include(
+        include_dirs: &[PathBuf],
+        requested: &str,
+        include_type: shaderc::IncludeType,
+        requesting: &str,
+    ) -> shaderc::IncludeCallbackResult {
+        let relative_dir = match include_type {
+            shaderc::IncludeType::Relative => Path::new(requesting).parent(),
+            shaderc::IncludeType::Standard => None,
+        };
+        let path = relative_dir
+            .into_iter()
+            .chain(include_dirs.iter().map(PathBuf::as_path))
+            .map(|dir| dir.join(requested))
+            .find(|path| path.is_file())
+            .ok_or_else(|| format!("Failed to find include {:?}", requested))?;
+        let content = fs::read_to_string(&path)
+            .map_err(|e| format!("Failed to read include {:?}: {}", path, e))?;
+
+        Ok(shaderc::ResolvedInclude {
+            resolved_name: path.display().to_string(),
+            content,
+        })
+    }
+}
+
+fn content_hash(content: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+
+    content.hash(&mut hasher);
+
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    const SPIRV_MAGIC: u32 = 0x0723_0203;
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("lve_rs_{}_{}", name, std::process::id()));
+
+        fs::create_dir_all(&dir).unwrap();
+
+        dir
+    }
+
+    #[test]
+    fn recompiles_cached_spirv_once_an_include_changes() {
+        let dir = test_dir("shader_cache");
+        let include = dir.join("common.glsl");
+        let compiles = Cell::new(0);
+        let compile = || {
+            compiles.set(compiles.get() + 1);
+            let content = fs::read_to_string(&include)?;
+
+            Ok((
+                vec![compiles.get()],
+                vec![(include.clone(), content_hash(&content))],
+            ))
+        };
+
+        fs::write(&include, "#define A 1").unwrap();
+        assert_eq!(ShaderSource::cached(1, compile).unwrap(), [1]);
+        assert_eq!(ShaderSource::cached(1, compile).unwrap(), [1]);
+        assert_eq!(compiles.get(), 1);
+
+        fs::write(&include, "#define A 2").unwrap();
+        assert_eq!(ShaderSource::cached(1, compile).unwrap(), [2]);
+        assert_eq!(ShaderSource::cached(1, compile).unwrap(), [2]);
+        assert_eq!(compiles.get(), 2);
+
+        // A different key is compiled on its own
+        assert_eq!(ShaderSource::cached(2, compile).unwrap(), [3]);
+
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(ShaderSource::cached(1, compile).is_err());
+    }
+
+    #[test]
+    fn compiles_glsl_and_hlsl() {
+        let glsl = ShaderSource::glsl("test.vert", "#version 450\nvoid main() {}\n")
+            .load(vk::ShaderStageFlags::VERTEX, "main")
+            .unwrap();
+        let hlsl = ShaderSource::hlsl("test.hlsl", "float4 main() : SV_Target { return 1; }")
+            .load(vk::ShaderStageFlags::FRAGMENT, "main")
+            .unwrap();
+
+        assert_eq!(glsl[0], SPIRV_MAGIC);
+        assert_eq!(hlsl[0], SPIRV_MAGIC);
+        assert!(
+            ShaderSource::glsl("broken.vert", "#version 450\nvoid main() {")
+                .load(vk::ShaderStageFlags::VERTEX, "main")
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn picks_up_edits_to_included_files() {
+        let dir = test_dir("shader_include");
+        let shader = dir.join("test.frag");
+        let include = dir.join("color.glsl");
+        let load = || ShaderSource::glsl_file(&shader).load(vk::ShaderStageFlags::FRAGMENT, "main");
+
+        fs::write(
+            &shader,
+            "#version 450\n#extension GL_GOOGLE_include_directive : require\n\
+             #include \"color.glsl\"\nlayout(location = 0) out vec4 color;\n\
+             void main() { color = COLOR; }\n",
+        )
+        .unwrap();
+        fs::write(&include, "#define COLOR vec4(1.0)\n").unwrap();
+        assert_eq!(load().unwrap()[0], SPIRV_MAGIC);
+
+        // The cached SPIR-V is thrown away once the include changes
+        fs::write(&include, "#define COLOR not_a_color\n").unwrap();
+        assert!(load().is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,94 @@
+use ash::vk;
+use std::{collections::BTreeMap, mem::size_of};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SpecializationValue {
+    Bool(bool),
+    I32(i32),
+    U32(u32),
+    F32(f32),
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SpecializationConstants {
+    constants: BTreeMap<u32, SpecializationValue>,
+}
+
+impl SpecializationValue {
+    fn bytes(&self) -> [u8; 4] {
+        match self {
+            // VkBool32 is 4 bytes wide
+            Self::Bool(value) => (if *value { vk::TRUE } else { vk::FALSE }).to_ne_bytes(),
+            Self::I32(value) => value.to_ne_bytes(),
+            Self::U32(value) => value.to_ne_bytes(),
+            Self::F32(value) => value.to_ne_bytes(),
+        }
+    }
+}
+
+impl From<bool> for SpecializationValue {
+    fn from(value: bool) -> Self {
+        Self::Bool(value)
+    }
+}
+
+impl From<i32> for SpecializationValue {
+    fn from(value: i32) -> Self {
+        Self::I32(value)
+    }
+}
+
+impl From<u32> for SpecializationValue {
+    fn from(value: u32) -> Self {
+        Self::U32(value)
+    }
+}
+
+impl From<f32> for SpecializationValue {
+    fn from(value: f32) -> Self {
+        Self::F32(value)
+    }
+}
+
+impl SpecializationConstants {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set<T: Into<SpecializationValue>>(&self, constant_id: u32, value: T) -> Self {
+        let mut constants = self.constants.clone();
+
+        constants.insert(constant_id, value.into());
+
+        Self { constants }
+    }
+
+    #[inline]
+    pub fn get(&self, constant_id: u32) -> Option<SpecializationValue> {
+        self.constants.get(&constant_id).copied()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.constants.is_empty()
+    }
+
+    // Map entries and the packed data they point into, ready for vk::SpecializationInfo
+    pub fn map_entries_and_data(&self) -> (Vec<vk::SpecializationMapEntry>, Vec<u8>) {
+        let mut map_entries = Vec::with_capacity(self.constants.len());
+        let mut data = Vec::with_capacity(self.constants.len() * size_of::<u32>());
+
+        for (constant_id, value) in self.constants.iter() {
+            map_entries.push(
+                vk::SpecializationMapEntry::builder()
+                    .constant_id(*constant_id)
+                    .offset(data.len() as u32)
+                    .size(size_of::<u32>())
+                    .build(),
+            );
+            data.extend_from_slice(&value.bytes());
+        }
+
+        (map_entries, data)
+    }
+}
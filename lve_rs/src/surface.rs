@@ -1,6 +1,9 @@
 use crate::Window;
 use anyhow::Result;
-use ash::{extensions::khr as vk_khr, vk};
+use ash::{
+    extensions::{ext as vk_ext, khr as vk_khr},
+    vk,
+};
 use ash_window;
 use raw_window_handle::{HasRawDisplayHandle, HasRawWindowHandle};
 use std::ffi::CStr;
@@ -19,15 +22,21 @@ pub struct SwapChainSupportDetails {
 impl Surface {
     pub fn new(window: &Window, entry: &ash::Entry, instance: &ash::Instance) -> Result<Self> {
         let extension = vk_khr::Surface::new(entry, instance);
-        let surface = unsafe {
-            ash_window::create_surface(
-                entry,
-                instance,
-                window.window().raw_display_handle(),
-                window.window().raw_window_handle(),
-                None,
-            )
-        }?;
+        let surface = match window.window() {
+            Some(window) => unsafe {
+                ash_window::create_surface(
+                    entry,
+                    instance,
+                    window.raw_display_handle(),
+                    window.raw_window_handle(),
+                    None,
+                )
+            }?,
+            None => unsafe {
+                vk_ext::HeadlessSurface::new(entry, instance)
+                    .create_headless_surface(&vk::HeadlessSurfaceCreateInfoEXT::default(), None)
+            }?,
+        };
 
         Ok(Self { extension, surface })
     }
@@ -1,53 +1,511 @@
-use anyhow::{bail, Context, Result};
+use anyhow::{Context, Result};
 use ash::{extensions::khr as vk_khr, vk};
 
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PresentMode {
+    // V-Sync, always supported
+    Fifo,
+    FifoRelaxed,
+    #[default]
+    Mailbox,
+    // Uncapped, may tear
+    Immediate,
+}
+
+// Mastering display description in CIE 1931 xy chromaticities and nits
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HdrMetadata {
+    pub display_primary_red: [f32; 2],
+    pub display_primary_green: [f32; 2],
+    pub display_primary_blue: [f32; 2],
+    pub white_point: [f32; 2],
+    pub max_luminance: f32,
+    pub min_luminance: f32,
+    pub max_content_light_level: f32,
+    pub max_frame_average_light_level: f32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SwapChainConfig {
+    pub present_mode: PresentMode,
+    // Requested count; clamped to Device::max_usable_sample_count
+    pub msaa_samples: vk::SampleCountFlags,
+    // Tried in order; the first format the surface offers wins
+    pub surface_formats: Vec<vk::SurfaceFormatKHR>,
+    // Only applied to HDR color spaces and when VK_EXT_hdr_metadata is available
+    pub hdr_metadata: Option<HdrMetadata>,
+    // More frames in flight raise throughput at the cost of input latency
+    pub frames_in_flight: usize,
+    // None requests min_image_count + 1; clamped to the surface capabilities
+    pub image_count: Option<u32>,
+    // Render with vkCmdBeginRendering instead of a VkRenderPass when the device supports it
+    pub dynamic_rendering: bool,
+}
+
+// What a pipeline needs to know about the render pass it draws into
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RenderPassInfo {
+    // Null with dynamic rendering; pipelines then target the formats below
+    pub render_pass: vk::RenderPass,
+    pub color_format: vk::Format,
+    pub depth_format: vk::Format,
+    pub color_space: vk::ColorSpaceKHR,
+    pub samples: vk::SampleCountFlags,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum LoadOp {
+    #[default]
+    Clear,
+    // Keeps what an earlier pass of the same frame stored
+    Load,
+    DontCare,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum StoreOp {
+    Store,
+    #[default]
+    DontCare,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct AttachmentOps {
+    pub load: LoadOp,
+    pub store: StoreOp,
+}
+
+/* MEMO
+ *  Load/store behaviour of the swap chain pass. Render passes differing only in
+ *  these are compatible, so pipelines and framebuffers are shared between them.
+ *   - The image that gets presented is always stored; color.store only decides
+ *     whether the multisampled image survives the pass when MSAA is on.
+ *   - Load expects an earlier swap chain pass of the same frame to have stored
+ *     the attachment.
+ *   - Stencil ops only apply to VkRenderPass; dynamic rendering has no stencil
+ *     attachment.
+ *   - They also do nothing when the depth format has no stencil aspect, which is
+ *     the common case: find_depth_format prefers D32_SFLOAT and only falls back
+ *     to D32_SFLOAT_S8_UINT / D24_UNORM_S8_UINT when it isn't supported.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RenderPassOps {
+    pub color: AttachmentOps,
+    pub depth: AttachmentOps,
+    pub stencil: AttachmentOps,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ClearValues {
+    pub color: [f32; 4],
+    // 0.0 for reversed depth
+    pub depth: f32,
+    // Ignored when the depth format has no stencil aspect (see RenderPassOps)
+    pub stencil: u32,
+}
+
 pub struct SwapChain {
+    config: SwapChainConfig,
+    present_mode: vk::PresentModeKHR,
+    msaa_samples: vk::SampleCountFlags,
+    swap_chain_color_space: vk::ColorSpaceKHR,
     swap_chain_image_format: vk::Format,
     swap_chain_depth_format: vk::Format,
     swap_chain_extent: vk::Extent2D,
     swap_chain_framebuffers: Vec<vk::Framebuffer>,
     render_pass: vk::RenderPass,
+    // Compatible with `render_pass`, created by prepare_render_pass
+    render_pass_variants: Vec<(RenderPassOps, vk::RenderPass)>,
+    dynamic_rendering: bool,
     depth_images: Vec<vk::Image>,
     depth_image_memories: Vec<vk::DeviceMemory>,
     depth_image_views: Vec<vk::ImageView>,
+    color_images: Vec<vk::Image>,
+    color_image_memories: Vec<vk::DeviceMemory>,
+    color_image_views: Vec<vk::ImageView>,
     swap_chain_images: Vec<vk::Image>,
     swap_chain_image_views: Vec<vk::ImageView>,
+    // TRANSFER_SRC is only included when the surface supports it
+    swap_chain_image_usage: vk::ImageUsageFlags,
     window_extent: vk::Extent2D,
     extension: vk_khr::Swapchain,
     swap_chain: vk::SwapchainKHR,
     image_available_semaphores: Vec<vk::Semaphore>,
     render_finished_semaphores: Vec<vk::Semaphore>,
-    in_flight_fences: Vec<vk::Fence>,
-    images_in_flight: Vec<vk::Fence>,
+    // Graphics timeline values the last submission of each frame and image signals
+    frame_values: Vec<u64>,
+    image_values: Vec<u64>,
     current_frame: usize,
 }
 
+impl PresentMode {
+    // Modes tried in order; FIFO is guaranteed by the spec so the chain never runs dry
+    pub fn fallback_chain(&self) -> &'static [vk::PresentModeKHR] {
+        match self {
+            Self::Fifo => &[vk::PresentModeKHR::FIFO],
+            Self::FifoRelaxed => &[vk::PresentModeKHR::FIFO_RELAXED, vk::PresentModeKHR::FIFO],
+            Self::Mailbox => &[vk::PresentModeKHR::MAILBOX, vk::PresentModeKHR::FIFO],
+            Self::Immediate => &[
+                vk::PresentModeKHR::IMMEDIATE,
+                vk::PresentModeKHR::MAILBOX,
+                vk::PresentModeKHR::FIFO,
+            ],
+        }
+    }
+}
+
+impl HdrMetadata {
+    // Rec. 2020 primaries, D65 white point
+    pub fn hdr10(max_luminance: f32, min_luminance: f32) -> Self {
+        Self {
+            display_primary_red: [0.708, 0.292],
+            display_primary_green: [0.170, 0.797],
+            display_primary_blue: [0.131, 0.046],
+            white_point: [0.3127, 0.3290],
+            max_luminance,
+            min_luminance,
+            max_content_light_level: max_luminance,
+            max_frame_average_light_level: max_luminance / 2.0,
+        }
+    }
+
+    fn vk_hdr_metadata(&self) -> vk::HdrMetadataEXT {
+        let xy = |[x, y]: [f32; 2]| vk::XYColorEXT { x, y };
+
+        vk::HdrMetadataEXT::builder()
+            .display_primary_red(xy(self.display_primary_red))
+            .display_primary_green(xy(self.display_primary_green))
+            .display_primary_blue(xy(self.display_primary_blue))
+            .white_point(xy(self.white_point))
+            .max_luminance(self.max_luminance)
+            .min_luminance(self.min_luminance)
+            .max_content_light_level(self.max_content_light_level)
+            .max_frame_average_light_level(self.max_frame_average_light_level)
+            .build()
+    }
+}
+
+impl Default for HdrMetadata {
+    fn default() -> Self {
+        Self::hdr10(1000.0, 0.001)
+    }
+}
+
+impl LoadOp {
+    fn vk_load_op(&self) -> vk::AttachmentLoadOp {
+        match self {
+            Self::Clear => vk::AttachmentLoadOp::CLEAR,
+            Self::Load => vk::AttachmentLoadOp::LOAD,
+            Self::DontCare => vk::AttachmentLoadOp::DONT_CARE,
+        }
+    }
+}
+
+impl StoreOp {
+    fn vk_store_op(&self) -> vk::AttachmentStoreOp {
+        match self {
+            Self::Store => vk::AttachmentStoreOp::STORE,
+            Self::DontCare => vk::AttachmentStoreOp::DONT_CARE,
+        }
+    }
+}
+
+impl AttachmentOps {
+    pub fn new(load: LoadOp, store: StoreOp) -> Self {
+        Self { load, store }
+    }
+}
+
+impl RenderPassOps {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn color(&self, load: LoadOp, store: StoreOp) -> Self {
+        Self {
+            color: AttachmentOps::new(load, store),
+            ..*self
+        }
+    }
+
+    pub fn depth(&self, load: LoadOp, store: StoreOp) -> Self {
+        Self {
+            depth: AttachmentOps::new(load, store),
+            ..*self
+        }
+    }
+
+    // No effect unless the depth format has a stencil aspect
+    pub fn stencil(&self, load: LoadOp, store: StoreOp) -> Self {
+        Self {
+            stencil: AttachmentOps::new(load, store),
+            ..*self
+        }
+    }
+
+    #[inline]
+    fn loads_depth_stencil(&self) -> bool {
+        self.depth.load == LoadOp::Load || self.stencil.load == LoadOp::Load
+    }
+}
+
+impl Default for RenderPassOps {
+    fn default() -> Self {
+        Self {
+            color: AttachmentOps::new(LoadOp::Clear, StoreOp::DontCare),
+            depth: AttachmentOps::new(LoadOp::Clear, StoreOp::DontCare),
+            stencil: AttachmentOps::new(LoadOp::DontCare, StoreOp::DontCare),
+        }
+    }
+}
+
+impl ClearValues {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn color(&self, color: [f32; 4]) -> Self {
+        Self { color, ..*self }
+    }
+
+    pub fn depth(&self, depth: f32) -> Self {
+        Self { depth, ..*self }
+    }
+
+    pub fn stencil(&self, stencil: u32) -> Self {
+        Self { stencil, ..*self }
+    }
+
+    pub(crate) fn vk_clear_values(&self) -> [vk::ClearValue; 2] {
+        [
+            vk::ClearValue {
+                color: vk::ClearColorValue {
+                    float32: self.color,
+                },
+            },
+            vk::ClearValue {
+                depth_stencil: vk::ClearDepthStencilValue::builder()
+                    .depth(self.depth)
+                    .stencil(self.stencil)
+                    .build(),
+            },
+        ]
+    }
+}
+
+impl Default for ClearValues {
+    fn default() -> Self {
+        Self {
+            color: [0.01, 0.01, 0.01, 1.0],
+            depth: 1.0,
+            stencil: 0,
+        }
+    }
+}
+
+impl SwapChainConfig {
+    pub const SDR_SURFACE_FORMATS: &'static [vk::SurfaceFormatKHR] = &[
+        vk::SurfaceFormatKHR {
+            format: vk::Format::B8G8R8A8_SRGB,
+            color_space: vk::ColorSpaceKHR::SRGB_NONLINEAR,
+        },
+        vk::SurfaceFormatKHR {
+            format: vk::Format::R8G8B8A8_SRGB,
+            color_space: vk::ColorSpaceKHR::SRGB_NONLINEAR,
+        },
+    ];
+    // 10-bit SDR, less banding in gradients
+    pub const WIDE_SURFACE_FORMATS: &'static [vk::SurfaceFormatKHR] = &[
+        vk::SurfaceFormatKHR {
+            format: vk::Format::A2B10G10R10_UNORM_PACK32,
+            color_space: vk::ColorSpaceKHR::SRGB_NONLINEAR,
+        },
+        vk::SurfaceFormatKHR {
+            format: vk::Format::A2R10G10B10_UNORM_PACK32,
+            color_space: vk::ColorSpaceKHR::SRGB_NONLINEAR,
+        },
+    ];
+    // Shaders must output PQ-encoded Rec. 2020
+    pub const HDR10_SURFACE_FORMATS: &'static [vk::SurfaceFormatKHR] = &[
+        vk::SurfaceFormatKHR {
+            format: vk::Format::A2B10G10R10_UNORM_PACK32,
+            color_space: vk::ColorSpaceKHR::HDR10_ST2084_EXT,
+        },
+        vk::SurfaceFormatKHR {
+            format: vk::Format::A2R10G10B10_UNORM_PACK32,
+            color_space: vk::ColorSpaceKHR::HDR10_ST2084_EXT,
+        },
+    ];
+    // scRGB: linear, 1.0 is SDR white and values beyond [0, 1] are valid
+    pub const EXTENDED_SRGB_SURFACE_FORMATS: &'static [vk::SurfaceFormatKHR] =
+        &[vk::SurfaceFormatKHR {
+            format: vk::Format::R16G16B16A16_SFLOAT,
+            color_space: vk::ColorSpaceKHR::EXTENDED_SRGB_LINEAR_EXT,
+        }];
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn surface_formats(&self, surface_formats: &[vk::SurfaceFormatKHR]) -> Self {
+        Self {
+            surface_formats: surface_formats.to_vec(),
+            ..self.clone()
+        }
+    }
+
+    // HDR first, SDR as the fallback
+    pub fn prefer_hdr(&self) -> Self {
+        let surface_formats = [
+            Self::HDR10_SURFACE_FORMATS,
+            Self::EXTENDED_SRGB_SURFACE_FORMATS,
+            Self::SDR_SURFACE_FORMATS,
+        ]
+        .concat();
+
+        Self {
+            surface_formats,
+            hdr_metadata: Some(self.hdr_metadata.unwrap_or_default()),
+            ..self.clone()
+        }
+    }
+
+    pub fn hdr_metadata(&self, hdr_metadata: Option<HdrMetadata>) -> Self {
+        Self {
+            hdr_metadata,
+            ..self.clone()
+        }
+    }
+
+    pub fn frames_in_flight(&self, frames_in_flight: usize) -> Self {
+        assert!(frames_in_flight > 0, "At least one frame must be in flight");
+
+        Self {
+            frames_in_flight,
+            ..self.clone()
+        }
+    }
+
+    pub fn image_count(&self, image_count: Option<u32>) -> Self {
+        Self {
+            image_count,
+            ..self.clone()
+        }
+    }
+
+    pub fn present_mode(&self, present_mode: PresentMode) -> Self {
+        Self {
+            present_mode,
+            ..self.clone()
+        }
+    }
+
+    pub fn msaa_samples(&self, msaa_samples: vk::SampleCountFlags) -> Self {
+        Self {
+            msaa_samples,
+            ..self.clone()
+        }
+    }
+
+    pub fn dynamic_rendering(&self, dynamic_rendering: bool) -> Self {
+        Self {
+            dynamic_rendering,
+            ..self.clone()
+        }
+    }
+}
+
+impl RenderPassInfo {
+    #[inline]
+    pub fn is_dynamic_rendering(&self) -> bool {
+        self.render_pass == vk::RenderPass::null()
+    }
+
+    // Whether pipelines built for `self` can be used with `other`; recreated swap
+    // chains get a new, but compatible, render pass
+    pub fn is_compatible_with(&self, other: &Self) -> bool {
+        self.is_dynamic_rendering() == other.is_dynamic_rendering()
+            && self.color_format == other.color_format
+            && self.depth_format == other.depth_format
+            && self.samples == other.samples
+    }
+}
+
+impl Default for SwapChainConfig {
+    fn default() -> Self {
+        Self {
+            present_mode: PresentMode::default(),
+            msaa_samples: vk::SampleCountFlags::TYPE_1,
+            surface_formats: Self::SDR_SURFACE_FORMATS.to_vec(),
+            hdr_metadata: None,
+            frames_in_flight: SwapChain::DEFAULT_FRAMES_IN_FLIGHT,
+            image_count: None,
+            dynamic_rendering: false,
+        }
+    }
+}
+
 impl SwapChain {
-    pub const MAX_FRAMES_IN_FLIGHT: i32 = 2;
+    pub const DEFAULT_FRAMES_IN_FLIGHT: usize = 2;
 
     pub fn new(device: &crate::Device, extent: vk::Extent2D) -> Result<Self> {
-        Self::init(device, extent, &vk::SwapchainKHR::null())
+        Self::with_config(device, extent, &SwapChainConfig::default())
+    }
+
+    pub fn with_config(
+        device: &crate::Device,
+        extent: vk::Extent2D,
+        config: &SwapChainConfig,
+    ) -> Result<Self> {
+        Self::init(
+            device,
+            device.surface(),
+            extent,
+            config,
+            &vk::SwapchainKHR::null(),
+        )
+    }
+
+    // Presents to `surface` instead of the device's own, see Device::create_surface
+    pub fn with_surface(
+        device: &crate::Device,
+        surface: &crate::Surface,
+        extent: vk::Extent2D,
+        config: &SwapChainConfig,
+        previous_swap_chain: &vk::SwapchainKHR,
+    ) -> Result<Self> {
+        Self::init(device, surface, extent, config, previous_swap_chain)
     }
 
     pub fn null(device: &crate::Device) -> Self {
         Self {
+            config: SwapChainConfig::default(),
+            present_mode: vk::PresentModeKHR::FIFO,
+            msaa_samples: vk::SampleCountFlags::TYPE_1,
+            swap_chain_color_space: vk::ColorSpaceKHR::SRGB_NONLINEAR,
             swap_chain_image_format: vk::Format::default(),
             swap_chain_depth_format: vk::Format::default(),
             swap_chain_extent: vk::Extent2D::default(),
             swap_chain_framebuffers: vec![],
             render_pass: vk::RenderPass::null(),
+            render_pass_variants: vec![],
+            dynamic_rendering: false,
             depth_images: vec![],
             depth_image_memories: vec![],
             depth_image_views: vec![],
+            color_images: vec![],
+            color_image_memories: vec![],
+            color_image_views: vec![],
             swap_chain_images: vec![],
             swap_chain_image_views: vec![],
+            swap_chain_image_usage: vk::ImageUsageFlags::empty(),
             window_extent: vk::Extent2D::default(),
             extension: vk_khr::Swapchain::new(device.instance(), device.device()),
             swap_chain: vk::SwapchainKHR::null(),
             image_available_semaphores: vec![],
             render_finished_semaphores: vec![],
-            in_flight_fences: vec![],
-            images_in_flight: vec![],
+            frame_values: vec![],
+            image_values: vec![],
             current_frame: 0,
         }
     }
@@ -55,9 +513,16 @@ impl SwapChain {
     pub fn with_previous_swap_chain(
         device: &crate::Device,
         extent: vk::Extent2D,
+        config: &SwapChainConfig,
         previous_swap_chain: &vk::SwapchainKHR,
     ) -> Result<Self> {
-        let swap_chain = Self::init(device, extent, previous_swap_chain)?;
+        let swap_chain = Self::init(
+            device,
+            device.surface(),
+            extent,
+            config,
+            previous_swap_chain,
+        )?;
 
         Ok(swap_chain)
     }
@@ -84,13 +549,28 @@ impl SwapChain {
                 .free_memory(self.depth_image_memories[index], None);
         });
 
+        (0..self.color_images.len()).into_iter().for_each(|index| {
+            device
+                .device()
+                .destroy_image_view(self.color_image_views[index], None);
+            device
+                .device()
+                .destroy_image(self.color_images[index], None);
+            device
+                .device()
+                .free_memory(self.color_image_memories[index], None);
+        });
+
         self.swap_chain_framebuffers.iter().for_each(|framebuffer| {
             device.device().destroy_framebuffer(*framebuffer, None);
         });
 
         device.device().destroy_render_pass(self.render_pass, None);
+        for (_, render_pass) in self.render_pass_variants.drain(..) {
+            device.device().destroy_render_pass(render_pass, None);
+        }
 
-        (0..Self::MAX_FRAMES_IN_FLIGHT as usize)
+        (0..self.image_available_semaphores.len())
             .into_iter()
             .for_each(|index| {
                 device
@@ -99,9 +579,6 @@ impl SwapChain {
                 device
                     .device()
                     .destroy_semaphore(self.image_available_semaphores[index], None);
-                device
-                    .device()
-                    .destroy_fence(self.in_flight_fences[index], None);
             });
     }
 
@@ -110,6 +587,71 @@ impl SwapChain {
         &self.swap_chain
     }
 
+    #[inline]
+    pub fn config(&self) -> &SwapChainConfig {
+        &self.config
+    }
+
+    // The mode actually in use, which may differ from the configured preference
+    #[inline]
+    pub fn present_mode(&self) -> vk::PresentModeKHR {
+        self.present_mode
+    }
+
+    #[inline]
+    pub fn msaa_samples(&self) -> vk::SampleCountFlags {
+        self.msaa_samples
+    }
+
+    #[inline]
+    pub fn render_pass_info(&self) -> RenderPassInfo {
+        RenderPassInfo {
+            render_pass: self.render_pass,
+            color_format: self.swap_chain_image_format,
+            depth_format: self.swap_chain_depth_format,
+            color_space: self.swap_chain_color_space,
+            samples: self.msaa_samples,
+        }
+    }
+
+    // Creates the render pass variant for `ops` unless it already exists
+    pub fn prepare_render_pass(
+        &mut self,
+        device: &crate::Device,
+        ops: &RenderPassOps,
+    ) -> Result<()> {
+        if self.dynamic_rendering || self.render_pass_for(ops).is_some() {
+            return Ok(());
+        }
+
+        let render_pass =
+            Self::create_render_pass(device, self.swap_chain_image_format, self.msaa_samples, ops)?;
+
+        self.render_pass_variants.push((*ops, render_pass));
+
+        Ok(())
+    }
+
+    // None with dynamic rendering or if `ops` was never prepared
+    pub fn render_pass_for(&self, ops: &RenderPassOps) -> Option<vk::RenderPass> {
+        if self.dynamic_rendering {
+            None
+        } else if *ops == RenderPassOps::default() {
+            Some(self.render_pass)
+        } else {
+            self.render_pass_variants
+                .iter()
+                .find(|(variant_ops, _)| variant_ops == ops)
+                .map(|(_, render_pass)| *render_pass)
+        }
+    }
+
+    // False when dynamic rendering was requested but the device lacks it
+    #[inline]
+    pub fn uses_dynamic_rendering(&self) -> bool {
+        self.dynamic_rendering
+    }
+
     #[inline]
     pub fn framebuffer(&self, index: usize) -> &vk::Framebuffer {
         &self.swap_chain_framebuffers[index]
@@ -120,11 +662,34 @@ impl SwapChain {
         &self.render_pass
     }
 
+    // False when the surface doesn't allow TRANSFER_SRC on its images
+    #[inline]
+    pub fn supports_capture(&self) -> bool {
+        self.swap_chain_image_usage
+            .contains(vk::ImageUsageFlags::TRANSFER_SRC)
+    }
+
+    #[inline]
+    pub fn image(&self, index: usize) -> vk::Image {
+        self.swap_chain_images[index]
+    }
+
     #[inline]
     pub fn image_view(&self, index: usize) -> &vk::ImageView {
         &self.swap_chain_image_views[index]
     }
 
+    #[inline]
+    pub fn frames_in_flight(&self) -> usize {
+        self.frame_values.len()
+    }
+
+    // Graphics timeline value of the most recently submitted frame
+    #[inline]
+    pub fn last_submitted_value(&self) -> u64 {
+        self.frame_values.iter().copied().max().unwrap_or(0)
+    }
+
     #[inline]
     pub fn image_count(&self) -> usize {
         self.swap_chain_images.len()
@@ -135,6 +700,55 @@ impl SwapChain {
         self.swap_chain_image_format
     }
 
+    #[inline]
+    pub fn swap_chain_color_space(&self) -> vk::ColorSpaceKHR {
+        self.swap_chain_color_space
+    }
+
+    #[inline]
+    pub fn is_hdr(&self) -> bool {
+        Self::is_hdr_color_space(self.swap_chain_color_space)
+    }
+
+    // Returns false when the color space is SDR or VK_EXT_hdr_metadata is unavailable
+    pub fn set_hdr_metadata(
+        &self,
+        device: &crate::Device,
+        hdr_metadata: &HdrMetadata,
+    ) -> Result<bool> {
+        let hdr_metadata_fn = match device.hdr_metadata() {
+            Some(hdr_metadata_fn) if self.is_hdr() => hdr_metadata_fn,
+            _ => return Ok(false),
+        };
+        let metadata = hdr_metadata.vk_hdr_metadata();
+
+        unsafe {
+            (hdr_metadata_fn.set_hdr_metadata_ext)(
+                device.device().handle(),
+                1,
+                &self.swap_chain,
+                &metadata,
+            )
+        };
+
+        Ok(true)
+    }
+
+    // Like set_hdr_metadata, but also kept in the config of this swap chain; None
+    //  only stops it from being applied to the swap chains replacing this one
+    pub(crate) fn update_hdr_metadata(
+        &mut self,
+        device: &crate::Device,
+        hdr_metadata: Option<HdrMetadata>,
+    ) -> Result<bool> {
+        self.config.hdr_metadata = hdr_metadata;
+
+        match hdr_metadata {
+            Some(hdr_metadata) => self.set_hdr_metadata(device, &hdr_metadata),
+            None => Ok(false),
+        }
+    }
+
     #[inline]
     pub fn swap_chain_extent(&self) -> vk::Extent2D {
         self.swap_chain_extent
@@ -161,12 +775,10 @@ impl SwapChain {
     }
 
     pub fn acquire_next_image(&self, device: &crate::Device) -> Result<(usize, bool)> {
-        if self.in_flight_fences[self.current_frame] == vk::Fence::null() {
-            bail!("in_flight_fences[{}] is NULL (invalid)", self.current_frame);
-        }
-
-        let fences = [self.in_flight_fences[self.current_frame]];
-        unsafe { device.device().wait_for_fences(&fences, true, u64::MAX) }?;
+        // Waits until the frame that last used this slot has finished
+        device
+            .graphics_timeline()
+            .wait(device, self.frame_values[self.current_frame], u64::MAX)?;
 
         let (image_index, result) = match unsafe {
             self.extension.acquire_next_image(
@@ -195,44 +807,46 @@ impl SwapChain {
         buffer: &vk::CommandBuffer,
         image_index: usize,
     ) -> Result<bool> {
-        if self.images_in_flight[image_index] != vk::Fence::null() {
-            unsafe {
-                device.device().wait_for_fences(
-                    std::slice::from_ref(&self.images_in_flight[image_index]),
-                    true,
-                    u64::MAX,
-                )
-            }?;
-        }
-        self.images_in_flight[image_index] = self.in_flight_fences[self.current_frame];
+        // An image can be acquired again before the frame that rendered to it has finished
+        device
+            .graphics_timeline()
+            .wait(device, self.image_values[image_index], u64::MAX)?;
 
-        let signal_semaphores = [self.render_finished_semaphores[self.current_frame]];
+        // Acquire and present only take binary semaphores; the timeline tracks completion
+        let frame_value = device.graphics_timeline().next_value();
         let wait_semaphores = [self.image_available_semaphores[self.current_frame]];
+        let wait_values = [0];
         let wait_stages = [vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT];
+        let signal_semaphores = [
+            self.render_finished_semaphores[self.current_frame],
+            device.graphics_timeline().semaphore(),
+        ];
+        let signal_values = [0, frame_value];
+        let mut timeline_info = vk::TimelineSemaphoreSubmitInfo::builder()
+            .wait_semaphore_values(&wait_values)
+            .signal_semaphore_values(&signal_values);
         let submit_info = vk::SubmitInfo::builder()
             .wait_semaphores(&wait_semaphores)
             .wait_dst_stage_mask(&wait_stages)
             .command_buffers(std::slice::from_ref(buffer))
             .signal_semaphores(&signal_semaphores)
-            .build();
+            .push_next(&mut timeline_info);
 
-        unsafe {
-            device.device().reset_fences(std::slice::from_ref(
-                &self.in_flight_fences[self.current_frame],
-            ))
-        }?;
         unsafe {
             device.device().queue_submit(
                 *device.graphics_queue(),
                 std::slice::from_ref(&submit_info),
-                self.in_flight_fences[self.current_frame],
+                vk::Fence::null(),
             )
         }?;
+        device.graphics_timeline().submitted(frame_value);
+        self.frame_values[self.current_frame] = frame_value;
+        self.image_values[image_index] = frame_value;
 
         let image_indices = [image_index as u32];
         let present_info = {
             vk::PresentInfoKHR::builder()
-                .wait_semaphores(&signal_semaphores)
+                .wait_semaphores(&signal_semaphores[..1])
                 .swapchains(std::slice::from_ref(&self.swap_chain))
                 .image_indices(&image_indices)
         };
@@ -251,87 +865,260 @@ impl SwapChain {
             }
         }?;
 
-        self.current_frame = (self.current_frame + 1) % Self::MAX_FRAMES_IN_FLIGHT as usize;
+        self.current_frame = (self.current_frame + 1) % self.frames_in_flight();
 
         Ok(result)
     }
 
     pub fn compare_swap_formats(&self, swap_chain: &Self) -> bool {
         self.swap_chain_image_format == swap_chain.swap_chain_image_format
+            && self.swap_chain_color_space == swap_chain.swap_chain_color_space
             && self.swap_chain_depth_format == swap_chain.swap_chain_depth_format
+            && self.msaa_samples == swap_chain.msaa_samples
+            && self.dynamic_rendering == swap_chain.dynamic_rendering
+    }
+
+    // Dynamic rendering counterpart of vkCmdBeginRenderPass on the swap chain framebuffer
+    pub(crate) unsafe fn begin_rendering(
+        &self,
+        device: &crate::Device,
+        command_buffer: &vk::CommandBuffer,
+        image_index: usize,
+        clear_values: &ClearValues,
+        ops: &RenderPassOps,
+        contents: vk::SubpassContents,
+    ) {
+        let (color, depth) = self.rendering_attachments(image_index, clear_values, ops);
+        let flags = if contents == vk::SubpassContents::SECONDARY_COMMAND_BUFFERS {
+            vk::RenderingFlags::CONTENTS_SECONDARY_COMMAND_BUFFERS
+        } else {
+            vk::RenderingFlags::empty()
+        };
+
+        crate::dynamic_rendering::begin_rendering(
+            device,
+            command_buffer,
+            self.swap_chain_extent,
+            Some(&color),
+            Some(&depth),
+            flags,
+        );
+    }
+
+    // Leaves the swap chain image in PRESENT_SRC_KHR, like the render pass does
+    pub(crate) unsafe fn end_rendering(
+        &self,
+        device: &crate::Device,
+        command_buffer: &vk::CommandBuffer,
+        image_index: usize,
+    ) {
+        let (color, depth) = self.rendering_attachments(
+            image_index,
+            &ClearValues::default(),
+            &RenderPassOps::default(),
+        );
+
+        crate::dynamic_rendering::end_rendering(device, command_buffer, Some(&color), Some(&depth));
+    }
+
+    fn rendering_attachments(
+        &self,
+        image_index: usize,
+        clear_values: &ClearValues,
+        ops: &RenderPassOps,
+    ) -> (
+        crate::dynamic_rendering::ColorAttachment,
+        crate::dynamic_rendering::DepthAttachment,
+    ) {
+        let swap_chain_image = (
+            self.swap_chain_images[image_index],
+            self.swap_chain_image_views[image_index],
+        );
+        // With MSAA we draw into the transient image and resolve into the swap chain image
+        let ((image, view), resolve) = if self.color_images.is_empty() {
+            (swap_chain_image, None)
+        } else {
+            (
+                (
+                    self.color_images[image_index],
+                    self.color_image_views[image_index],
+                ),
+                Some(swap_chain_image),
+            )
+        };
+
+        (
+            crate::dynamic_rendering::ColorAttachment {
+                image,
+                view,
+                resolve,
+                clear_color: clear_values.color,
+                load_op: ops.color.load.vk_load_op(),
+                store_op: if resolve.is_some() {
+                    ops.color.store.vk_store_op()
+                } else {
+                    vk::AttachmentStoreOp::STORE
+                },
+                initial_layout: if resolve.is_some() {
+                    vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL
+                } else {
+                    vk::ImageLayout::PRESENT_SRC_KHR
+                },
+                final_layout: vk::ImageLayout::PRESENT_SRC_KHR,
+            },
+            crate::dynamic_rendering::DepthAttachment {
+                image: self.depth_images[image_index],
+                view: self.depth_image_views[image_index],
+                format: self.swap_chain_depth_format,
+                clear_depth: clear_values.depth,
+                load_op: ops.depth.load.vk_load_op(),
+                store_op: ops.depth.store.vk_store_op(),
+                initial_layout: vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+                final_layout: None,
+            },
+        )
     }
 
     fn init(
         device: &crate::Device,
+        surface: &crate::Surface,
         extent: vk::Extent2D,
+        config: &SwapChainConfig,
         previous_swap_chain: &vk::SwapchainKHR,
     ) -> Result<Self> {
-        let (extension, swap_chain, swap_chain_images, swap_chain_image_format, swap_chain_extent) =
-            Self::create_swap_chain(device, &extent, previous_swap_chain)?;
+        let (
+            extension,
+            swap_chain,
+            swap_chain_images,
+            surface_format,
+            swap_chain_extent,
+            present_mode,
+            swap_chain_image_usage,
+        ) = Self::create_swap_chain(device, surface, &extent, config, previous_swap_chain)?;
+        let swap_chain_image_format = surface_format.format;
+        let swap_chain_color_space = surface_format.color_space;
+        let msaa_samples = Self::choose_msaa_samples(device, config.msaa_samples);
         let swap_chain_image_views =
             Self::create_image_views(device, &swap_chain_images, swap_chain_image_format)?;
-        let render_pass = Self::create_render_pass(device, swap_chain_image_format)?;
+        let dynamic_rendering = config.dynamic_rendering && device.supports_dynamic_rendering();
+        let render_pass = if dynamic_rendering {
+            vk::RenderPass::null()
+        } else {
+            Self::create_render_pass(
+                device,
+                swap_chain_image_format,
+                msaa_samples,
+                &RenderPassOps::default(),
+            )?
+        };
         let (depth_images, depth_image_memories, depth_image_views, swap_chain_depth_format) =
-            Self::create_depth_resources(device, &swap_chain_extent, &swap_chain_images)?;
-        let swap_chain_framebuffers = Self::create_framebuffers(
-            device,
-            &swap_chain_extent,
-            &swap_chain_images,
-            &swap_chain_image_views,
-            &depth_image_views,
-            &render_pass,
-        )?;
-        let (
-            image_available_semaphores,
-            render_finished_semaphores,
-            in_flight_fences,
-            images_in_flight,
-        ) = Self::create_sync_objects(&device, &swap_chain_images)?;
+            Self::create_depth_resources(
+                device,
+                &swap_chain_extent,
+                &swap_chain_images,
+                msaa_samples,
+            )?;
+        let (color_images, color_image_memories, color_image_views) =
+            if msaa_samples != vk::SampleCountFlags::TYPE_1 {
+                Self::create_color_resources(
+                    device,
+                    &swap_chain_extent,
+                    &swap_chain_images,
+                    swap_chain_image_format,
+                    msaa_samples,
+                )?
+            } else {
+                (vec![], vec![], vec![])
+            };
+        let swap_chain_framebuffers = if dynamic_rendering {
+            vec![]
+        } else {
+            Self::create_framebuffers(
+                device,
+                &swap_chain_extent,
+                &swap_chain_images,
+                &swap_chain_image_views,
+                &depth_image_views,
+                &color_image_views,
+                &render_pass,
+            )?
+        };
+        let (image_available_semaphores, render_finished_semaphores) =
+            Self::create_sync_objects(&device, config.frames_in_flight)?;
+        let image_count = swap_chain_images.len();
 
-        Ok(Self {
+        let swap_chain = Self {
+            config: config.clone(),
+            present_mode,
+            msaa_samples,
+            swap_chain_color_space,
             swap_chain_image_format,
             swap_chain_depth_format,
             swap_chain_extent,
             swap_chain_framebuffers,
             render_pass,
+            render_pass_variants: vec![],
+            dynamic_rendering,
             depth_images,
             depth_image_memories,
             depth_image_views,
+            color_images,
+            color_image_memories,
+            color_image_views,
             swap_chain_images,
             swap_chain_image_views,
+            swap_chain_image_usage,
             window_extent: extent,
             extension,
             swap_chain,
             image_available_semaphores,
             render_finished_semaphores,
-            in_flight_fences,
-            images_in_flight,
+            frame_values: vec![0; config.frames_in_flight],
+            image_values: vec![0; image_count],
             current_frame: 0,
-        })
+        };
+
+        if let Some(hdr_metadata) = &config.hdr_metadata {
+            swap_chain.set_hdr_metadata(device, hdr_metadata)?;
+        }
+
+        Ok(swap_chain)
     }
 
     fn create_swap_chain(
         device: &crate::Device,
+        surface: &crate::Surface,
         window_extent: &vk::Extent2D,
+        config: &SwapChainConfig,
         previous_swap_chain: &vk::SwapchainKHR,
     ) -> Result<(
         vk_khr::Swapchain,
         vk::SwapchainKHR,
         Vec<vk::Image>,
-        vk::Format,
+        vk::SurfaceFormatKHR,
         vk::Extent2D,
+        vk::PresentModeKHR,
+        vk::ImageUsageFlags,
     )> {
-        let swap_chain_support = unsafe { device.swap_chain_support() }?;
-        let surface_format = Self::choose_swap_surface_format(&swap_chain_support.formats)?;
-        let present_mode = Self::choose_swap_present_mode(&swap_chain_support.present_modes);
+        let swap_chain_support = unsafe { device.swap_chain_support_for(surface) }?;
+        let surface_format =
+            Self::choose_swap_surface_format(&swap_chain_support.formats, &config.surface_formats)?;
+        let present_mode =
+            Self::choose_swap_present_mode(&swap_chain_support.present_modes, config.present_mode);
         let extent = Self::choose_swap_extent(window_extent, &swap_chain_support.capabilities);
-        let image_count = if swap_chain_support.capabilities.max_image_count > 0
-            && (swap_chain_support.capabilities.min_image_count + 1
-                > swap_chain_support.capabilities.max_image_count)
-        {
-            swap_chain_support.capabilities.max_image_count
-        } else {
-            swap_chain_support.capabilities.min_image_count + 1
+        let image_count = {
+            let capabilities = &swap_chain_support.capabilities;
+            let requested = config
+                .image_count
+                .unwrap_or(capabilities.min_image_count + 1)
+                .max(capabilities.min_image_count);
+
+            // A max_image_count of 0 means there is no upper limit
+            if capabilities.max_image_count > 0 && requested > capabilities.max_image_count {
+                capabilities.max_image_count
+            } else {
+                requested
+            }
         };
         let indices = device.find_physical_queue_families()?;
         let queue_family_indices = [
@@ -344,16 +1131,20 @@ impl SwapChain {
         ];
         let queue_family_matches = queue_family_indices[0] == queue_family_indices[1];
         let extension = vk_khr::Swapchain::new(device.instance(), device.device());
+        // TRANSFER_SRC lets Renderer::capture_frame copy the presented image
+        let image_usage = vk::ImageUsageFlags::COLOR_ATTACHMENT
+            | (swap_chain_support.capabilities.supported_usage_flags
+                & vk::ImageUsageFlags::TRANSFER_SRC);
 
         let swap_chain = {
             let create_info = vk::SwapchainCreateInfoKHR::builder()
-                .surface(*device.surface().surface())
+                .surface(*surface.surface())
                 .min_image_count(image_count)
                 .image_format(surface_format.format)
                 .image_color_space(surface_format.color_space)
                 .image_extent(extent)
                 .image_array_layers(1)
-                .image_usage(vk::ImageUsageFlags::COLOR_ATTACHMENT)
+                .image_usage(image_usage)
                 .image_sharing_mode(if queue_family_matches {
                     vk::SharingMode::EXCLUSIVE
                 } else {
@@ -383,8 +1174,10 @@ impl SwapChain {
             extension,
             swap_chain,
             swap_chain_images,
-            surface_format.format,
+            surface_format,
             extent,
+            present_mode,
+            image_usage,
         ))
     }
 
@@ -423,6 +1216,7 @@ impl SwapChain {
         device: &crate::Device,
         swap_chain_extent: &vk::Extent2D,
         swap_chain_images: &[vk::Image],
+        msaa_samples: vk::SampleCountFlags,
     ) -> Result<(
         Vec<vk::Image>,
         Vec<vk::DeviceMemory>,
@@ -444,7 +1238,7 @@ impl SwapChain {
             .tiling(vk::ImageTiling::OPTIMAL)
             .initial_layout(vk::ImageLayout::UNDEFINED)
             .usage(vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT)
-            .samples(vk::SampleCountFlags::TYPE_1)
+            .samples(msaa_samples)
             .sharing_mode(vk::SharingMode::EXCLUSIVE);
 
         let mut depth_images = (0..image_count)
@@ -488,32 +1282,127 @@ impl SwapChain {
         ))
     }
 
+    fn create_color_resources(
+        device: &crate::Device,
+        swap_chain_extent: &vk::Extent2D,
+        swap_chain_images: &[vk::Image],
+        swap_chain_image_format: vk::Format,
+        msaa_samples: vk::SampleCountFlags,
+    ) -> Result<(Vec<vk::Image>, Vec<vk::DeviceMemory>, Vec<vk::ImageView>)> {
+        let image_count = swap_chain_images.len();
+        let image_info = vk::ImageCreateInfo::builder()
+            .image_type(vk::ImageType::TYPE_2D)
+            .extent(vk::Extent3D {
+                width: swap_chain_extent.width,
+                height: swap_chain_extent.height,
+                depth: 1,
+            })
+            .mip_levels(1)
+            .array_layers(1)
+            .format(swap_chain_image_format)
+            .tiling(vk::ImageTiling::OPTIMAL)
+            .initial_layout(vk::ImageLayout::UNDEFINED)
+            // Not TRANSIENT_ATTACHMENT: RenderPassOps can store it at the end of one swap
+            //  chain pass and load it in the next, which lazily allocated memory can't back
+            .usage(vk::ImageUsageFlags::COLOR_ATTACHMENT)
+            .samples(msaa_samples)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE);
+
+        let mut color_images = Vec::with_capacity(image_count);
+        let mut color_image_memories = Vec::with_capacity(image_count);
+        let mut color_image_views = Vec::with_capacity(image_count);
+
+        for _ in 0..image_count {
+            let (image, image_memory) = device
+                .create_image_with_info(&image_info, vk::MemoryPropertyFlags::DEVICE_LOCAL)?;
+            let image_view = {
+                let create_info = vk::ImageViewCreateInfo::builder()
+                    .image(image)
+                    .view_type(vk::ImageViewType::TYPE_2D)
+                    .format(swap_chain_image_format)
+                    .subresource_range(vk::ImageSubresourceRange {
+                        aspect_mask: vk::ImageAspectFlags::COLOR,
+                        base_mip_level: 0,
+                        level_count: 1,
+                        base_array_layer: 0,
+                        layer_count: 1,
+                    });
+
+                unsafe { device.device().create_image_view(&create_info, None) }?
+            };
+
+            color_images.push(image);
+            color_image_memories.push(image_memory);
+            color_image_views.push(image_view);
+        }
+
+        Ok((color_images, color_image_memories, color_image_views))
+    }
+
     fn create_render_pass(
         device: &crate::Device,
         swap_chain_image_format: vk::Format,
+        msaa_samples: vk::SampleCountFlags,
+        ops: &RenderPassOps,
     ) -> Result<vk::RenderPass> {
-        let attachment = [
+        let multisampled = msaa_samples != vk::SampleCountFlags::TYPE_1;
+        // Where the previous swap chain pass of the frame left the attachments
+        let color_initial_layout = match (ops.color.load, multisampled) {
+            (LoadOp::Load, true) => vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            (LoadOp::Load, false) => vk::ImageLayout::PRESENT_SRC_KHR,
+            _ => vk::ImageLayout::UNDEFINED,
+        };
+        let depth_initial_layout = if ops.loads_depth_stencil() {
+            vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL
+        } else {
+            vk::ImageLayout::UNDEFINED
+        };
+        // With MSAA, attachment 0 is the multisampled image and the swap chain image is
+        //  attached last as the resolve target
+        let mut attachment = vec![
             vk::AttachmentDescription::builder()
                 .format(swap_chain_image_format)
-                .samples(vk::SampleCountFlags::TYPE_1)
-                .load_op(vk::AttachmentLoadOp::CLEAR)
-                .store_op(vk::AttachmentStoreOp::STORE)
+                .samples(msaa_samples)
+                .load_op(ops.color.load.vk_load_op())
+                .store_op(if multisampled {
+                    ops.color.store.vk_store_op()
+                } else {
+                    vk::AttachmentStoreOp::STORE
+                })
                 .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
                 .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
-                .initial_layout(vk::ImageLayout::UNDEFINED)
-                .final_layout(vk::ImageLayout::PRESENT_SRC_KHR)
+                .initial_layout(color_initial_layout)
+                .final_layout(if multisampled {
+                    vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL
+                } else {
+                    vk::ImageLayout::PRESENT_SRC_KHR
+                })
                 .build(),
             vk::AttachmentDescription::builder()
                 .format(Self::find_depth_format_from_device(device)?)
-                .samples(vk::SampleCountFlags::TYPE_1)
-                .load_op(vk::AttachmentLoadOp::CLEAR)
-                .store_op(vk::AttachmentStoreOp::DONT_CARE)
-                .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
-                .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
-                .initial_layout(vk::ImageLayout::UNDEFINED)
+                .samples(msaa_samples)
+                .load_op(ops.depth.load.vk_load_op())
+                .store_op(ops.depth.store.vk_store_op())
+                .stencil_load_op(ops.stencil.load.vk_load_op())
+                .stencil_store_op(ops.stencil.store.vk_store_op())
+                .initial_layout(depth_initial_layout)
                 .final_layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL)
                 .build(),
         ];
+        if multisampled {
+            attachment.push(
+                vk::AttachmentDescription::builder()
+                    .format(swap_chain_image_format)
+                    .samples(vk::SampleCountFlags::TYPE_1)
+                    .load_op(vk::AttachmentLoadOp::DONT_CARE)
+                    .store_op(vk::AttachmentStoreOp::STORE)
+                    .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
+                    .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+                    .initial_layout(vk::ImageLayout::UNDEFINED)
+                    .final_layout(vk::ImageLayout::PRESENT_SRC_KHR)
+                    .build(),
+            );
+        }
         let color_attachment = vk::AttachmentReference::builder()
             .attachment(0)
             .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
@@ -522,29 +1411,66 @@ impl SwapChain {
             .attachment(1)
             .layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL)
             .build();
+        let resolve_attachment = vk::AttachmentReference::builder()
+            .attachment(2)
+            .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+            .build();
         let subpass = {
-            vk::SubpassDescription::builder()
+            let subpass = vk::SubpassDescription::builder()
                 .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
                 .color_attachments(std::slice::from_ref(&color_attachment))
-                .depth_stencil_attachment(&depth_stencil_attachment)
+                .depth_stencil_attachment(&depth_stencil_attachment);
+
+            if multisampled {
+                subpass.resolve_attachments(std::slice::from_ref(&resolve_attachment))
+            } else {
+                subpass
+            }
+        };
+        let loads = ops.color.load == LoadOp::Load || ops.loads_depth_stencil();
+        let dependency = if loads {
+            // Loaded contents were written by the previous pass and must be visible
+            vk::SubpassDependency::builder()
+                .src_subpass(vk::SUBPASS_EXTERNAL)
+                .src_stage_mask(
+                    vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+                        | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+                )
+                .src_access_mask(
+                    vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+                        | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+                )
+                .dst_subpass(0)
+                .dst_stage_mask(
+                    vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+                        | vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS,
+                )
+                .dst_access_mask(
+                    vk::AccessFlags::COLOR_ATTACHMENT_READ
+                        | vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+                        | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ
+                        | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+                )
+                .build()
+        } else {
+            vk::SubpassDependency::builder()
+                .src_subpass(vk::SUBPASS_EXTERNAL)
+                .src_stage_mask(
+                    vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+                        | vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS,
+                )
+                .src_access_mask(vk::AccessFlags::empty())
+                .dst_subpass(0)
+                .dst_stage_mask(
+                    vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+                        | vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS,
+                )
+                .dst_access_mask(
+                    vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+                        | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+                )
+                .build()
         };
-        let dependency = vk::SubpassDependency::builder()
-            .src_subpass(vk::SUBPASS_EXTERNAL)
-            .src_stage_mask(
-                vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
-                    | vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS,
-            )
-            .src_access_mask(vk::AccessFlags::empty())
-            .dst_subpass(0)
-            .dst_stage_mask(
-                vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
-                    | vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS,
-            )
-            .dst_access_mask(
-                vk::AccessFlags::COLOR_ATTACHMENT_WRITE
-                    | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
-            )
-            .build();
         let create_info = vk::RenderPassCreateInfo::builder()
             .attachments(&attachment)
             .subpasses(std::slice::from_ref(&subpass))
@@ -560,6 +1486,7 @@ impl SwapChain {
         swap_chain_images: &[vk::Image],
         swap_chain_image_views: &[vk::ImageView],
         depth_image_views: &[vk::ImageView],
+        color_image_views: &[vk::ImageView],
         render_pass: &vk::RenderPass,
     ) -> Result<Vec<vk::Framebuffer>> {
         let image_count = swap_chain_images.len();
@@ -570,7 +1497,16 @@ impl SwapChain {
         );
 
         for index in 0..image_count {
-            let attachments = [swap_chain_image_views[index], depth_image_views[index]];
+            // Must match the attachment order of create_render_pass
+            let attachments = if color_image_views.is_empty() {
+                vec![swap_chain_image_views[index], depth_image_views[index]]
+            } else {
+                vec![
+                    color_image_views[index],
+                    depth_image_views[index],
+                    swap_chain_image_views[index],
+                ]
+            };
             let create_info = vk::FramebufferCreateInfo::builder()
                 .render_pass(*render_pass)
                 .attachments(&attachments)
@@ -588,85 +1524,105 @@ impl SwapChain {
 
     fn create_sync_objects(
         device: &crate::Device,
-        swap_chain_images: &[vk::Image],
-    ) -> Result<(
-        Vec<vk::Semaphore>,
-        Vec<vk::Semaphore>,
-        Vec<vk::Fence>,
-        Vec<vk::Fence>,
-    )> {
+        frames_in_flight: usize,
+    ) -> Result<(Vec<vk::Semaphore>, Vec<vk::Semaphore>)> {
         let mut image_available_semaphores = Vec::from_iter(
-            (0..Self::MAX_FRAMES_IN_FLIGHT)
+            (0..frames_in_flight)
                 .into_iter()
                 .map(|_| vk::Semaphore::null()),
         );
         let mut render_finished_semaphores = Vec::from_iter(
-            (0..Self::MAX_FRAMES_IN_FLIGHT)
+            (0..frames_in_flight)
                 .into_iter()
                 .map(|_| vk::Semaphore::null()),
         );
-        let mut in_flight_fences = Vec::from_iter(
-            (0..Self::MAX_FRAMES_IN_FLIGHT)
-                .into_iter()
-                .map(|_| vk::Fence::null()),
-        );
-        let image_count = swap_chain_images.len();
-        let images_in_flight = (0..image_count)
-            .into_iter()
-            .map(|_| vk::Fence::null())
-            .collect::<Vec<_>>();
         let semaphore_info = vk::SemaphoreCreateInfo::builder();
-        let fence_info = vk::FenceCreateInfo::builder().flags(vk::FenceCreateFlags::SIGNALED);
 
-        for index in 0..Self::MAX_FRAMES_IN_FLIGHT as usize {
+        for index in 0..frames_in_flight {
             image_available_semaphores[index] =
                 unsafe { device.device().create_semaphore(&semaphore_info, None) }?;
             render_finished_semaphores[index] =
                 unsafe { device.device().create_semaphore(&semaphore_info, None) }?;
-            in_flight_fences[index] = unsafe { device.device().create_fence(&fence_info, None) }?;
         }
 
-        Ok((
-            image_available_semaphores,
-            render_finished_semaphores,
-            in_flight_fences,
-            images_in_flight,
-        ))
+        Ok((image_available_semaphores, render_finished_semaphores))
     }
 
     /* --- Helper functions --- */
 
     fn choose_swap_surface_format(
         available_formats: &Vec<vk::SurfaceFormatKHR>,
+        preferred_formats: &[vk::SurfaceFormatKHR],
     ) -> Result<vk::SurfaceFormatKHR> {
-        for available_format in available_formats {
-            if available_format.format == vk::Format::B8G8R8A8_SRGB
-                && available_format.color_space == vk::ColorSpaceKHR::SRGB_NONLINEAR
-            {
-                return Ok(*available_format);
-            }
-        }
-
-        Ok(*available_formats
+        let surface_format = match preferred_formats
             .iter()
-            .next()
-            .context("No format was available")?)
+            .find(|preferred_format| available_formats.contains(preferred_format))
+        {
+            Some(preferred_format) => *preferred_format,
+            None => *available_formats
+                .iter()
+                .next()
+                .context("No format was available")?,
+        };
+
+        println!(
+            "Surface format: {:?} / {:?}",
+            surface_format.format, surface_format.color_space
+        );
+
+        Ok(surface_format)
+    }
+
+    fn is_hdr_color_space(color_space: vk::ColorSpaceKHR) -> bool {
+        matches!(
+            color_space,
+            vk::ColorSpaceKHR::HDR10_ST2084_EXT
+                | vk::ColorSpaceKHR::HDR10_HLG_EXT
+                | vk::ColorSpaceKHR::EXTENDED_SRGB_LINEAR_EXT
+                | vk::ColorSpaceKHR::EXTENDED_SRGB_NONLINEAR_EXT
+                | vk::ColorSpaceKHR::BT2020_LINEAR_EXT
+                | vk::ColorSpaceKHR::DOLBYVISION_EXT
+        )
     }
 
     fn choose_swap_present_mode(
         available_present_modes: &Vec<vk::PresentModeKHR>,
+        preferred_present_mode: PresentMode,
     ) -> vk::PresentModeKHR {
-        for availbale_present_mode in available_present_modes {
-            if *availbale_present_mode == vk::PresentModeKHR::MAILBOX {
-                println!("Present mode: Mailbox");
-
-                return *availbale_present_mode;
-            }
+        let present_mode = preferred_present_mode
+            .fallback_chain()
+            .iter()
+            .find(|present_mode| available_present_modes.contains(present_mode))
+            .copied()
+            .unwrap_or(vk::PresentModeKHR::FIFO);
+
+        if present_mode != preferred_present_mode.fallback_chain()[0] {
+            eprintln!(
+                "Present mode {:?} is not supported, falling back to {:?}",
+                preferred_present_mode, present_mode
+            );
         }
+        println!("Present mode: {:?}", present_mode);
 
-        println!("Present mode: V-Sync");
+        present_mode
+    }
 
-        vk::PresentModeKHR::FIFO
+    fn choose_msaa_samples(
+        device: &crate::Device,
+        requested_samples: vk::SampleCountFlags,
+    ) -> vk::SampleCountFlags {
+        let max_samples = device.max_usable_sample_count();
+
+        if requested_samples.as_raw() > max_samples.as_raw() {
+            eprintln!(
+                "MSAA {:?} is not supported, clamping to {:?}",
+                requested_samples, max_samples
+            );
+
+            max_samples
+        } else {
+            requested_samples
+        }
     }
 
     fn choose_swap_extent(
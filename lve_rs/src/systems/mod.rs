@@ -1,5 +1,7 @@
 pub mod point_light_system;
+pub mod render_system;
 pub mod simple_render_system;
 
 pub use point_light_system::PointLightSystem;
+pub use render_system::{RenderSystem, RenderSystemRegistry};
 pub use simple_render_system::{SimplePushConstantData, SimpleRenderSystem};
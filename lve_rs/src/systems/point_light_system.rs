@@ -1,6 +1,6 @@
 use anyhow::Result;
 use ash::vk;
-use std::{collections::HashMap, mem::size_of};
+use std::{collections::HashMap, mem::size_of, sync::Arc};
 
 #[repr(C, align(16))]
 pub struct PointLightPushConstants {
@@ -12,19 +12,24 @@ pub struct PointLightPushConstants {
 pub struct PointLightSystem {
     pipeline: Box<crate::Pipeline>,
     pipeline_layout: vk::PipelineLayout,
+    assets: Arc<crate::AssetLoader>,
 }
 
 impl PointLightSystem {
+    pub const MAX_LIGHTS_CONSTANT_ID: u32 = 0;
+
     pub fn new(
         device: &crate::Device,
-        render_pass: &vk::RenderPass,
+        render_pass_info: &crate::RenderPassInfo,
         global_set_layout: &vk::DescriptorSetLayout,
+        assets: &Arc<crate::AssetLoader>,
     ) -> Result<Self> {
         let pipeline_layout = Self::create_pipeline_layout(device, global_set_layout)?;
-        let pipeline = Self::create_pipeline(device, &pipeline_layout, render_pass)?;
+        let pipeline = Self::create_pipeline(device, &pipeline_layout, render_pass_info, assets)?;
         Ok(Self {
             pipeline_layout,
             pipeline,
+            assets: Arc::clone(assets),
         })
     }
 
@@ -35,23 +40,16 @@ impl PointLightSystem {
             .destroy_pipeline_layout(self.pipeline_layout, None);
     }
 
-    pub fn update(&self, frame_info: &mut crate::FrameInfo, ubo: &mut crate::GlobalUbo) {
+    // Fixed update step; rotates the lights by `step` seconds worth of motion
+    pub fn fixed_update(game_objects: &mut crate::Map, step: f32) {
         let rotate_light = glm::rotate(
             &glm::Mat4::identity(),
-            20.0 * frame_info.frame_time,
+            20.0 * step,
             &glm::vec3(0.0, -1.0, 0.0),
         );
-        let mut light_index = 0;
-
-        for kv in frame_info.game_objects.iter_mut() {
-            let obj = kv.1;
-
-            if let Some(point_light) = obj.point_light {
-                assert!(
-                    light_index < crate::frame_info::MAX_LIGHT,
-                    "Point lights exceed maximum specified"
-                );
 
+        for obj in game_objects.values_mut() {
+            if obj.point_light.is_some() {
                 obj.transform.translation = (rotate_light
                     * glm::vec4(
                         obj.transform.translation.x,
@@ -60,12 +58,30 @@ impl PointLightSystem {
                         1.0,
                     ))
                 .xyz();
-                ubo.point_lights[light_index].position = glm::vec4(
-                    obj.transform.translation.x,
-                    obj.transform.translation.y,
-                    obj.transform.translation.z,
-                    1.0,
+            }
+        }
+    }
+
+    // Writes the lights at their interpolated positions, in object id order so
+    //  the shaders sum them up the same way in every run
+    pub fn write_lights(&self, frame_info: &crate::FrameInfo, ubo: &mut crate::GlobalUbo) {
+        let mut ids = frame_info.game_objects.keys().copied().collect::<Vec<_>>();
+        let mut light_index = 0;
+
+        ids.sort_unstable();
+        for id in ids {
+            let obj = &frame_info.game_objects[&id];
+
+            if let Some(point_light) = obj.point_light {
+                assert!(
+                    light_index < ubo.point_lights.len(),
+                    "Point lights exceed maximum specified"
                 );
+
+                let translation = obj.interpolated_transform(frame_info.alpha).translation;
+
+                ubo.point_lights[light_index].position =
+                    glm::vec4(translation.x, translation.y, translation.z, 1.0);
                 ubo.point_lights[light_index].color = glm::vec4(
                     obj.color.x,
                     obj.color.y,
@@ -85,7 +101,8 @@ impl PointLightSystem {
             let obj = kv.1;
 
             if obj.point_light.is_some() {
-                let offset = frame_info.camera.position() - obj.transform.translation;
+                let offset = frame_info.camera.position()
+                    - obj.interpolated_transform(frame_info.alpha).translation;
                 let distance_squared = offset.dot(&offset);
 
                 sorted.insert(ordered_float::OrderedFloat(distance_squared), obj.id());
@@ -105,18 +122,16 @@ impl PointLightSystem {
             std::slice::from_ref(&frame_info.global_descriptor_set),
             &[],
         );
+        frame_info.draw_counters.record_pipeline_bind();
+        frame_info.draw_counters.record_descriptor_bind();
 
         for kv in sorted.iter().rev() {
             let obj = &frame_info.game_objects[&kv.1];
 
             if let Some(point_light) = &obj.point_light {
+                let translation = obj.interpolated_transform(frame_info.alpha).translation;
                 let push = PointLightPushConstants {
-                    position: glm::vec4(
-                        obj.transform.translation.x,
-                        obj.transform.translation.y,
-                        obj.transform.translation.z,
-                        1.0,
-                    ),
+                    position: glm::vec4(translation.x, translation.y, translation.z, 1.0),
                     color: glm::vec4(
                         obj.color.x,
                         obj.color.y,
@@ -160,8 +175,11 @@ impl PointLightSystem {
                     push_constant_offsets[2],
                     bytemuck::cast_slice(std::slice::from_ref(&push.radius)),
                 );
+                frame_info.draw_counters.record_push_constants(3);
             }
             device_ref.cmd_draw(frame_info.command_buffer, 6, 1, 0, 0);
+            // Billboard quad
+            frame_info.draw_counters.record_draw(2);
         }
     }
 
@@ -187,7 +205,8 @@ impl PointLightSystem {
     fn create_pipeline(
         device: &crate::Device,
         pipeline_layout: &vk::PipelineLayout,
-        render_pass: &vk::RenderPass,
+        render_pass_info: &crate::RenderPassInfo,
+        assets: &crate::AssetLoader,
     ) -> Result<Box<crate::Pipeline>> {
         assert!(
             *pipeline_layout != vk::PipelineLayout::null(),
@@ -198,14 +217,59 @@ impl PointLightSystem {
 
         config_info.binding_descriptions.clear();
         config_info.attribute_descriptions.clear();
-        config_info.render_pass = *render_pass;
+        config_info.set_render_pass_info(render_pass_info);
         config_info.pipeline_layout = *pipeline_layout;
+        let light_constants = crate::SpecializationConstants::new().set(
+            Self::MAX_LIGHTS_CONSTANT_ID,
+            <crate::GlobalUbo>::MAX_LIGHTS as i32,
+        );
+        let stages = [
+            crate::ShaderStage::vertex(assets.shader_source("shaders/point_light.vert.spv")?)
+                .specialization_constants(light_constants.clone()),
+            crate::ShaderStage::fragment(assets.shader_source("shaders/point_light.frag.spv")?)
+                .specialization_constants(light_constants),
+        ];
 
         Ok(Box::new(crate::Pipeline::new(
             &device,
-            "./shaders/point_light.vert.spv",
-            "./shaders/point_light.frag.spv",
+            &stages,
             &config_info,
         )?))
     }
 }
+
+impl crate::RenderSystem for PointLightSystem {
+    fn name(&self) -> &str {
+        "point light system"
+    }
+
+    fn setup(
+        &mut self,
+        device: &crate::Device,
+        render_pass_info: &crate::RenderPassInfo,
+    ) -> Result<()> {
+        let pipeline = Self::create_pipeline(
+            device,
+            &self.pipeline_layout,
+            render_pass_info,
+            &self.assets,
+        )?;
+
+        unsafe { self.pipeline.destroy(device) };
+        self.pipeline = pipeline;
+
+        Ok(())
+    }
+
+    fn update(&mut self, frame_info: &mut crate::FrameInfo, ubo: &mut crate::GlobalUbo) {
+        self.write_lights(frame_info, ubo);
+    }
+
+    unsafe fn render(&self, device: &crate::Device, frame_info: &mut crate::FrameInfo) {
+        PointLightSystem::render(self, device, frame_info);
+    }
+
+    unsafe fn destroy(&mut self, device: &crate::Device) {
+        PointLightSystem::destroy(self, device);
+    }
+}
@@ -0,0 +1,180 @@
+use anyhow::Result;
+use ash::vk;
+
+/* MEMO
+ *  Common interface of the systems that draw into the swap chain render pass.
+ *  A system builds its pipelines in its own constructor, against
+ *  Renderer::swap_chain_render_pass_info, and is then handed to
+ *  Renderer::add_render_system, which calls the hooks:
+ *   - setup when a recreated swap chain is no longer compatible with the
+ *     pipelines (formats, sample count or dynamic rendering changed)
+ *   - resize when the swap chain extent changed
+ *   - update once per frame, before the global UBO is written
+ *   - render inside the swap chain render pass, or record_secondary when the
+ *     renderer records it on worker threads (see Renderer::set_worker_threads)
+ */
+pub trait RenderSystem {
+    // Used for profiler scopes
+    fn name(&self) -> &str;
+
+    // Rebuilds the pipelines for `render_pass_info`; the device is idle
+    fn setup(
+        &mut self,
+        device: &crate::Device,
+        render_pass_info: &crate::RenderPassInfo,
+    ) -> Result<()>;
+
+    fn resize(&mut self, _device: &crate::Device, _extent: vk::Extent2D) -> Result<()> {
+        Ok(())
+    }
+
+    fn update(&mut self, _frame_info: &mut crate::FrameInfo, _ubo: &mut crate::GlobalUbo) {}
+
+    unsafe fn render(&self, device: &crate::Device, frame_info: &mut crate::FrameInfo);
+
+    // By default `render` goes into a single secondary command buffer on the calling thread
+    unsafe fn record_secondary(
+        &self,
+        device: &crate::Device,
+        frame_info: &mut crate::FrameInfo,
+        recorder: &mut crate::SecondaryRecorder,
+    ) -> Result<()> {
+        let primary_command_buffer = frame_info.command_buffer;
+        let result = recorder.record(device, |command_buffer| {
+            frame_info.command_buffer = *command_buffer;
+            self.render(device, frame_info);
+        });
+
+        frame_info.command_buffer = primary_command_buffer;
+
+        result
+    }
+
+    unsafe fn destroy(&mut self, device: &crate::Device);
+}
+
+// Systems run in the order they were added
+#[derive(Default)]
+pub struct RenderSystemRegistry {
+    systems: Vec<Box<dyn RenderSystem>>,
+    render_pass_info: Option<crate::RenderPassInfo>,
+    extent: vk::Extent2D,
+}
+
+impl RenderSystemRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub unsafe fn destroy(&mut self, device: &crate::Device) {
+        for system in self.systems.iter_mut() {
+            system.destroy(device);
+        }
+        self.systems.clear();
+    }
+
+    // `render_pass_info` and `extent` are what the system was built for
+    pub fn add(
+        &mut self,
+        system: Box<dyn RenderSystem>,
+        render_pass_info: crate::RenderPassInfo,
+        extent: vk::Extent2D,
+    ) {
+        self.render_pass_info.get_or_insert(render_pass_info);
+        self.extent = extent;
+        self.systems.push(system);
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.systems.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.systems.is_empty()
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.systems.iter().map(|system| system.name())
+    }
+
+    // Called with the device idle whenever the swap chain was replaced
+    pub fn swap_chain_changed(
+        &mut self,
+        device: &crate::Device,
+        render_pass_info: crate::RenderPassInfo,
+        extent: vk::Extent2D,
+    ) -> Result<()> {
+        if self.render_pass_info.map_or(false, |current| {
+            !current.is_compatible_with(&render_pass_info)
+        }) {
+            for system in self.systems.iter_mut() {
+                system.setup(device, &render_pass_info)?;
+            }
+        }
+        self.render_pass_info = Some(render_pass_info);
+
+        if self.extent != extent {
+            for system in self.systems.iter_mut() {
+                system.resize(device, extent)?;
+            }
+            self.extent = extent;
+        }
+
+        Ok(())
+    }
+
+    pub fn update(&mut self, frame_info: &mut crate::FrameInfo, ubo: &mut crate::GlobalUbo) {
+        for system in self.systems.iter_mut() {
+            system.update(frame_info, ubo);
+        }
+    }
+
+    // Each system gets a profiler scope named after it
+    pub unsafe fn render(
+        &self,
+        device: &crate::Device,
+        frame_info: &mut crate::FrameInfo,
+        mut profiler: Option<&mut crate::GpuProfiler>,
+    ) {
+        for system in self.systems.iter() {
+            let command_buffer = frame_info.command_buffer;
+            let scope = profiler
+                .as_mut()
+                .map(|profiler| profiler.begin_scope(device, &command_buffer, system.name()));
+
+            system.render(device, frame_info);
+            if let (Some(profiler), Some(scope)) = (profiler.as_mut(), scope) {
+                profiler.end_scope(device, &command_buffer, scope);
+            }
+        }
+    }
+
+    // Like render, but into `recorder`; the profiler scopes get command buffers of their own
+    pub unsafe fn record_secondary(
+        &self,
+        device: &crate::Device,
+        frame_info: &mut crate::FrameInfo,
+        recorder: &mut crate::SecondaryRecorder,
+        mut profiler: Option<&mut crate::GpuProfiler>,
+    ) -> Result<()> {
+        for system in self.systems.iter() {
+            let mut scope = None;
+
+            if let Some(profiler) = profiler.as_mut() {
+                recorder.record(device, |command_buffer| {
+                    scope = Some(profiler.begin_scope(device, command_buffer, system.name()));
+                })?;
+            }
+            system.record_secondary(device, frame_info, recorder)?;
+            if let (Some(profiler), Some(scope)) = (profiler.as_mut(), scope) {
+                recorder.record(device, |command_buffer| {
+                    profiler.end_scope(device, command_buffer, scope)
+                })?;
+            }
+        }
+
+        Ok(())
+    }
+}
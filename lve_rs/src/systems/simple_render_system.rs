@@ -1,6 +1,6 @@
 use anyhow::Result;
 use ash::vk;
-use std::mem::size_of;
+use std::{mem::size_of, sync::Arc};
 
 #[derive(Default)]
 #[repr(C, align(16))]
@@ -9,22 +9,60 @@ pub struct SimplePushConstantData {
     normal_matrix: glm::Mat4,
 }
 
+// What a worker thread needs to record the draw of a game object
+struct ObjectDraw {
+    transform: crate::TransformComponent,
+    model: crate::ModelDraw,
+}
+
 pub struct SimpleRenderSystem {
     pipeline: Box<crate::Pipeline>,
     pipeline_layout: vk::PipelineLayout,
+    specular_exponent: f32,
+    // Shared with the other systems; shaders are loaded again on setup
+    assets: Arc<crate::AssetLoader>,
 }
 
 impl SimpleRenderSystem {
+    pub const MAX_LIGHTS_CONSTANT_ID: u32 = 0;
+    pub const SPECULAR_EXPONENT_CONSTANT_ID: u32 = 1;
+    pub const DEFAULT_SPECULAR_EXPONENT: f32 = 32.0;
+
     pub fn new(
         device: &crate::Device,
-        render_pass: &vk::RenderPass,
+        render_pass_info: &crate::RenderPassInfo,
+        global_set_layout: &vk::DescriptorSetLayout,
+        assets: &Arc<crate::AssetLoader>,
+    ) -> Result<Self> {
+        Self::with_specular_exponent(
+            device,
+            render_pass_info,
+            global_set_layout,
+            assets,
+            Self::DEFAULT_SPECULAR_EXPONENT,
+        )
+    }
+
+    pub fn with_specular_exponent(
+        device: &crate::Device,
+        render_pass_info: &crate::RenderPassInfo,
         global_set_layout: &vk::DescriptorSetLayout,
+        assets: &Arc<crate::AssetLoader>,
+        specular_exponent: f32,
     ) -> Result<Self> {
         let pipeline_layout = Self::create_pipeline_layout(device, global_set_layout)?;
-        let pipeline = Self::create_pipeline(device, &pipeline_layout, render_pass)?;
+        let pipeline = Self::create_pipeline(
+            device,
+            &pipeline_layout,
+            render_pass_info,
+            assets,
+            specular_exponent,
+        )?;
         Ok(Self {
             pipeline_layout,
             pipeline,
+            specular_exponent,
+            assets: Arc::clone(assets),
         })
     }
 
@@ -40,54 +78,100 @@ impl SimpleRenderSystem {
         device: &crate::Device,
         frame_info: &mut crate::FrameInfo,
     ) {
-        let device_ref = device.device();
+        let draws = Self::collect_draws(frame_info);
+
+        self.record_draws(
+            device.device(),
+            &frame_info.command_buffer,
+            frame_info.global_descriptor_set,
+            &draws,
+        );
+        frame_info.draw_counters.record_pipeline_bind();
+        frame_info.draw_counters.record_descriptor_bind();
+    }
+
+    // In object id order, so the draw order doesn't depend on how recording is split up
+    fn collect_draws(frame_info: &crate::FrameInfo) -> Vec<ObjectDraw> {
+        let mut ids = frame_info.game_objects.keys().copied().collect::<Vec<_>>();
+        let mut draws = Vec::with_capacity(ids.len());
+
+        ids.sort_unstable();
+        for id in ids {
+            let game_object = &frame_info.game_objects[&id];
+
+            if let Some(model) = &game_object.model {
+                let model = model.borrow().draw_command();
+
+                frame_info.draw_counters.record_push_constants(2);
+                frame_info
+                    .draw_counters
+                    .record_draw(model.triangle_count() as u64);
+                draws.push(ObjectDraw {
+                    transform: game_object.interpolated_transform(frame_info.alpha),
+                    model,
+                });
+            }
+        }
+
+        draws
+    }
+
+    // Takes the ash::Device only, so it can run on any thread
+    unsafe fn record_draws(
+        &self,
+        device: &ash::Device,
+        command_buffer: &vk::CommandBuffer,
+        global_descriptor_set: vk::DescriptorSet,
+        draws: &[ObjectDraw],
+    ) {
+        let offsets = {
+            let normal_matrix = bytemuck::offset_of!(SimplePushConstantData, normal_matrix) as u32;
+            let aligned_offset = |offset: u32| {
+                if offset % 16 == 0 {
+                    offset
+                } else {
+                    (offset / 16 + 1) * 16
+                }
+            };
+
+            [0, aligned_offset(normal_matrix)]
+        };
 
-        self.pipeline.bind(device, &frame_info.command_buffer);
-        device_ref.cmd_bind_descriptor_sets(
-            frame_info.command_buffer,
+        device.cmd_bind_pipeline(
+            *command_buffer,
+            vk::PipelineBindPoint::GRAPHICS,
+            self.pipeline.graphics_pipeline(),
+        );
+        device.cmd_bind_descriptor_sets(
+            *command_buffer,
             vk::PipelineBindPoint::GRAPHICS,
             self.pipeline_layout,
             0,
-            std::slice::from_ref(&frame_info.global_descriptor_set),
+            std::slice::from_ref(&global_descriptor_set),
             &[],
         );
-        for key in frame_info.game_objects.keys() {
-            if let Some(model) = &frame_info.game_objects[key].model {
-                let push = SimplePushConstantData {
-                    model_matrix: frame_info.game_objects[key].transform.mat4(),
-                    normal_matrix: frame_info.game_objects[key].transform.normal_matrix(),
-                };
-                let offsets = {
-                    let normal_matrix =
-                        bytemuck::offset_of!(SimplePushConstantData, normal_matrix) as u32;
-                    let aligned_offset = |offset: u32| {
-                        if offset % 16 == 0 {
-                            offset
-                        } else {
-                            (offset / 16 + 1) * 16
-                        }
-                    };
-
-                    [0, aligned_offset(normal_matrix)]
-                };
-
-                device_ref.cmd_push_constants(
-                    frame_info.command_buffer,
-                    self.pipeline_layout,
-                    vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
-                    offsets[0],
-                    bytemuck::cast_slice(push.model_matrix.as_slice()),
-                );
-                device_ref.cmd_push_constants(
-                    frame_info.command_buffer,
-                    self.pipeline_layout,
-                    vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
-                    offsets[1],
-                    bytemuck::cast_slice(push.normal_matrix.as_slice()),
-                );
-                model.borrow().bind(device, &frame_info.command_buffer);
-                model.borrow().draw(device, &frame_info.command_buffer);
-            }
+        for draw in draws {
+            let push = SimplePushConstantData {
+                model_matrix: draw.transform.mat4(),
+                normal_matrix: draw.transform.normal_matrix(),
+            };
+
+            device.cmd_push_constants(
+                *command_buffer,
+                self.pipeline_layout,
+                vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+                offsets[0],
+                bytemuck::cast_slice(push.model_matrix.as_slice()),
+            );
+            device.cmd_push_constants(
+                *command_buffer,
+                self.pipeline_layout,
+                vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+                offsets[1],
+                bytemuck::cast_slice(push.normal_matrix.as_slice()),
+            );
+            draw.model.bind(device, command_buffer);
+            draw.model.draw(device, command_buffer);
         }
     }
 
@@ -113,7 +197,9 @@ impl SimpleRenderSystem {
     fn create_pipeline(
         device: &crate::Device,
         pipeline_layout: &vk::PipelineLayout,
-        render_pass: &vk::RenderPass,
+        render_pass_info: &crate::RenderPassInfo,
+        assets: &crate::AssetLoader,
+        specular_exponent: f32,
     ) -> Result<Box<crate::Pipeline>> {
         assert!(
             *pipeline_layout != vk::PipelineLayout::null(),
@@ -122,14 +208,81 @@ impl SimpleRenderSystem {
 
         let mut config_info = crate::Pipeline::default_pipeline_config_info();
 
-        config_info.render_pass = *render_pass;
+        config_info.set_render_pass_info(render_pass_info);
         config_info.pipeline_layout = *pipeline_layout;
+        let light_constants = crate::SpecializationConstants::new().set(
+            Self::MAX_LIGHTS_CONSTANT_ID,
+            <crate::GlobalUbo>::MAX_LIGHTS as i32,
+        );
+        let stages = [
+            crate::ShaderStage::vertex(assets.shader_source("shaders/simple_shader.vert.spv")?)
+                .specialization_constants(light_constants.clone()),
+            crate::ShaderStage::fragment(assets.shader_source("shaders/simple_shader.frag.spv")?)
+                .specialization_constants(
+                    light_constants.set(Self::SPECULAR_EXPONENT_CONSTANT_ID, specular_exponent),
+                ),
+        ];
 
         Ok(Box::new(crate::Pipeline::new(
             &device,
-            "./shaders/simple_shader.vert.spv",
-            "./shaders/simple_shader.frag.spv",
+            &stages,
             &config_info,
         )?))
     }
 }
+
+impl crate::RenderSystem for SimpleRenderSystem {
+    fn name(&self) -> &str {
+        "simple render system"
+    }
+
+    fn setup(
+        &mut self,
+        device: &crate::Device,
+        render_pass_info: &crate::RenderPassInfo,
+    ) -> Result<()> {
+        let pipeline = Self::create_pipeline(
+            device,
+            &self.pipeline_layout,
+            render_pass_info,
+            &self.assets,
+            self.specular_exponent,
+        )?;
+
+        unsafe { self.pipeline.destroy(device) };
+        self.pipeline = pipeline;
+
+        Ok(())
+    }
+
+    unsafe fn render(&self, device: &crate::Device, frame_info: &mut crate::FrameInfo) {
+        self.render_game_objects(device, frame_info);
+    }
+
+    // Splits the game objects across the worker threads
+    unsafe fn record_secondary(
+        &self,
+        device: &crate::Device,
+        frame_info: &mut crate::FrameInfo,
+        recorder: &mut crate::SecondaryRecorder,
+    ) -> Result<()> {
+        let draws = Self::collect_draws(frame_info);
+        let global_descriptor_set = frame_info.global_descriptor_set;
+        // Every command buffer binds the pipeline and descriptor set again
+        let command_buffers =
+            recorder.record_parallel(device, &draws, |device, command_buffer, draws| {
+                self.record_draws(device, command_buffer, global_descriptor_set, draws)
+            })?;
+
+        for _ in 0..command_buffers {
+            frame_info.draw_counters.record_pipeline_bind();
+            frame_info.draw_counters.record_descriptor_bind();
+        }
+
+        Ok(())
+    }
+
+    unsafe fn destroy(&mut self, device: &crate::Device) {
+        SimpleRenderSystem::destroy(self, device);
+    }
+}
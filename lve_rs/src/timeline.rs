@@ -0,0 +1,109 @@
+use anyhow::Result;
+use ash::vk;
+use std::cell::Cell;
+
+/* MEMO
+ *  A VK_SEMAPHORE_TYPE_TIMELINE semaphore whose counter only ever grows. Every
+ *  submission that signals it uses next_value and records it with submitted once
+ *  queue_submit went through, so "work N finished" is simply "the counter reached
+ *  N", which both the CPU (wait) and other submissions (Device::submit_graphics)
+ *  can wait for. A failed submit leaves no value behind that is never signaled.
+ *
+ *  let value = device.graphics_timeline().next_value();
+ *  ... submit, signaling `value` ...
+ *  device.graphics_timeline().submitted(value);
+ *  device.graphics_timeline().wait(&device, value, u64::MAX)?;
+ */
+pub struct TimelineSemaphore {
+    semaphore: vk::Semaphore,
+    // Last value recorded by submitted or signal
+    value: Cell<u64>,
+}
+
+impl TimelineSemaphore {
+    pub fn new(device: &crate::Device) -> Result<Self> {
+        Self::with_ash_device(device.device())
+    }
+
+    // Device::new creates its own timelines before a crate::Device exists
+    pub(crate) fn with_ash_device(device: &ash::Device) -> Result<Self> {
+        let mut type_create_info = vk::SemaphoreTypeCreateInfo::builder()
+            .semaphore_type(vk::SemaphoreType::TIMELINE)
+            .initial_value(0);
+        let create_info = vk::SemaphoreCreateInfo::builder().push_next(&mut type_create_info);
+        let semaphore = unsafe { device.create_semaphore(&create_info, None) }?;
+
+        Ok(Self {
+            semaphore,
+            value: Cell::new(0),
+        })
+    }
+
+    pub unsafe fn destroy(&self, device: &crate::Device) {
+        device.device().destroy_semaphore(self.semaphore, None);
+    }
+
+    #[inline]
+    pub fn semaphore(&self) -> vk::Semaphore {
+        self.semaphore
+    }
+
+    // Value the next signaling submission has to use; doesn't reserve it
+    #[inline]
+    pub fn next_value(&self) -> u64 {
+        self.value.get() + 1
+    }
+
+    // Call once the submission signaling `value` has been queued
+    #[inline]
+    pub fn submitted(&self, value: u64) {
+        assert_eq!(value, self.next_value(), "Timeline values out of order");
+        self.value.set(value);
+    }
+
+    // Most recently submitted value, i.e. the one all submitted work will have reached
+    #[inline]
+    pub fn last_value(&self) -> u64 {
+        self.value.get()
+    }
+
+    pub fn completed_value(&self, device: &crate::Device) -> Result<u64> {
+        unsafe { device.get_semaphore_counter_value(self.semaphore) }
+    }
+
+    #[inline]
+    pub fn is_complete(&self, device: &crate::Device, value: u64) -> Result<bool> {
+        Ok(self.completed_value(device)? >= value)
+    }
+
+    // Returns false if `timeout` nanoseconds passed first
+    pub fn wait(&self, device: &crate::Device, value: u64, timeout: u64) -> Result<bool> {
+        if value == 0 {
+            return Ok(true);
+        }
+
+        let wait_info = vk::SemaphoreWaitInfo::builder()
+            .semaphores(std::slice::from_ref(&self.semaphore))
+            .values(std::slice::from_ref(&value));
+
+        match unsafe { device.wait_semaphores(&wait_info, timeout) } {
+            Ok(()) => Ok(true),
+            Err(err) if err == vk::Result::TIMEOUT => Ok(false),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    // Signals from the host, e.g. to release work waiting on a CPU-side upload
+    pub fn signal(&self, device: &crate::Device, value: u64) -> Result<()> {
+        let signal_info = vk::SemaphoreSignalInfo::builder()
+            .semaphore(self.semaphore)
+            .value(value);
+
+        unsafe { device.signal_semaphore(&signal_info) }?;
+        if value > self.value.get() {
+            self.value.set(value);
+        }
+
+        Ok(())
+    }
+}
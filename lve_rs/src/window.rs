@@ -1,35 +1,134 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
 use ash::vk;
 use winit::{
     dpi::LogicalSize,
     event_loop::EventLoop,
-    window::{self, WindowBuilder},
+    window::{self, Fullscreen, WindowBuilder},
 };
 
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FullscreenMode {
+    #[default]
+    Windowed,
+    // Covers the current monitor without changing its video mode
+    Borderless,
+    // Switches the current monitor to its largest, fastest video mode
+    Exclusive,
+}
+
 pub struct Window {
-    window: window::Window,
+    // None for headless windows, which present to a VK_EXT_headless_surface
+    window: Option<window::Window>,
     window_name: Box<str>,
+    // Physical pixels, i.e. the size of the swap chain images
     width: i32,
     height: i32,
     framebuffer_resized: bool,
+    fullscreen_mode: FullscreenMode,
 }
 
 impl Window {
+    // `width` and `height` are logical, so the window looks the same on HiDPI screens
     pub fn new<T>(event_loop: &EventLoop<T>, width: i32, height: i32, name: &str) -> Result<Self> {
         let window = Self::init_window(event_loop, width, height, name)?;
+        let size = window.inner_size();
 
         Ok(Self {
-            window,
+            window: Some(window),
+            window_name: Box::from(name),
+            width: size.width.try_into()?,
+            height: size.height.try_into()?,
+            framebuffer_resized: false,
+            fullscreen_mode: FullscreenMode::Windowed,
+        })
+    }
+
+    // Needs no display server, nor an event loop to pump
+    pub fn headless(width: i32, height: i32, name: &str) -> Self {
+        Self {
+            window: None,
             window_name: Box::from(name),
             width,
             height,
             framebuffer_resized: false,
-        })
+            fullscreen_mode: FullscreenMode::Windowed,
+        }
+    }
+
+    #[inline]
+    pub fn window(&self) -> Option<&window::Window> {
+        self.window.as_ref()
+    }
+
+    #[inline]
+    pub fn is_headless(&self) -> bool {
+        self.window.is_none()
     }
 
     #[inline]
-    pub fn window(&self) -> &window::Window {
-        &self.window
+    pub fn scale_factor(&self) -> f64 {
+        self.window
+            .as_ref()
+            .map_or(1.0, |window| window.scale_factor())
+    }
+
+    // Nothing can be presented until the window is restored
+    pub fn is_minimized(&self) -> bool {
+        self.width == 0
+            || self.height == 0
+            || self
+                .window
+                .as_ref()
+                .and_then(|window| window.is_minimized())
+                .unwrap_or(false)
+    }
+
+    #[inline]
+    pub fn fullscreen_mode(&self) -> FullscreenMode {
+        self.fullscreen_mode
+    }
+
+    // The swap chain is recreated on the Resized event that follows
+    pub fn set_fullscreen_mode(&mut self, fullscreen_mode: FullscreenMode) -> Result<()> {
+        let window = match self.window.as_ref() {
+            Some(window) => window,
+            None => return Ok(()),
+        };
+        let fullscreen = match fullscreen_mode {
+            FullscreenMode::Windowed => None,
+            FullscreenMode::Borderless => Some(Fullscreen::Borderless(window.current_monitor())),
+            FullscreenMode::Exclusive => {
+                let video_mode = window
+                    .current_monitor()
+                    .context("No monitor to go fullscreen on")?
+                    .video_modes()
+                    .max_by_key(|video_mode| {
+                        let size = video_mode.size();
+
+                        (
+                            size.width * size.height,
+                            video_mode.refresh_rate_millihertz(),
+                        )
+                    })
+                    .context("Monitor reports no video modes")?;
+
+                Some(Fullscreen::Exclusive(video_mode))
+            }
+        };
+
+        window.set_fullscreen(fullscreen);
+        self.fullscreen_mode = fullscreen_mode;
+
+        Ok(())
+    }
+
+    // Switches to `fullscreen_mode`, or back to windowed if already in it
+    pub fn toggle_fullscreen(&mut self, fullscreen_mode: FullscreenMode) -> Result<()> {
+        if self.fullscreen_mode == fullscreen_mode {
+            self.set_fullscreen_mode(FullscreenMode::Windowed)
+        } else {
+            self.set_fullscreen_mode(fullscreen_mode)
+        }
     }
 
     #[inline]
@@ -58,6 +157,7 @@ impl Window {
         crate::Surface::new(self, entry, instance)
     }
 
+    // Physical size, as reported by WindowEvent::Resized and ScaleFactorChanged
     pub fn framebuffer_resized(&mut self, width: i32, height: i32) {
         self.framebuffer_resized = true;
         self.width = width;
@@ -72,7 +172,7 @@ impl Window {
         name: &str,
     ) -> Result<window::Window> {
         let window = WindowBuilder::new()
-            .with_resizable(false)
+            .with_resizable(true)
             .with_inner_size(LogicalSize::new(width, height))
             .with_title(name)
             .build(event_loop)?;
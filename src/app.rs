@@ -1,10 +1,16 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
 use ash::vk;
-use std::{cell::RefCell, mem::size_of, rc::Rc};
+use std::{
+    cell::RefCell,
+    mem::size_of,
+    path::{Path, PathBuf},
+    rc::Rc,
+    sync::Arc,
+    time,
+};
 use winit::{
     event::VirtualKeyCode,
     event_loop::{ControlFlow, EventLoop},
-    window::Window,
 };
 
 extern crate nalgebra_glm as glm;
@@ -13,8 +19,6 @@ pub struct App {
     window: lve_rs::Window,
     device: lve_rs::Device,
     renderer: lve_rs::Renderer,
-    simple_render_system: lve_rs::SimpleRenderSystem,
-    point_light_system: lve_rs::PointLightSystem,
     camera: lve_rs::Camera,
     camera_controller: lve_rs::controller::keyboard::KeyboardMovementController,
     viewer_object: lve_rs::GameObject,
@@ -23,40 +27,71 @@ pub struct App {
     global_descriptor_sets: Vec<vk::DescriptorSet>,
     global_set_layout: Box<lve_rs::DescriptorSetLayout>,
     ubo_buffers: Vec<Box<lve_rs::Buffer>>,
+    profiler: Option<lve_rs::GpuProfiler>,
+    frame_stats: lve_rs::FrameStatsCollector,
+    fixed_timestep: lve_rs::FixedTimestep,
+    // Of the most recent update
+    delta_time: f32,
+    // Taken by the next frame that is actually rendered
+    pending_screenshot: Option<PathBuf>,
+    screenshot_key_down: bool,
+    fullscreen_key_down: bool,
+    msaa_key_down: bool,
 }
 
 impl App {
     pub const WIDTH: i32 = 1280;
     pub const HEIGHT: i32 = 800;
+    pub const SCREENSHOT_KEY: VirtualKeyCode = VirtualKeyCode::F12;
+    // Borderless, or exclusive while shift is held
+    pub const FULLSCREEN_KEY: VirtualKeyCode = VirtualKeyCode::F11;
+    // Switches between 4x MSAA and none
+    pub const MSAA_KEY: VirtualKeyCode = VirtualKeyCode::F10;
+    const NAME: &'static str = "Hello Vulkan!";
 
     pub fn new<T>(
         event_loop: &EventLoop<T>,
         width: Option<i32>,
         height: Option<i32>,
     ) -> Result<Self> {
-        let width = if let Some(width) = width {
-            width
-        } else {
-            Self::WIDTH
-        };
-        let height = if let Some(height) = height {
-            height
-        } else {
-            Self::HEIGHT
-        };
-        let window = lve_rs::Window::new(event_loop, width, height, "Hello Vulkan!")?;
+        let window = lve_rs::Window::new(
+            event_loop,
+            width.unwrap_or(Self::WIDTH),
+            height.unwrap_or(Self::HEIGHT),
+            Self::NAME,
+        )?;
+
+        Self::with_window(window)
+    }
+
+    // Renders to a VK_EXT_headless_surface, so frames can only be seen by capturing them
+    pub fn headless(width: Option<i32>, height: Option<i32>) -> Result<Self> {
+        Self::with_window(lve_rs::Window::headless(
+            width.unwrap_or(Self::WIDTH),
+            height.unwrap_or(Self::HEIGHT),
+            Self::NAME,
+        ))
+    }
+
+    fn with_window(window: lve_rs::Window) -> Result<Self> {
         let device = lve_rs::Device::new(&window, &lve_rs::ApplicationInfo::default())?;
-        let renderer = lve_rs::Renderer::new(&window, &device)?;
+        let mut renderer = lve_rs::Renderer::with_config(
+            &window,
+            &device,
+            &lve_rs::SwapChainConfig::new().msaa_samples(vk::SampleCountFlags::TYPE_4),
+        )?;
         let global_pool = lve_rs::DescriptorPool::builder()
-            .set_max_sets(lve_rs::SwapChain::MAX_FRAMES_IN_FLIGHT as u32)
+            .set_max_sets(renderer.frames_in_flight() as u32)
             .add_pool_size(
                 vk::DescriptorType::UNIFORM_BUFFER,
-                lve_rs::SwapChain::MAX_FRAMES_IN_FLIGHT as u32,
+                renderer.frames_in_flight() as u32,
             )
             .build(&device)?;
+        // One set of asset roots for the models and every pipeline
+        let assets = Arc::new(lve_rs::AssetLoader::with_default_roots());
         let mut game_objects = lve_rs::Map::new();
 
-        Self::load_game_object(&mut game_objects, &device)?;
+        Self::load_game_object(&mut game_objects, &device, &assets)?;
 
         let mut camera = lve_rs::Camera::new();
         let camera_controller =
@@ -70,20 +105,30 @@ impl App {
                 None,
             )
             .build(&device)?;
-        let simple_render_system = lve_rs::SimpleRenderSystem::new(
+        let mut frame_stats = lve_rs::FrameStatsCollector::new(&device, &renderer)?;
+        // Leaves a core for the main thread
+        renderer.set_worker_threads(
             &device,
-            renderer.swap_chain_render_pass(),
-            &global_set_layout.descriptor_set_layout(),
+            std::thread::available_parallelism().map_or(0, |threads| threads.get() - 1),
+            Some(&mut frame_stats),
         )?;
-        let point_light_system = lve_rs::PointLightSystem::new(
+        // Drawn in the order they are added
+        renderer.add_render_system(lve_rs::SimpleRenderSystem::new(
             &device,
-            renderer.swap_chain_render_pass(),
+            &renderer.swap_chain_render_pass_info(),
             &global_set_layout.descriptor_set_layout(),
-        )?;
-        let mut ubo_buffers = Vec::with_capacity(lve_rs::SwapChain::MAX_FRAMES_IN_FLIGHT as usize);
+            &assets,
+        )?);
+        renderer.add_render_system(lve_rs::PointLightSystem::new(
+            &device,
+            &renderer.swap_chain_render_pass_info(),
+            &global_set_layout.descriptor_set_layout(),
+            &assets,
+        )?);
+        let mut ubo_buffers = Vec::with_capacity(renderer.frames_in_flight());
         let mut global_descriptor_sets = vec![];
 
-        for i in 0..lve_rs::SwapChain::MAX_FRAMES_IN_FLIGHT as usize {
+        for i in 0..renderer.frames_in_flight() {
             ubo_buffers.push(Box::new(lve_rs::Buffer::new(
                 &device,
                 size_of::<lve_rs::GlobalUbo>() as u64,
@@ -104,6 +149,14 @@ impl App {
             );
         }
 
+        let profiler = match lve_rs::GpuProfiler::new(&device, renderer.frames_in_flight()) {
+            Ok(profiler) => Some(profiler),
+            Err(err) => {
+                eprintln!("GPU profiling not available: {}", err);
+                None
+            }
+        };
+
         viewer_object.transform.translation.z = -2.5;
         camera.set_view_target(&[-1.0, -2.0, 2.0], &[0.0, 0.0, 2.5], None);
 
@@ -111,8 +164,6 @@ impl App {
             window,
             device,
             renderer,
-            simple_render_system,
-            point_light_system,
             camera,
             camera_controller,
             viewer_object,
@@ -121,34 +172,39 @@ impl App {
             global_descriptor_sets,
             global_set_layout,
             ubo_buffers,
+            profiler,
+            frame_stats,
+            fixed_timestep: lve_rs::FixedTimestep::default(),
+            delta_time: 0.0,
+            pending_screenshot: None,
+            screenshot_key_down: false,
+            fullscreen_key_down: false,
+            msaa_key_down: false,
         })
     }
 
-    pub fn draw_frame(
+    /* MEMO
+     *  Same as an update followed by a render, but the frame is always written to
+     *  `path` and nothing depends on the event loop. Returns false if no frame was rendered (e.g. the
+     *  swap chain had to be recreated first), in which case the caller should
+     *  retry with the same input rather than advance its clock.
+     */
+    pub fn record_frame<P: Into<PathBuf>>(
         &mut self,
-        mut control_flow: Option<&mut ControlFlow>,
         delta_time: f32,
         keys: &[Option<VirtualKeyCode>],
-    ) -> Result<()> {
-        let aspect = self.renderer.aspect_ratio();
-
-        self.camera_controller
-            .move_in_plane_xz(delta_time, &mut self.viewer_object, keys);
-        self.camera.set_view_xyz(
-            &[
-                self.viewer_object.transform.translation.x,
-                self.viewer_object.transform.translation.y,
-                self.viewer_object.transform.translation.z,
-            ],
-            &[
-                self.viewer_object.transform.rotation.x,
-                self.viewer_object.transform.rotation.y,
-                self.viewer_object.transform.rotation.z,
-            ],
-        );
-        self.camera
-            .set_perspective_projection(f32::to_radians(50.0), aspect, 0.1, 100.0);
+        path: P,
+    ) -> Result<bool> {
+        self.render_frame(None, Some((delta_time, keys)), Some(path.into()))
+    }
 
+    // With `simulation`, the fixed update steps only run if the frame is rendered
+    fn render_frame(
+        &mut self,
+        mut control_flow: Option<&mut ControlFlow>,
+        simulation: Option<(f32, &[Option<VirtualKeyCode>])>,
+        capture_path: Option<PathBuf>,
+    ) -> Result<bool> {
         let command_buffer = self.renderer.begin_frame(
             &self.window,
             &self.device,
@@ -160,14 +216,52 @@ impl App {
         )?;
 
         if command_buffer != vk::CommandBuffer::null() {
+            if let Some((delta_time, keys)) = simulation {
+                self.simulate(delta_time, keys);
+            }
+
+            let alpha = self.fixed_timestep.alpha();
+            let viewer_transform = self.viewer_object.interpolated_transform(alpha);
+
+            self.camera.set_view_xyz(
+                &[
+                    viewer_transform.translation.x,
+                    viewer_transform.translation.y,
+                    viewer_transform.translation.z,
+                ],
+                &[
+                    viewer_transform.rotation.x,
+                    viewer_transform.rotation.y,
+                    viewer_transform.rotation.z,
+                ],
+            );
+            self.camera.set_perspective_projection(
+                f32::to_radians(50.0),
+                self.renderer.aspect_ratio(),
+                0.1,
+                100.0,
+            );
+
             let frame_index = self.renderer.frame_index();
+
+            if let Some(profiler) = self.profiler.as_mut() {
+                unsafe { profiler.begin_frame(&self.device, &command_buffer, frame_index) };
+            }
+            unsafe {
+                self.frame_stats
+                    .begin_frame(&self.device, &command_buffer, frame_index)
+            };
+
             let mut frame_info = lve_rs::FrameInfo {
                 frame_index,
-                frame_time: delta_time,
+                frame_time: self.delta_time,
+                alpha,
                 command_buffer,
                 camera: &self.camera,
                 global_descriptor_set: self.global_descriptor_sets[frame_index],
                 game_objects: &mut self.game_objects,
+                draw_counters: self.frame_stats.counters(),
+                last_frame_stats: self.frame_stats.last_frame().copied(),
             };
             // update
             let mut ubo = lve_rs::GlobalUbo {
@@ -176,7 +270,9 @@ impl App {
                 inverse_view: *self.camera.inverse_view(),
                 ..Default::default()
             };
-            self.point_light_system.update(&mut frame_info, &mut ubo);
+            self.renderer
+                .render_systems_mut()
+                .update(&mut frame_info, &mut ubo);
             unsafe {
                 self.ubo_buffers[frame_index].write_to_buffer(
                     &self.device,
@@ -190,11 +286,17 @@ impl App {
             unsafe {
                 self.renderer
                     .begin_swap_chain_render_pass(&self.device, &command_buffer);
-                self.simple_render_system
-                    .render_game_objects(&self.device, &mut frame_info);
-                self.point_light_system.render(&self.device, &frame_info);
+                self.renderer.record_render_systems(
+                    &self.device,
+                    &mut frame_info,
+                    self.profiler.as_mut(),
+                )?;
                 self.renderer
                     .end_swap_chain_render_pass(&self.device, &command_buffer);
+                if let Some(capture_path) = capture_path {
+                    self.renderer.capture_frame(&self.device, capture_path)?;
+                }
+                self.frame_stats.end_frame(&self.device, &command_buffer);
                 self.renderer.end_frame(
                     &mut self.window,
                     &self.device,
@@ -205,19 +307,46 @@ impl App {
                     },
                 )?;
             }
+
+            return Ok(true);
         }
 
-        Ok(())
+        Ok(false)
     }
 
-    #[inline]
-    pub fn window(&self) -> &Window {
-        &self.window.window()
+    fn simulate(&mut self, delta_time: f32, keys: &[Option<VirtualKeyCode>]) {
+        self.delta_time = delta_time;
+        for _ in 0..self.fixed_timestep.advance(delta_time) {
+            self.fixed_update(keys);
+        }
     }
 
-    #[inline]
-    pub fn window_resized(&mut self, width: i32, height: i32) {
-        self.window.framebuffer_resized(width, height);
+    // Runs once per FixedTimestep step, independent of the frame rate
+    fn fixed_update(&mut self, keys: &[Option<VirtualKeyCode>]) {
+        let step = self.fixed_timestep.step();
+
+        self.viewer_object.store_previous_transform();
+        for game_object in self.game_objects.values_mut() {
+            game_object.store_previous_transform();
+        }
+        self.camera_controller
+            .move_in_plane_xz(step, &mut self.viewer_object, keys);
+        lve_rs::PointLightSystem::fixed_update(&mut self.game_objects, step);
+    }
+
+    // Waits for the frames in flight so the file covers every rendered frame
+    pub fn write_profile<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
+        let profiler = self
+            .profiler
+            .as_mut()
+            .context("GPU profiling is not available")?;
+
+        unsafe {
+            self.device.device().device_wait_idle()?;
+            profiler.resolve_pending(&self.device);
+        }
+
+        profiler.write(path)
     }
 
     #[inline]
@@ -225,19 +354,34 @@ impl App {
         Ok(self.device.device().device_wait_idle()?)
     }
 
-    fn load_game_object(game_objects: &mut lve_rs::Map, device: &lve_rs::Device) -> Result<()> {
+    fn screenshot_path() -> PathBuf {
+        let timestamp = time::SystemTime::now()
+            .duration_since(time::UNIX_EPOCH)
+            .map(|duration| duration.as_millis())
+            .unwrap_or_default();
+
+        PathBuf::from(format!("screenshot-{}.png", timestamp))
+    }
+
+    fn load_game_object(
+        game_objects: &mut lve_rs::Map,
+        device: &lve_rs::Device,
+        assets: &lve_rs::AssetLoader,
+    ) -> Result<()> {
         let mut smooth_vase = {
-            let model = lve_rs::Model::create_model_from_file(device, "models/smooth_vase.obj")?;
+            let model =
+                lve_rs::Model::create_model_from_asset(device, assets, "models/smooth_vase.obj")?;
 
             unsafe { lve_rs::GameObject::create_game_object(Some(Rc::new(RefCell::new(*model)))) }
         };
         let mut flat_vase = {
-            let model = lve_rs::Model::create_model_from_file(device, "models/flat_vase.obj")?;
+            let model =
+                lve_rs::Model::create_model_from_asset(device, assets, "models/flat_vase.obj")?;
 
             unsafe { lve_rs::GameObject::create_game_object(Some(Rc::new(RefCell::new(*model)))) }
         };
         let mut floor = {
-            let model = lve_rs::Model::create_model_from_file(device, "models/quad.obj")?;
+            let model = lve_rs::Model::create_model_from_asset(device, assets, "models/quad.obj")?;
 
             unsafe { lve_rs::GameObject::create_game_object(Some(Rc::new(RefCell::new(*model)))) }
         };
@@ -279,6 +423,86 @@ impl App {
     }
 }
 
+impl lve_rs::Application for App {
+    fn init(event_loop: &EventLoop<()>) -> Result<Self> {
+        Self::new(event_loop, None, None)
+    }
+
+    #[inline]
+    fn window(&self) -> &lve_rs::Window {
+        &self.window
+    }
+
+    #[inline]
+    fn window_mut(&mut self) -> &mut lve_rs::Window {
+        &mut self.window
+    }
+
+    fn update(&mut self, keys: &[Option<VirtualKeyCode>], delta_time: f32) -> Result<()> {
+        // Only on the press itself, not for every frame the key is held
+        let screenshot_key_down = keys.contains(&Some(Self::SCREENSHOT_KEY));
+
+        if screenshot_key_down && !self.screenshot_key_down {
+            self.pending_screenshot = Some(Self::screenshot_path());
+        }
+        self.screenshot_key_down = screenshot_key_down;
+
+        let fullscreen_key_down = keys.contains(&Some(Self::FULLSCREEN_KEY));
+
+        if fullscreen_key_down && !self.fullscreen_key_down {
+            let shift_down = keys.contains(&Some(VirtualKeyCode::LShift))
+                || keys.contains(&Some(VirtualKeyCode::RShift));
+
+            self.window.toggle_fullscreen(if shift_down {
+                lve_rs::FullscreenMode::Exclusive
+            } else {
+                lve_rs::FullscreenMode::Borderless
+            })?;
+        }
+        self.fullscreen_key_down = fullscreen_key_down;
+
+        let msaa_key_down = keys.contains(&Some(Self::MSAA_KEY));
+
+        if msaa_key_down && !self.msaa_key_down {
+            let msaa_samples = if self.renderer.msaa_samples() == vk::SampleCountFlags::TYPE_1 {
+                vk::SampleCountFlags::TYPE_4
+            } else {
+                vk::SampleCountFlags::TYPE_1
+            };
+            let config = self.renderer.swap_chain_config().msaa_samples(msaa_samples);
+
+            self.renderer
+                .set_swap_chain_config(&self.window, &self.device, &config)?;
+        }
+        self.msaa_key_down = msaa_key_down;
+
+        self.simulate(delta_time, keys);
+
+        Ok(())
+    }
+
+    fn render(&mut self, control_flow: &mut ControlFlow) -> Result<()> {
+        if self.render_frame(Some(control_flow), None, self.pending_screenshot.clone())? {
+            self.pending_screenshot = None;
+        }
+
+        Ok(())
+    }
+
+    fn shutdown(&mut self, frame_timing: &lve_rs::FrameTimingStats) -> Result<()> {
+        println!(
+            "{:.1} fps, frame time avg {:.2} ms, p50 {:.2} ms, p95 {:.2} ms, p99 {:.2} ms",
+            frame_timing.fps,
+            frame_timing.average_ms,
+            frame_timing.p50_ms,
+            frame_timing.p95_ms,
+            frame_timing.p99_ms
+        );
+
+        unsafe { self.device_wait_idle() }.context("Device failed to wait idle")
+    }
+}
+
 impl Drop for App {
     fn drop(&mut self) {
         unsafe {
@@ -294,8 +518,10 @@ impl Drop for App {
             }
             self.game_objects.clear();
             self.global_pool.destroy(&self.device);
-            self.point_light_system.destroy(&self.device);
-            self.simple_render_system.destroy(&self.device);
+            if let Some(profiler) = self.profiler.as_mut() {
+                profiler.destroy(&self.device);
+            }
+            self.frame_stats.destroy(&self.device);
             self.renderer.destroy(&self.device);
             self.device.destroy();
         }
@@ -1,103 +1,35 @@
 mod app;
+mod recording;
 
-use anyhow::{bail, Result};
+use anyhow::{Context, Result};
 use app::App;
-use std::{borrow::BorrowMut, time};
-use winit::{
-    event::{
-        DeviceEvent, ElementState, Event, KeyboardInput, StartCause, VirtualKeyCode, WindowEvent,
-    },
-    event_loop::{ControlFlow, EventLoop},
-    platform::run_return::EventLoopExtRunReturn,
-};
+use recording::Recording;
+use std::env;
 
 fn main() -> Result<()> {
-    let mut event_loop = EventLoop::new();
-    let mut app = App::new(&event_loop, None, None)?;
-    let mut current_time = time::Instant::now();
-    let mut keys_pressed: [Option<VirtualKeyCode>; 10] =
-        [None, None, None, None, None, None, None, None, None, None];
+    let (max_fps, args) = take_max_fps(env::args().skip(1))?;
 
-    let result = event_loop
-        .borrow_mut()
-        .run_return(move |event, _, control_flow| {
-            control_flow.set_poll();
-
-            let new_time = time::Instant::now();
-            let frame_time = (new_time - current_time).as_secs_f32();
-
-            current_time = new_time;
+    if let Some(recording) = Recording::from_args(args.into_iter())? {
+        return recording.run();
+    }
 
-            match event {
-                Event::WindowEvent {
-                    ref event,
-                    window_id,
-                } if window_id == app.window().id() => match event {
-                    WindowEvent::CloseRequested => {
-                        *control_flow = ControlFlow::Exit;
-                    }
-                    WindowEvent::Resized(physical_size) => {
-                        println!(
-                            "window resized: ({}, {})",
-                            physical_size.width, physical_size.height
-                        );
-                        app.window_resized(physical_size.width as i32, physical_size.height as i32);
-                    }
-                    _ => (),
-                },
-                Event::DeviceEvent { event, .. } => match event {
-                    DeviceEvent::Key(KeyboardInput {
-                        state,
-                        virtual_keycode,
-                        ..
-                    }) => match state {
-                        ElementState::Pressed => {
-                            if !keys_pressed.contains(&virtual_keycode) {
-                                if let Some(key_unassigned) =
-                                    keys_pressed.iter_mut().filter(|key| key.is_none()).next()
-                                {
-                                    *key_unassigned = virtual_keycode;
-                                }
-                            }
-                        }
-                        ElementState::Released => {
-                            if let Some(key_assigned) = keys_pressed
-                                .iter_mut()
-                                .filter(|key| **key == virtual_keycode)
-                                .next()
-                            {
-                                *key_assigned = None
-                            }
-                        }
-                    },
-                    _ => (),
-                },
-                Event::RedrawRequested(_) => {}
-                Event::NewEvents(StartCause::ResumeTimeReached { .. }) => {}
-                Event::MainEventsCleared => app
-                    .draw_frame(Some(control_flow), frame_time, &keys_pressed)
-                    .unwrap_or_else(|e| {
-                        eprintln!("{:?}", e);
-                        *control_flow = ControlFlow::ExitWithCode(0x10);
-                    }),
-                _ => (),
-            }
+    lve_rs::run::<App>(&lve_rs::RunConfig::new().target_fps(max_fps))
+}
 
-            match *control_flow {
-                ControlFlow::Exit | ControlFlow::ExitWithCode(_) => {
-                    unsafe { app.device_wait_idle() }.unwrap()
-                }
-                _ => {}
-            }
+// Strips `--max-fps <fps>` off the arguments; the rest are left for Recording
+fn take_max_fps<I: Iterator<Item = String>>(mut args: I) -> Result<(Option<f64>, Vec<String>)> {
+    let mut max_fps = None;
+    let mut rest = vec![];
 
-            unsafe { app.device_wait_idle() }.unwrap_or_else(|e| {
-                eprintln!("Device failed to wait idle!: {:?}", e);
-                *control_flow = ControlFlow::ExitWithCode(0x20);
-            });
-        });
+    while let Some(arg) = args.next() {
+        if arg == "--max-fps" {
+            let value = args.next().context("Missing value for --max-fps")?;
 
-    match result {
-        0 => Ok(()),
-        _ => bail!("Exit with status: {:0x}", result),
+            max_fps = Some(value.parse().context("Invalid frame rate")?);
+        } else {
+            rest.push(arg);
+        }
     }
+
+    Ok((max_fps, rest))
 }
@@ -0,0 +1,251 @@
+use crate::app::App;
+use anyhow::{bail, Context, Result};
+use std::{fs, path::PathBuf};
+use winit::{event::VirtualKeyCode, event_loop::EventLoop};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RecordingFormat {
+    Png,
+    Raw,
+}
+
+// Keys held from `first_frame` through `last_frame`, both inclusive
+#[derive(Clone, Debug)]
+pub struct InputSpan {
+    pub first_frame: u32,
+    pub last_frame: u32,
+    pub keys: Vec<VirtualKeyCode>,
+}
+
+/* MEMO
+ *  Renders `frames` frames, each advanced by exactly `frame_time` seconds and
+ *  driven by the scripted `input` instead of the event loop, so two runs with
+ *  the same arguments produce the same images.
+ *
+ *  vulkan-tutorial --record <frames> [--frame-time <seconds>] [--output <dir>]
+ *                  [--format png|raw] [--size <width>x<height>] [--headless]
+ *                  [--input <first>[-<last>]:<key>[+<key>...],...]
+ *                  [--profile <file.csv|file.json>]
+ */
+#[derive(Clone, Debug)]
+pub struct Recording {
+    pub frames: u32,
+    pub frame_time: f32,
+    pub output_dir: PathBuf,
+    pub format: RecordingFormat,
+    pub width: Option<i32>,
+    pub height: Option<i32>,
+    pub headless: bool,
+    pub input: Vec<InputSpan>,
+    // GPU scope timings, written once all frames are recorded
+    pub profile: Option<PathBuf>,
+}
+
+impl Recording {
+    pub const DEFAULT_FRAME_TIME: f32 = 1.0 / 60.0;
+    const MAX_KEYS: usize = 10;
+    // Frames that are skipped for swap chain recreation before giving up
+    const MAX_SKIPPED_FRAMES: u32 = 8;
+
+    // None unless `--record` was given
+    pub fn from_args<I: Iterator<Item = String>>(mut args: I) -> Result<Option<Self>> {
+        let mut recording = Self {
+            frames: 0,
+            frame_time: Self::DEFAULT_FRAME_TIME,
+            output_dir: PathBuf::from("recording"),
+            format: RecordingFormat::Png,
+            width: None,
+            height: None,
+            headless: false,
+            input: vec![],
+            profile: None,
+        };
+        let mut record = false;
+
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .with_context(|| format!("Missing value for {}", arg))
+            };
+
+            match arg.as_str() {
+                "--record" => {
+                    record = true;
+                    recording.frames = value()?.parse().context("Invalid frame count")?;
+                }
+                "--frame-time" => {
+                    recording.frame_time = value()?.parse().context("Invalid frame time")?;
+                    if recording.frame_time.is_nan() || recording.frame_time <= 0.0 {
+                        bail!("Frame time must be positive");
+                    }
+                }
+                "--output" => recording.output_dir = PathBuf::from(value()?),
+                "--format" => {
+                    recording.format = match value()?.as_str() {
+                        "png" => RecordingFormat::Png,
+                        "raw" => RecordingFormat::Raw,
+                        format => bail!("Unknown recording format {:?}", format),
+                    }
+                }
+                "--size" => {
+                    let size = value()?;
+                    let (width, height) = size
+                        .split_once('x')
+                        .with_context(|| format!("Invalid size {:?}", size))?;
+
+                    recording.width = Some(width.parse().context("Invalid width")?);
+                    recording.height = Some(height.parse().context("Invalid height")?);
+                }
+                "--headless" => recording.headless = true,
+                "--input" => recording.input = Self::parse_input(&value()?)?,
+                "--profile" => recording.profile = Some(PathBuf::from(value()?)),
+                _ => bail!("Unknown argument {:?}", arg),
+            }
+        }
+
+        Ok(record.then_some(recording))
+    }
+
+    pub fn run(&self) -> Result<()> {
+        // Kept alive for as long as the window created from it
+        let event_loop;
+        let mut app = if self.headless {
+            App::headless(self.width, self.height)?
+        } else {
+            event_loop = EventLoop::new();
+            App::new(&event_loop, self.width, self.height)?
+        };
+        let mut frame = 0;
+        let mut skipped_frames = 0;
+
+        fs::create_dir_all(&self.output_dir)
+            .with_context(|| format!("Failed to create {:?}", self.output_dir))?;
+
+        while frame < self.frames {
+            if app.record_frame(
+                self.frame_time,
+                &self.keys_at(frame),
+                self.frame_path(frame),
+            )? {
+                frame += 1;
+                skipped_frames = 0;
+            } else {
+                skipped_frames += 1;
+                if skipped_frames > Self::MAX_SKIPPED_FRAMES {
+                    bail!("Frame {} could not be rendered", frame);
+                }
+            }
+        }
+
+        if let Some(profile) = self.profile.as_ref() {
+            app.write_profile(profile)?;
+        }
+
+        unsafe { app.device_wait_idle() }
+    }
+
+    pub fn keys_at(&self, frame: u32) -> [Option<VirtualKeyCode>; Self::MAX_KEYS] {
+        let mut keys = [None; Self::MAX_KEYS];
+        let held = self
+            .input
+            .iter()
+            .filter(|span| (span.first_frame..=span.last_frame).contains(&frame))
+            .flat_map(|span| span.keys.iter().copied());
+
+        for (slot, key) in keys.iter_mut().zip(held) {
+            *slot = Some(key);
+        }
+
+        keys
+    }
+
+    pub fn frame_path(&self, frame: u32) -> PathBuf {
+        let extension = match self.format {
+            RecordingFormat::Png => "png",
+            RecordingFormat::Raw => "raw",
+        };
+
+        self.output_dir
+            .join(format!("frame_{:05}.{}", frame, extension))
+    }
+
+    /* --- Helper functions --- */
+    fn parse_input(script: &str) -> Result<Vec<InputSpan>> {
+        script
+            .split(',')
+            .filter(|span| !span.trim().is_empty())
+            .map(|span| {
+                let (frames, keys) = span
+                    .split_once(':')
+                    .with_context(|| format!("Input span {:?} has no keys", span))?;
+                let (first_frame, last_frame) = match frames.split_once('-') {
+                    Some((first, last)) => (first.trim().parse()?, last.trim().parse()?),
+                    None => {
+                        let frame = frames.trim().parse()?;
+
+                        (frame, frame)
+                    }
+                };
+                let keys = keys
+                    .split('+')
+                    .map(|key| Self::parse_key(key.trim()))
+                    .collect::<Result<Vec<_>>>()?;
+
+                if first_frame > last_frame {
+                    bail!("Input span {:?} ends before it starts", span);
+                }
+
+                Ok(InputSpan {
+                    first_frame,
+                    last_frame,
+                    keys,
+                })
+            })
+            .collect()
+    }
+
+    fn parse_key(name: &str) -> Result<VirtualKeyCode> {
+        use VirtualKeyCode::*;
+
+        Ok(match name.to_ascii_lowercase().as_str() {
+            "a" => A,
+            "b" => B,
+            "c" => C,
+            "d" => D,
+            "e" => E,
+            "f" => F,
+            "g" => G,
+            "h" => H,
+            "i" => I,
+            "j" => J,
+            "k" => K,
+            "l" => L,
+            "m" => M,
+            "n" => N,
+            "o" => O,
+            "p" => P,
+            "q" => Q,
+            "r" => R,
+            "s" => S,
+            "t" => T,
+            "u" => U,
+            "v" => V,
+            "w" => W,
+            "x" => X,
+            "y" => Y,
+            "z" => Z,
+            "left" => Left,
+            "right" => Right,
+            "up" => Up,
+            "down" => Down,
+            "space" => Space,
+            "lshift" => LShift,
+            "rshift" => RShift,
+            "lcontrol" => LControl,
+            "rcontrol" => RControl,
+            "escape" => Escape,
+            "f12" => F12,
+            _ => bail!("Unknown key {:?}", name),
+        })
+    }
+}